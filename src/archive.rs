@@ -0,0 +1,47 @@
+use std::path::{Component, Path};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::command::CommandRunner;
+use crate::{progress, ChromashError, Result};
+
+/// How long tar gets to pack or unpack a preset, wallpaper included.
+const TAR_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Packs the contents of `dir` into a gzipped tarball at `output`.
+pub fn create(dir: &Path, output: &Path, runner: &dyn CommandRunner) -> Result<()> {
+    log::debug!("Packing {} into {}", dir.display(), output.display());
+    let spinner = progress::spinner("Packing preset");
+    let result = runner.run(Command::new("tar").arg("-czf").arg(output).arg("-C").arg(dir).arg("."), TAR_TIMEOUT);
+    spinner.finish_and_clear();
+    result.map(drop)
+}
+
+/// Unpacks a gzipped tarball into `dest` after checking that no entry escapes it and that
+/// it holds only regular files and directories.
+pub fn extract(archive: &Path, dest: &Path, runner: &dyn CommandRunner) -> Result<()> {
+    log::debug!("Unpacking {} into {}", archive.display(), dest.display());
+    let invalid = |_| ChromashError::General(format!("Not a valid preset archive: {}", archive.display()));
+    let listing = runner.run(Command::new("tar").arg("-tzf").arg(archive), TAR_TIMEOUT).map_err(invalid)?;
+    for entry in listing.lines() {
+        let unsafe_entry = Path::new(entry).components()
+            .any(|c| matches!(c, Component::ParentDir | Component::RootDir | Component::Prefix(_)));
+        if unsafe_entry {
+            return Err(ChromashError::General(format!("Archive contains unsafe path: {}", entry)));
+        }
+    }
+    // Links could point later entries outside `dest`, and presets only hold plain files
+    let details = runner.run(Command::new("tar").arg("-tvzf").arg(archive), TAR_TIMEOUT).map_err(invalid)?;
+    for entry in details.lines() {
+        if !entry.starts_with(['-', 'd']) {
+            return Err(ChromashError::General(format!("Archive contains a link or special file: {}", entry)));
+        }
+    }
+
+    let spinner = progress::spinner("Unpacking preset");
+    let result = runner.run(
+        Command::new("tar").arg("-xzf").arg(archive).arg("-C").arg(dest).arg("--no-same-owner"),
+        TAR_TIMEOUT);
+    spinner.finish_and_clear();
+    result.map(drop)
+}
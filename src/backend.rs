@@ -0,0 +1,267 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use crate::config::{CommandsConfig, SwwwConfig};
+use crate::hyprpaper::Assignments;
+use crate::command::CommandRunner;
+use crate::{doctor, hyprland, sandbox, ChromashError, Result};
+
+/// The graphical session chromash runs in, from the environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    Hyprland,
+    Sway,
+    /// Another Wayland compositor.
+    Wayland,
+    X11,
+    MacOs,
+    Unknown,
+}
+
+impl Session {
+    pub fn detect() -> Self {
+        let set = |name| env::var_os(name).is_some_and(|v| !v.is_empty());
+        if cfg!(target_os = "macos") {
+            Self::MacOs
+        } else if set("HYPRLAND_INSTANCE_SIGNATURE") {
+            Self::Hyprland
+        } else if set("SWAYSOCK") {
+            Self::Sway
+        } else if set("WAYLAND_DISPLAY") {
+            Self::Wayland
+        } else if set("DISPLAY") {
+            Self::X11
+        } else {
+            Self::Unknown
+        }
+    }
+
+    /// Names of the connected outputs; empty if they can't be listed.
+    pub fn monitors(self, limits: &CommandsConfig, runner: &dyn CommandRunner) -> Vec<String> {
+        let run = |program: &str, args: &[&str]| {
+            log::debug!("Running {} {}", program, args.join(" "));
+            runner.run_with_retries(Command::new(program).args(args), Duration::from_secs(limits.timeout), limits.retries).ok()
+        };
+        match self {
+            Self::Hyprland | Self::Unknown => hyprland::monitors(limits, runner)
+                .inspect_err(|e| log::debug!("Listing Hyprland monitors failed: {}", e))
+                .unwrap_or_default(),
+            Self::Sway => run("swaymsg", &["-t", "get_outputs", "-r"])
+                .and_then(|output| serde_json::from_str::<Vec<serde_json::Value>>(&output).ok())
+                .map(|outputs| outputs.iter()
+                    .filter(|o| o["active"].as_bool().unwrap_or(true))
+                    .filter_map(|o| o["name"].as_str().map(str::to_string))
+                    .collect())
+                .unwrap_or_default(),
+            // `xrandr --listmonitors` prints ` 0: +*eDP-1 1920/344x1080/193+0+0  eDP-1`
+            Self::X11 => run("xrandr", &["--listmonitors"])
+                .map(|output| output.lines()
+                    .skip(1)
+                    .filter_map(|line| line.split_whitespace().last().map(str::to_string))
+                    .collect())
+                .unwrap_or_default(),
+            Self::Wayland | Self::MacOs => Vec::new(),
+        }
+    }
+}
+
+/// The program that draws the wallpaper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Hyprpaper,
+    Swww,
+    Swaybg,
+    Feh,
+    /// The macOS desktop picture, set through System Events.
+    MacOs,
+}
+
+impl Backend {
+    pub const NAMES: &'static [&'static str] = &["hyprpaper", "swww", "swaybg", "feh", "macos"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hyprpaper => "hyprpaper",
+            Self::Swww => "swww",
+            Self::Swaybg => "swaybg",
+            Self::Feh => "feh",
+            Self::MacOs => "macos",
+        }
+    }
+
+    /// The program that has to be installed for this backend.
+    pub fn program(self) -> &'static str {
+        match self {
+            Self::MacOs => "osascript",
+            backend => backend.as_str(),
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "hyprpaper" => Some(Self::Hyprpaper),
+            "swww" => Some(Self::Swww),
+            "swaybg" => Some(Self::Swaybg),
+            "feh" => Some(Self::Feh),
+            "macos" => Some(Self::MacOs),
+            _ => None,
+        }
+    }
+
+    /// `$CHROMASH_BACKEND` (set by `--backend`), then `configured`, then whichever daemon is
+    /// already running, then the usual one for the session.
+    pub fn select(configured: Option<&str>) -> Result<Self> {
+        let requested = env::var("CHROMASH_BACKEND").ok()
+            .filter(|name| !name.is_empty())
+            .or_else(|| configured.map(str::to_string));
+        if let Some(name) = requested {
+            return Self::from_str(&name)
+                .ok_or_else(|| crate::unknown_value("backend", &name, Self::NAMES));
+        }
+        Ok(Self::detect(Session::detect()))
+    }
+
+    fn detect(session: Session) -> Self {
+        if is_running("hyprpaper") {
+            return Self::Hyprpaper;
+        }
+        if is_running("swww-daemon") {
+            return Self::Swww;
+        }
+        if is_running("swaybg") {
+            return Self::Swaybg;
+        }
+        let installed = |name| doctor::find_executable(name).is_some();
+        match session {
+            Session::Hyprland if !installed("hyprpaper") && installed("swww") => Self::Swww,
+            Session::Hyprland | Session::Unknown => Self::Hyprpaper,
+            Session::Sway | Session::Wayland if installed("swww") => Self::Swww,
+            Session::Sway | Session::Wayland => Self::Swaybg,
+            Session::X11 => Self::Feh,
+            Session::MacOs => Self::MacOs,
+        }
+    }
+
+    /// Shows `assignments` with a backend other than hyprpaper, whose config file chromash
+    /// manages itself. swww animates the change as `transition` says.
+    pub fn show(self, assignments: &Assignments, limits: &CommandsConfig, transition: &SwwwConfig, runner: &dyn CommandRunner) -> Result<()> {
+        let timeout = Duration::from_secs(limits.timeout);
+        match self {
+            Self::Hyprpaper => Err(ChromashError::General("hyprpaper is configured through hyprpaper.conf".into())),
+            Self::Swww => {
+                let transition = transition.args()?;
+                if !is_running("swww-daemon") {
+                    start_swww_daemon(runner, timeout)?;
+                }
+                for (path, outputs) in by_path(assignments) {
+                    let mut command = Command::new("swww");
+                    command.args(["img", path]).args(&transition);
+                    if !outputs.is_empty() {
+                        command.args(["--outputs", &outputs.join(",")]);
+                    }
+                    log::debug!("Running {:?}", command);
+                    runner.run_with_retries(&mut command, timeout, limits.retries)?;
+                }
+                Ok(())
+            }
+            Self::Swaybg => {
+                // swaybg has no IPC; replace the running instance with one showing everything
+                let _ = runner.run(Command::new("pkill").args(["-x", "swaybg"]), timeout);
+                let mut command = Command::new("swaybg");
+                for (monitor, path) in &assignments.monitors {
+                    command.args(["-o", monitor, "-i", path, "-m", "fill"]);
+                }
+                if let Some(fallback) = &assignments.fallback {
+                    command.args(["-o", "*", "-i", fallback, "-m", "fill"]);
+                }
+                log::debug!("Running {:?}", command);
+                runner.spawn(&mut command)
+            }
+            Self::Feh => {
+                // feh gives Xinerama screens the images in order, without naming them
+                let mut paths: Vec<&str> = assignments.monitors.values().map(String::as_str).collect();
+                if paths.is_empty() {
+                    paths.extend(assignments.fallback.as_deref());
+                }
+                let mut command = Command::new("feh");
+                command.args(["--no-fehbg", "--bg-fill"]).args(paths);
+                log::debug!("Running {:?}", command);
+                runner.run(&mut command, timeout).map(|_| ())
+            }
+            Self::MacOs => {
+                // Displays have no names chromash knows, so every desktop gets one picture
+                let Some(path) = assignments.fallback.as_deref().or(assignments.monitors.values().next().map(String::as_str)) else {
+                    return Ok(());
+                };
+                let mut command = Command::new("osascript");
+                command.args([
+                    "-e", "on run argv",
+                    "-e", "tell application \"System Events\" to tell every desktop to set picture to (item 1 of argv)",
+                    "-e", "end run",
+                    path,
+                ]);
+                log::debug!("Running {:?}", command);
+                runner.run_with_retries(&mut command, timeout, limits.retries).map(|_| ())
+            }
+        }
+    }
+}
+
+/// Monitors showing each wallpaper; the fallback gets no names, meaning every output.
+fn by_path(assignments: &Assignments) -> Vec<(&str, Vec<&str>)> {
+    if assignments.monitors.is_empty() {
+        return assignments.fallback.iter().map(|path| (path.as_str(), Vec::new())).collect();
+    }
+    let mut grouped: Vec<(&str, Vec<&str>)> = Vec::new();
+    for (monitor, path) in &assignments.monitors {
+        match grouped.iter_mut().find(|(p, _)| p == path) {
+            Some((_, monitors)) => monitors.push(monitor),
+            None => grouped.push((path, vec![monitor])),
+        }
+    }
+    grouped
+}
+
+fn start_swww_daemon(runner: &dyn CommandRunner, timeout: Duration) -> Result<()> {
+    log::debug!("Starting swww-daemon");
+    runner.spawn(&mut Command::new("swww-daemon"))?;
+    // `swww query` fails until the daemon's socket accepts connections
+    if !wait_until(timeout, || runner.run(Command::new("swww").arg("query"), timeout).is_ok()) {
+        return Err(ChromashError::Process("swww-daemon did not start".into()));
+    }
+    Ok(())
+}
+
+/// Polls `ready` until it returns true or `timeout` passes; returns whether it did.
+pub fn wait_until(timeout: Duration, mut ready: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if ready() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Whether a live process named `name` is running, from `/proc/<pid>/stat`. Zombies, such
+/// as a killed hyprpaper the daemon hasn't reaped, don't count.
+pub fn is_running(name: &str) -> bool {
+    // Sandboxed runs only ever start stubs, so real daemons must not steer them
+    if sandbox::active() {
+        return false;
+    }
+    let Ok(entries) = fs::read_dir("/proc") else { return false };
+    entries.flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().bytes().all(|b| b.is_ascii_digit()))
+        .filter_map(|entry| fs::read_to_string(entry.path().join("stat")).ok())
+        .any(|stat| {
+            // `<pid> (<comm>) <state> ...`; comm may itself contain parentheses
+            let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else { return false };
+            &stat[open + 1..close] == name && !stat[close + 1..].trim_start().starts_with('Z')
+        })
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::path::Path;
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+const PLATFORM_PROFILE: &str = "/sys/firmware/acpi/platform_profile";
+/// Where power-profiles-daemon keeps the profile it was last set to.
+const POWER_PROFILES_STATE: &str = "/var/lib/power-profiles-daemon/state.ini";
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryState {
+    pub discharging: bool,
+    pub capacity: Option<u8>,
+}
+
+/// Reads the first battery found under sysfs, or `None` on machines without one.
+pub fn read_state() -> Option<BatteryState> {
+    let entries = fs::read_dir(POWER_SUPPLY_DIR).ok()?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if read_attr(&path, "type").as_deref() != Some("Battery") {
+            continue;
+        }
+        let status = read_attr(&path, "status").unwrap_or_default();
+        return Some(BatteryState {
+            discharging: status == "Discharging",
+            capacity: read_attr(&path, "capacity").and_then(|c| c.parse().ok()),
+        });
+    }
+    None
+}
+
+/// Whether the machine runs on battery in power-saver mode, going by the ACPI platform
+/// profile or power-profiles-daemon's state.
+pub fn power_saver() -> bool {
+    if !read_state().is_some_and(|state| state.discharging) {
+        return false;
+    }
+    let platform = fs::read_to_string(PLATFORM_PROFILE).unwrap_or_default();
+    platform.trim() == "low-power"
+        || fs::read_to_string(POWER_PROFILES_STATE).unwrap_or_default().lines().any(|line| line.trim() == "Profile=power-saver")
+}
+
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name)).ok().map(|s| s.trim().to_string())
+}
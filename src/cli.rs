@@ -0,0 +1,1232 @@
+use std::env;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    completions, config, daemon, desktop, doctor, download, fuzzy, graphics, history, hub, init, menu, palette, paths,
+    picker, profile, progress, rpc, screenshot, tui, watch, waybar,
+};
+use crate::theme::Theme;
+use crate::{
+    confirm, format_timestamp, normalize_hex, random_theme, random_u64, split_command_line, unknown_value,
+    ChromashApi, ChromashError, ColorMode, Config, CurrentTheme, PresetMetadata, Result, SchemeType, ThemeOptions,
+};
+
+/// Size in terminal cells of inline previews in `presets --long` and `wallpaper list`.
+const PREVIEW_COLS: u16 = 24;
+const PREVIEW_ROWS: u16 = 6;
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Prints `message`, or `value` with `--json`.
+fn print_outcome(json: bool, value: serde_json::Value, message: impl std::fmt::Display) -> Result<()> {
+    if json {
+        return print_json(&value);
+    }
+    println!("{}", message);
+    Ok(())
+}
+
+/// Formats seconds as e.g. `3d 4h`, `2h 13m` or `45s`.
+fn format_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h),
+    }
+}
+
+/// Prints presets as aligned columns for `presets --format table`.
+fn print_preset_table(presets: &[PresetMetadata]) {
+    let rows: Vec<[String; 6]> = presets.iter()
+        .map(|p| [
+            format!("{}{}", if p.pinned { "* " } else { "" }, p.name),
+            p.source_kind().to_string(),
+            p.mode.map_or("-", |m| m.as_str()).to_string(),
+            p.scheme.map_or("-", |s| s.as_str().trim_start_matches("scheme-")).to_string(),
+            format_timestamp(p.modified),
+            p.tags.join(", "),
+        ])
+        .collect();
+    let header = ["NAME", "SOURCE", "MODE", "SCHEME", "MODIFIED", "TAGS"].map(String::from);
+    let mut widths = header.clone().map(|h| h.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row.iter().zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Everything the `chromash` command accepts.
+#[derive(Parser, Debug)]
+#[command(name = "chromash", about = "Chromash - Dynamic Theme Manager", after_help = AFTER_HELP)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Flags accepted anywhere on the command line.
+#[derive(Args, Debug, Default, Clone)]
+#[command(next_help_heading = "Global options")]
+pub struct GlobalArgs {
+    /// More diagnostic output on stderr (-vv for even more)
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Only errors on stderr
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Use <DIR> instead of ~/.config/chromash for config, presets and state (or set CHROMASH_CONFIG_DIR)
+    #[arg(long, value_name = "DIR", global = true)]
+    pub config: Option<String>,
+    /// Set wallpapers with hyprpaper, swww, swaybg, feh or macos instead of detecting it (or set CHROMASH_BACKEND)
+    #[arg(long, value_name = "NAME", global = true)]
+    pub backend: Option<String>,
+    /// Keep config, state and generated files under <DIR> and record external commands in <DIR>/commands.log
+    #[arg(long, value_name = "DIR", global = true)]
+    pub sandbox: Option<String>,
+    /// Print results, and errors with their code, as JSON on stdout (or set CHROMASH_OUTPUT=json); not for completions,
+    /// menu or the interactive and long-running commands
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Print what applying would do without doing it
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Print why: the wallpaper picked, seed candidates and their scores, where mode and scheme came from, the
+    /// generator command and the templates (with --dry-run, only plan)
+    #[arg(long, global = true)]
+    pub explain: bool,
+    /// Apply cheaply: cached or quickly sampled seeds, wallpapers over IPC without transitions, and no lock screen,
+    /// monitor accents or snapshots (automatic on battery in power-saver mode unless minimal = "never" in config.toml)
+    #[arg(long, global = true)]
+    pub minimal: bool,
+    /// Delete presets and old wallpapers instead of moving them to the trash
+    #[arg(long, global = true)]
+    pub purge: bool,
+    /// Animate swww wallpaper changes: fade, wipe, grow, none... (defaults from [swww] in config.toml)
+    #[arg(long, value_name = "TYPE", global = true)]
+    pub transition: Option<String>,
+    /// Seconds a swww transition takes
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub transition_duration: Option<f64>,
+    /// Where grow and outer transitions start: center, top-left and so on, or x,y
+    #[arg(long, value_name = "POS", global = true)]
+    pub transition_pos: Option<String>,
+    /// Frames per second of swww transitions
+    #[arg(long, value_name = "FPS", global = true)]
+    pub transition_fps: Option<u32>,
+    /// Apply in this process even if a daemon is running
+    #[arg(long, global = true)]
+    pub no_daemon: bool,
+}
+
+impl GlobalArgs {
+    /// Whether to print JSON: `--json` or `CHROMASH_OUTPUT=json`.
+    pub fn json(&self) -> bool {
+        self.json || json_from_env()
+    }
+
+    /// These flags, with any `outer` sets that these leave unset.
+    fn merge(self, outer: &GlobalArgs) -> GlobalArgs {
+        GlobalArgs {
+            json: self.json || outer.json,
+            dry_run: self.dry_run || outer.dry_run,
+            explain: self.explain || outer.explain,
+            minimal: self.minimal || outer.minimal,
+            purge: self.purge || outer.purge,
+            transition: self.transition.or_else(|| outer.transition.clone()),
+            transition_duration: self.transition_duration.or(outer.transition_duration),
+            transition_pos: self.transition_pos.or_else(|| outer.transition_pos.clone()),
+            transition_fps: self.transition_fps.or(outer.transition_fps),
+            ..self
+        }
+    }
+
+    fn transition(&self) -> config::SwwwConfig {
+        config::SwwwConfig {
+            transition_type: self.transition.clone(),
+            transition_duration: self.transition_duration,
+            transition_pos: self.transition_pos.clone(),
+            transition_fps: self.transition_fps,
+        }
+    }
+}
+
+/// How to generate a theme; what isn't given comes from config.toml or the seed.
+#[derive(Args, Debug, Default, Clone)]
+pub struct ThemeArgs {
+    /// light or dark
+    #[arg(short, long, value_parser = parse_mode)]
+    pub mode: Option<ColorMode>,
+    /// content, expressive, fidelity, fruit-salad, monochrome, neutral, rainbow or tonal-spot
+    #[arg(short, long, value_parser = parse_scheme)]
+    pub scheme: Option<SchemeType>,
+    /// From -1 to 1
+    #[arg(long, allow_negative_numbers = true)]
+    pub contrast: Option<f64>,
+    /// Shift the seed toward orange (positive) or blue (negative), from -50 to 50
+    #[arg(long, allow_negative_numbers = true)]
+    pub warmth: Option<f64>,
+    /// Rotate the seed's hue by this many degrees
+    #[arg(long, value_name = "DEGREES", allow_negative_numbers = true)]
+    pub hue_shift: Option<f64>,
+    /// Sample a small thumbnail for the seed, for slow machines
+    #[arg(long)]
+    pub fast: bool,
+    /// Also save the theme as a preset
+    #[arg(long, value_name = "NAME", num_args = 0..=1)]
+    pub save_preset: Option<Option<String>>,
+}
+
+impl ThemeArgs {
+    /// These options as `apply` parameters for the daemon; `None` if it can't express them.
+    fn params(&self) -> Option<serde_json::Map<String, serde_json::Value>> {
+        let save_as = match &self.save_preset {
+            Some(None) => return None,
+            Some(Some(name)) => Some(name),
+            None => None,
+        };
+        let params = json!({
+            "mode": self.mode.map(|mode| mode.as_str()),
+            "scheme": self.scheme.map(|scheme| scheme.as_str()),
+            "contrast": self.contrast,
+            "warmth": self.warmth,
+            "hue_shift": self.hue_shift,
+            "fast": self.fast,
+            "save_as": save_as,
+        });
+        match params {
+            serde_json::Value::Object(params) => Some(params),
+            _ => None,
+        }
+    }
+
+    fn options(&self) -> ThemeOptions {
+        ThemeOptions {
+            mode: self.mode,
+            scheme: self.scheme,
+            contrast: self.contrast.map(|contrast| contrast.clamp(-1.0, 1.0)),
+            warmth: self.warmth.map(|warmth| warmth.clamp(-50.0, 50.0)),
+            hue_shift: self.hue_shift,
+            fast: self.fast,
+            save_preset: self.save_preset.is_some(),
+            preset_name: self.save_preset.clone().flatten(),
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate a theme from a color; without one, pick it interactively
+    Color {
+        hex: Option<String>,
+        #[command(flatten)]
+        theme: ThemeArgs,
+    },
+    /// Apply a random seed color and scheme; the same --seed gives the same theme again
+    RandomTheme {
+        #[arg(long)]
+        seed: Option<u64>,
+        #[command(flatten)]
+        theme: ThemeArgs,
+    },
+    /// Switch mode without regenerating colors
+    Toggle {
+        #[arg(value_parser = parse_mode)]
+        mode: Option<ColorMode>,
+    },
+    /// Change one role of the current palette, e.g. set-color surface 101014; kept until new colors
+    SetColor {
+        role: String,
+        hex: String,
+        #[arg(short, long, value_parser = parse_mode)]
+        mode: Option<ColorMode>,
+    },
+    /// Swap only primary, secondary and tertiary colors, keeping the current surfaces
+    Accent {
+        hex: String,
+    },
+    /// Set a wallpaper and extract colors; without a path, the current or first one
+    #[command(args_conflicts_with_subcommands = true)]
+    Wallpaper {
+        #[command(subcommand)]
+        command: Option<WallpaperCommand>,
+        path: Option<String>,
+        /// Change only this monitor's wallpaper, keep the others
+        #[arg(long)]
+        monitor: Option<String>,
+        /// A random wallpaper from the collection (the daemon's next precached pick, if it queued any)
+        #[arg(long, conflicts_with = "path")]
+        random: bool,
+        #[command(flatten)]
+        theme: ThemeArgs,
+    },
+    /// Set a wallpaper only
+    WallpaperOnly {
+        path: String,
+        #[arg(long)]
+        monitor: Option<String>,
+    },
+    /// Extract colors from an image without setting it
+    Match {
+        image: String,
+        /// Copy another UI's look: its largest flat region sets the mode, its most saturated color the accent
+        #[arg(long)]
+        ui: bool,
+        #[command(flatten)]
+        theme: ThemeArgs,
+    },
+    /// List saved presets
+    Presets {
+        #[arg(long)]
+        tag: Option<String>,
+        /// Descriptions, sources and previews too
+        #[arg(short, long)]
+        long: bool,
+        /// Automatic snapshots instead of presets
+        #[arg(long)]
+        snapshots: bool,
+        #[arg(long, value_enum)]
+        format: Option<PresetsFormat>,
+    },
+    /// Apply, save and manage presets
+    Preset {
+        #[command(subcommand)]
+        command: PresetCommand,
+    },
+    /// Show the current theme
+    Theme,
+    /// Show the current palette's colors
+    Palette {
+        /// Every tone of its tonal palettes too
+        #[arg(long)]
+        tones: bool,
+    },
+    /// List recently applied themes
+    History {
+        #[arg(short = 'n', value_name = "COUNT", default_value_t = 20)]
+        count: usize,
+    },
+    /// Summarize presets, schemes and wallpapers used
+    Stats,
+    /// Step back through applied themes
+    Undo,
+    /// Step forth through undone themes
+    Redo,
+    /// Refuse all theme changes, including automation
+    Lock,
+    Unlock,
+    /// Browse wallpapers and presets with previews
+    Tui,
+    /// Print presets and wallpapers for a launcher, or apply the entry it printed
+    Menu {
+        #[arg(long, default_value = "rofi", value_parser = ["rofi", "wofi", "plain"])]
+        format: String,
+        /// Apply the entry the launcher printed on stdin
+        #[arg(long)]
+        apply: bool,
+    },
+    /// Run schedules, battery profiles and hotplug handling, and answer JSON-RPC on $XDG_RUNTIME_DIR/chromash.sock and
+    /// D-Bus as org.chromash.Theme; while it runs, color, wallpaper <path> and preset apply are handed to it
+    Daemon,
+    /// Print the theme, palette and wallpaper as a JSON line now and on every change
+    Subscribe,
+    /// Re-apply when the wallpaper or templates change
+    Watch,
+    /// Output for a waybar custom module (return-type json), or run a click action
+    WaybarModule {
+        /// next, prev or toggle-mode: step through presets or flip the mode
+        action: Option<String>,
+        #[arg(long)]
+        once: bool,
+    },
+    /// Apply or undo the dimmed idle variant
+    Idle {
+        #[command(subcommand)]
+        command: IdleCommand,
+    },
+    /// Search and install community presets
+    Hub {
+        #[command(subcommand)]
+        command: HubCommand,
+    },
+    /// List profiles, or switch to one and re-apply the current theme with its settings
+    Profile {
+        #[command(subcommand)]
+        command: Option<ProfileCommand>,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_parser = ["bash", "zsh", "fish"])]
+        shell: String,
+    },
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+    /// Interactive first-run setup
+    Init,
+    /// Add launcher entries: the TUI, a random wallpaper and toggling dark mode
+    InstallDesktop,
+    /// Check dependencies and the environment
+    Doctor,
+    /// An alias from config.toml
+    #[command(external_subcommand)]
+    Alias(Vec<String>),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WallpaperCommand {
+    /// List wallpapers with previews
+    List,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum PresetsFormat {
+    Table,
+    Json,
+    Names,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PresetCommand {
+    /// Apply a preset, or a random one
+    Apply {
+        #[arg(required_unless_present = "random")]
+        name: Option<String>,
+        /// A random preset
+        #[arg(long, conflicts_with = "name")]
+        random: bool,
+        /// With --random, only pinned presets
+        #[arg(long, requires = "random")]
+        pinned_only: bool,
+        /// Apply the closest match if no preset has this name
+        #[arg(long)]
+        fuzzy: bool,
+        /// Keep the current wallpaper
+        #[arg(long)]
+        colors_only: bool,
+        /// Replay the preset's recorded palette instead of generating it again
+        #[arg(long)]
+        exact: bool,
+        #[command(flatten)]
+        theme: ThemeArgs,
+    },
+    /// Save the current theme as a preset
+    Save {
+        name: String,
+        /// Keep a copy of the wallpaper in the preset
+        #[arg(long)]
+        bundle: bool,
+    },
+    /// Copy the preset's wallpaper into the preset
+    Bundle { name: String },
+    Delete { name: String },
+    Export {
+        name: String,
+        /// Defaults to <name>.chromash
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Include the wallpaper
+        #[arg(long)]
+        wallpaper: bool,
+    },
+    /// Import a .chromash file or URL
+    Import {
+        source: String,
+        /// Replace a preset with the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print a chromash:// share string
+    Share { name: String },
+    /// Apply and save a shared preset
+    ImportString { string: String },
+    /// List previous versions
+    History { name: String },
+    /// Restore a previous version
+    Revert {
+        name: String,
+        #[arg(long, default_value_t = 1)]
+        to: usize,
+    },
+    Rename { old: String, new: String },
+    /// Set notes shown by presets --long
+    Describe {
+        name: String,
+        text: Vec<String>,
+    },
+    /// Pinned presets are listed first
+    Pin { name: String },
+    Unpin { name: String },
+    Tag { name: String, tag: String },
+    Untag { name: String, tag: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdleCommand {
+    Dim,
+    Restore,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HubCommand {
+    /// Search the community preset index
+    Search { query: Option<String> },
+    /// Download and install a community preset
+    Install { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// List the profiles in config.toml; * marks the active one
+    List,
+    /// Use a profile's [profiles.<name>] settings
+    Switch { name: String },
+    /// Go back to the config without a profile
+    Off,
+}
+
+fn parse_mode(value: &str) -> std::result::Result<ColorMode, String> {
+    ColorMode::from_str(value).ok_or_else(|| unknown_value("mode", value, &["light", "dark"]).to_string())
+}
+
+fn parse_scheme(value: &str) -> std::result::Result<SchemeType, String> {
+    SchemeType::from_str(value).ok_or_else(|| unknown_value("scheme", value, &completions::SCHEMES).to_string())
+}
+
+/// Whether `CHROMASH_OUTPUT=json` asks for JSON without `--json`.
+pub fn json_from_env() -> bool {
+    env::var("CHROMASH_OUTPUT").is_ok_and(|output| output == "json")
+}
+
+/// Prints an error that ended a command: as `{"error": {"code", "message"}}` on stdout
+/// with JSON output, as a message on stderr otherwise.
+pub fn print_error(error: &ChromashError, json: bool) {
+    if json {
+        println!("{}", json!({ "error": { "code": error.code(), "message": error.to_string() } }));
+    } else {
+        eprintln!("Error: {}", error);
+    }
+}
+
+/// Parses a chromash command line, `args[0]` being the program name.
+fn parse(args: &[String]) -> Result<Cli> {
+    Cli::try_parse_from(args).map_err(|e| ChromashError::Usage(e.render().to_string().trim_end().to_string()))
+}
+
+/// Runs a chromash command line, `args[0]` being the program name: an alias, scheduled
+/// action or command received by the daemon.
+pub fn execute(api: &mut ChromashApi, args: &[String]) -> Result<()> {
+    run(api, parse(args)?)
+}
+
+/// Replaces an alias from the config with the command line it stands for, keeping the
+/// arguments after it and the global flags before it. Aliases may refer to other aliases;
+/// built-in commands can't be shadowed, as they never parse as one.
+fn resolve_alias(api: &ChromashApi, mut cli: Cli) -> Result<Cli> {
+    let mut seen: Vec<String> = Vec::new();
+    while let Some(Command::Alias(words)) = &cli.command {
+        let name = &words[0];
+        let Some(expansion) = api.config().aliases.get(name) else {
+            let aliases = api.config().aliases.keys().map(String::as_str);
+            let known: Vec<&str> = completions::COMMANDS.iter().copied().chain(aliases).collect();
+            return Err(match fuzzy::closest(name, known) {
+                Some(closest) => ChromashError::Usage(format!("Unknown command: {} (did you mean {}?)", name, closest)),
+                None => ChromashError::Usage(format!("Unknown command: {} (run `chromash help` for a list)", name)),
+            });
+        };
+        if seen.contains(name) {
+            return Err(ChromashError::Usage(format!("Alias loop: {} -> {}", seen.join(" -> "), name)));
+        }
+        seen.push(name.clone());
+        let mut args = vec!["chromash".to_string()];
+        args.extend(split_command_line(expansion));
+        args.extend(words[1..].iter().cloned());
+        let expanded = parse(&args)?;
+        cli = Cli { global: expanded.global.merge(&cli.global), command: expanded.command };
+    }
+    Ok(cli)
+}
+
+/// Hands `color`, `wallpaper <path>` and `preset apply` to a running daemon, which has the
+/// config, backend and generator ready, and prints what it did. False if there is no daemon
+/// or the command needs this process: flags the daemon has no equivalent for, aliases, or
+/// a downloaded preset, which the daemon checks for and hands back to be confirmed here.
+pub fn forward(cli: &Cli) -> Result<bool> {
+    let global = &cli.global;
+    if global.no_daemon || global.dry_run || global.explain || global.minimal || global.purge
+        || global.backend.is_some() || env::var_os("CHROMASH_BACKEND").is_some_and(|backend| !backend.is_empty())
+        || global.transition.is_some() || global.transition_duration.is_some()
+        || global.transition_pos.is_some() || global.transition_fps.is_some()
+    {
+        return Ok(false);
+    }
+    let (source, value, monitor, theme) = match &cli.command {
+        Some(Command::Color { hex: Some(hex), theme }) => ("color", normalize_hex(hex)?, None, theme),
+        Some(Command::Wallpaper { command: None, path: Some(path), monitor, random: false, theme }) => {
+            let path = env::current_dir()?.join(paths::expand(path));
+            ("wallpaper", paths::encode(&path), monitor.as_ref(), theme)
+        }
+        Some(Command::Preset { command: PresetCommand::Apply { name: Some(name), random: false, fuzzy: false, colors_only: false, exact: false, theme, .. } }) => {
+            ("preset", name.clone(), None, theme)
+        }
+        _ => return Ok(false),
+    };
+    let Some(mut params) = theme.params() else {
+        return Ok(false);
+    };
+    params.insert(source.into(), value.clone().into());
+    if let Some(monitor) = monitor {
+        params.insert("monitor".into(), monitor.clone().into());
+    }
+    params.retain(|_, value| !value.is_null());
+    let Some(applied) = rpc::call("apply", params.into())? else {
+        return Ok(false);
+    };
+    if global.json() {
+        print_json(&applied)?;
+    } else {
+        match source {
+            "color" => println!("Applied color theme: #{}", value),
+            "preset" => println!("Applied preset: {}", value),
+            _ if applied["colors"] == true => println!("Applied wallpaper and extracted colors"),
+            _ => println!("Applied wallpaper without colors"),
+        }
+    }
+    Ok(true)
+}
+
+/// Runs a parsed command line.
+pub fn run(api: &mut ChromashApi, cli: Cli) -> Result<()> {
+    let Cli { global, command } = resolve_alias(api, cli)?;
+    let json = global.json();
+    api.set_dry_run(global.dry_run);
+    api.set_explain(global.explain);
+    api.set_minimal(global.minimal);
+    api.set_purge(global.purge);
+    api.set_transition(global.transition());
+    progress::set_enabled(!json && std::io::stdout().is_terminal() && std::io::stderr().is_terminal());
+    let Some(command) = command else {
+        Cli::command().print_help()?;
+        return Ok(());
+    };
+
+    match command {
+        Command::Color { hex, theme } => {
+            let color = match hex {
+                Some(color) => color,
+                None if std::io::stdin().is_terminal() => {
+                    let accent = api.load_current_theme()?.and_then(|theme| theme.accent);
+                    match picker::pick(accent.as_deref())? {
+                        Some(color) => color,
+                        None => {
+                            println!("No color picked");
+                            return Ok(());
+                        }
+                    }
+                }
+                None => return Err(ChromashError::Usage("color needs a hex value, e.g. chromash color 6750a4".into())),
+            };
+            let color = normalize_hex(&color)?;
+            let applied = Theme::from_color(&color).options(theme.options()).apply(api)?;
+            if json {
+                return print_json(&applied);
+            }
+            println!("Applied color theme: #{}", color);
+        }
+        Command::RandomTheme { seed, theme } => {
+            // Short enough to note down and pass to --seed
+            let seed = seed.unwrap_or_else(|| random_u64() % 1_000_000);
+            let mut options = theme.options();
+            let (color, scheme) = random_theme(seed);
+            let scheme = *options.scheme.get_or_insert(scheme);
+            let applied = Theme::from_color(&color).options(options).apply(api)?;
+            if json {
+                return print_json(&applied);
+            }
+            println!("Applied random theme #{} with {} (--seed {})", color, scheme.as_str(), seed);
+        }
+        Command::SetColor { role, hex, mode } => {
+            let palette = api.set_role(&role, &hex, mode)?;
+            if json {
+                return print_json(&palette);
+            }
+            let mode = mode.unwrap_or(palette.mode());
+            println!("Set {} to #{} in {} mode", role, normalize_hex(&hex)?, mode.as_str());
+        }
+        Command::Profile { command } => match command {
+            None | Some(ProfileCommand::List) => {
+                let active = profile::active();
+                let names: Vec<&String> = api.config().profiles.keys().collect();
+                if json {
+                    return print_json(&json!({ "active": active, "profiles": names }));
+                }
+                if names.is_empty() {
+                    println!("No profiles; add [profiles.<name>] sections to {}", Config::config_file().display());
+                }
+                for name in names {
+                    let marker = if active.as_ref() == Some(name) { "*" } else { " " };
+                    println!("{} {}", marker, name);
+                }
+            }
+            Some(ProfileCommand::Switch { name }) => {
+                api.switch_profile(Some(&name))?;
+                print_outcome(json, json!({ "profile": name }), format_args!("Switched to profile {}", name))?;
+            }
+            Some(ProfileCommand::Off) => {
+                api.switch_profile(None)?;
+                print_outcome(json, json!({ "profile": null }), "Using the config without a profile")?;
+            }
+        },
+        Command::Toggle { mode } => {
+            let mode = api.switch_mode(mode)?;
+            print_outcome(json, json!({ "mode": mode.as_str() }), format_args!("Switched to {} mode", mode.as_str()))?;
+        }
+        Command::Accent { hex } => {
+            let palette = api.apply_accent(&hex)?;
+            if json {
+                return print_json(&palette);
+            }
+            println!("Applied accent #{}; surfaces kept", normalize_hex(&hex)?);
+        }
+        Command::Wallpaper { command: Some(WallpaperCommand::List), .. } => {
+            let wallpapers = api.list_wallpapers();
+            if json {
+                return print_json(&wallpapers);
+            }
+            if wallpapers.is_empty() {
+                println!("No wallpapers found in {}", api.wallpaper_dir().display());
+            }
+            for wallpaper in wallpapers {
+                println!("{}", wallpaper.display());
+                if let Err(e) = graphics::print_file(&wallpaper, PREVIEW_COLS, PREVIEW_ROWS) {
+                    log::warn!("Failed to preview {}: {}", wallpaper.display(), e);
+                }
+            }
+        }
+        Command::Wallpaper { command: None, path, monitor, random, theme } => {
+            let mut wallpaper = match path {
+                Some(path) => Theme::from_wallpaper(paths::expand(&path)),
+                None if random => Theme::from_wallpaper(api.random_wallpaper()?),
+                None => Theme::from_default_wallpaper(),
+            };
+            if let Some(monitor) = &monitor {
+                wallpaper = wallpaper.monitor(monitor);
+            }
+            let applied = wallpaper.options(theme.options()).apply(api)?;
+            if json {
+                return print_json(&applied);
+            }
+            if applied.colors {
+                println!("Applied wallpaper and extracted colors");
+            } else {
+                println!("Applied wallpaper without colors");
+            }
+        }
+        Command::WallpaperOnly { path, monitor } => {
+            api.apply_wallpaper_on(Some(&path), monitor.as_deref(), false, ThemeOptions::default())?;
+            if json {
+                return print_json(&json!({ "wallpaper": paths::expand(&path), "monitor": monitor }));
+            }
+            match monitor {
+                Some(monitor) => println!("Set wallpaper on {}: {}", monitor, path),
+                None => println!("Set wallpaper: {}", path),
+            }
+        }
+        Command::Match { image, ui, theme } => {
+            let path = paths::expand(&image);
+            let options = theme.options();
+            if !ui {
+                if !api.apply_wallpaper_colors(&path, options)? {
+                    return Err(ChromashError::General(format!("Failed to decode {}", path.display())));
+                }
+                if json {
+                    return print_json(&json!({ "image": path, "palette": api.current_palette()? }));
+                }
+                println!("Applied colors from {}", path.display());
+                return Ok(());
+            }
+            let ui = screenshot::analyze(&path)?;
+            let [r, g, b] = ui.surface;
+            let options = ThemeOptions {
+                mode: options.mode.or(Some(ColorMode::from_brightness(r, g, b))),
+                scheme: options.scheme.or(Some(SchemeType::Fidelity)),
+                ..options
+            };
+            let accent = format!("{:02x}{:02x}{:02x}", ui.accent[0], ui.accent[1], ui.accent[2]);
+            let applied = Theme::from_color(&accent).options(options).apply(api)?;
+            if json {
+                return print_json(&applied);
+            }
+            println!("Surface #{:02x}{:02x}{:02x}, accent #{}", r, g, b, accent);
+            println!("Applied color theme: #{}", accent);
+        }
+        Command::Presets { tag, long, snapshots, format } => {
+            let mut presets = api.list_presets()?;
+            presets.retain(|p| p.snapshot == snapshots);
+            if let Some(tag) = &tag {
+                presets.retain(|p| p.tags.contains(tag));
+            }
+            match format.or(json.then_some(PresetsFormat::Json)) {
+                None => {}
+                Some(PresetsFormat::Names) => {
+                    for preset in &presets {
+                        println!("{}", preset.name);
+                    }
+                    return Ok(());
+                }
+                Some(PresetsFormat::Json) => {
+                    let entries = presets.iter()
+                        .map(|preset| {
+                            let mut entry = serde_json::to_value(preset)?;
+                            let thumbnail = api.preset_thumbnail(&preset.name)?;
+                            entry["source_type"] = preset.source_kind().into();
+                            entry["system"] = preset.system.into();
+                            entry["thumbnail"] = thumbnail.map(|t| t.display().to_string()).into();
+                            Ok(entry)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                    return Ok(());
+                }
+                Some(PresetsFormat::Table) => {
+                    print_preset_table(&presets);
+                    return Ok(());
+                }
+            }
+            if presets.is_empty() {
+                println!("No saved presets found");
+            } else {
+                for preset in presets {
+                    let pin = if preset.pinned { "* " } else { "" };
+                    if preset.tags.is_empty() {
+                        println!("{}{} ({})", pin, preset.name, format_timestamp(preset.modified));
+                    } else {
+                        println!("{}{} ({}) [{}]", pin, preset.name, format_timestamp(preset.modified), preset.tags.join(", "));
+                    }
+                    if long {
+                        if let Some(description) = &preset.description {
+                            println!("    {}", description);
+                        }
+                        if preset.system {
+                            println!("    system preset (read-only)");
+                        }
+                        if let Some(source) = &preset.source {
+                            println!("    source:    {}", source);
+                        }
+                        if let Some(wallpaper) = &preset.wallpaper {
+                            println!("    wallpaper: {}", wallpaper.display());
+                        }
+                        if let Ok(Some(thumbnail)) = api.preset_thumbnail(&preset.name) {
+                            println!("    thumbnail: {}", thumbnail.display());
+                            if let Err(e) = graphics::print_file(&thumbnail, PREVIEW_COLS, PREVIEW_ROWS) {
+                                log::warn!("Failed to preview {}: {}", preset.name, e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Command::Preset { command } => run_preset(api, command, json)?,
+        Command::Theme => {
+            if json {
+                let mut state = match api.load_current_theme()? {
+                    Some(current) => serde_json::to_value(current)?,
+                    None => json!({}),
+                };
+                state["locked"] = api.is_locked().into();
+                return print_json(&state);
+            }
+            if let Ok(Some(current)) = api.load_current_theme() {
+                println!("Source: {}", current.source);
+                println!("Time: {}", format_timestamp(current.timestamp));
+                if let Some(preset) = current.preset_name {
+                    println!("Preset: {}", preset);
+                }
+                if api.is_locked() {
+                    println!("Locked: yes");
+                }
+            } else {
+                println!("No theme info");
+            }
+        }
+        Command::History { count } => {
+            let entries = history::load()?;
+            if json {
+                let newest: Vec<&CurrentTheme> = entries.iter().rev().take(count).collect();
+                return print_json(&newest);
+            }
+            if entries.is_empty() {
+                println!("No themes applied yet");
+            }
+            for (i, entry) in entries.iter().rev().take(count).enumerate() {
+                let mut details = Vec::new();
+                if let Some(preset) = &entry.preset_name {
+                    details.push(format!("preset {}", preset));
+                }
+                if let Some(mode) = entry.mode {
+                    details.push(mode.as_str().to_string());
+                }
+                if let Some(scheme) = entry.scheme {
+                    details.push(scheme.as_str().trim_start_matches("scheme-").to_string());
+                }
+                if details.is_empty() {
+                    println!("{:>3}. {}  {}", i + 1, format_timestamp(entry.timestamp), entry.source);
+                } else {
+                    println!("{:>3}. {}  {} ({})", i + 1, format_timestamp(entry.timestamp), entry.source, details.join(", "));
+                }
+            }
+        }
+        Command::Stats => {
+            let stats = history::Stats::from_entries(&history::load()?);
+            if json {
+                return print_json(&stats);
+            }
+            let Some(since) = stats.since else {
+                println!("No themes applied yet");
+                return Ok(());
+            };
+            println!("Themes applied: {} (since {})", stats.applied, format_timestamp(since));
+            if let Some(lifetime) = stats.average_lifetime {
+                println!("Average theme lifetime: {}", format_duration(lifetime));
+            }
+            let sections = [
+                ("Most used presets", &stats.presets),
+                ("Most common schemes", &stats.schemes),
+                ("Modes", &stats.modes),
+            ];
+            for (title, counts) in sections {
+                if !counts.is_empty() {
+                    println!("\n{}:", title);
+                    for (value, count) in counts.iter().take(5) {
+                        println!("  {:>4}  {}", count, value);
+                    }
+                }
+            }
+            if !stats.wallpapers.is_empty() {
+                println!("\nWallpapers used: {} distinct", stats.wallpapers.len());
+                for (wallpaper, count) in stats.wallpapers.iter().take(5) {
+                    println!("  {:>4}  {}", count, wallpaper);
+                }
+            }
+        }
+        Command::Palette { tones } => {
+            let palette = api.current_palette()?
+                .ok_or_else(|| ChromashError::NotFound("No palette generated yet".into()))?;
+            if json {
+                return print_json(&palette);
+            }
+            let mode = palette.mode();
+            println!("Mode: {}", mode.as_str());
+            for (role, hex) in palette.roles(mode) {
+                println!("  {:<28} {}", role, hex);
+            }
+            if tones {
+                for name in palette::TONAL_PALETTES {
+                    let tones = palette.tones(name);
+                    if tones.is_empty() {
+                        continue;
+                    }
+                    println!("\n{}:", name);
+                    for (tone, hex) in tones {
+                        println!("  {:<28} {}", tone, hex);
+                    }
+                }
+            }
+        }
+        Command::InstallDesktop => {
+            let written = desktop::install(&env::current_exe()?, api.dry_run)?;
+            if json {
+                return print_json(&written);
+            }
+            for path in written {
+                println!("Wrote {}", path.display());
+            }
+        }
+        Command::Completions { shell } => {
+            let script = completions::script(&shell)
+                .ok_or_else(|| ChromashError::General(format!("Unsupported shell: {} (expected bash, zsh or fish)", shell)))?;
+            print!("{}", script);
+        }
+        Command::Complete { words } => {
+            for candidate in completions::complete(api, &words)? {
+                println!("{}", candidate);
+            }
+        }
+        Command::Lock => {
+            let changed = api.lock()?;
+            let message = if changed { "Theme locked" } else { "Theme already locked" };
+            print_outcome(json, json!({ "locked": true, "changed": changed }), message)?;
+        }
+        Command::Unlock => {
+            let changed = api.unlock()?;
+            let message = if changed { "Theme unlocked" } else { "Theme not locked" };
+            print_outcome(json, json!({ "locked": false, "changed": changed }), message)?;
+        }
+        Command::Undo | Command::Redo => {
+            let theme = if matches!(command, Command::Undo) { api.undo()? } else { api.redo()? };
+            if json {
+                return print_json(&theme);
+            }
+            println!("Restored theme: {}", theme.preset_name.as_deref().unwrap_or(&theme.source));
+        }
+        Command::Tui => tui::run(api)?,
+        Command::Menu { apply: true, .. } => {
+            if let Some(applied) = menu::apply(api)? {
+                println!("{}", applied);
+            }
+        }
+        Command::Menu { format, .. } => menu::list(api, &format)?,
+        Command::Daemon => daemon::run(api)?,
+        Command::Subscribe => rpc::subscribe(|state| {
+            let mut stdout = std::io::stdout().lock();
+            // A closed pipe means whoever was reading has gone; stop quietly
+            writeln!(stdout, "{}", state).and_then(|_| stdout.flush()).is_ok()
+        })?,
+        Command::Watch => watch::run(api)?,
+        Command::WaybarModule { action: Some(action), .. } => {
+            let applied = waybar::action(api, &action)?;
+            print_outcome(json, json!({ "applied": applied }), format_args!("Applied {}", applied))?;
+        }
+        Command::WaybarModule { action: None, once } => waybar::run(once)?,
+        Command::Hub { command } => {
+            let index = api.config().hub.index.clone();
+            match command {
+                HubCommand::Search { query } => {
+                    let results = hub::search(hub::fetch_index(&index, api.runner().as_ref())?, query.as_deref().unwrap_or_default());
+                    if json {
+                        return print_json(&results);
+                    }
+                    if results.is_empty() {
+                        println!("No hub presets found");
+                    }
+                    for entry in results {
+                        println!("{} - {}", entry.name, entry.description);
+                    }
+                }
+                HubCommand::Install { name } => {
+                    let name = hub::install(api, &index, &name)?;
+                    let message = format!("Installed preset: {} (run `chromash preset apply {}`)", name, name);
+                    print_outcome(json, json!({ "preset": name }), message)?;
+                }
+            }
+        }
+        Command::Idle { command: IdleCommand::Dim } => {
+            let options = api.config().idle.clone().unwrap_or_default().dim_options();
+            api.regenerate_colors(&options)?;
+            print_outcome(json, json!({ "dimmed": true }), "Applied idle dim theme")?;
+        }
+        Command::Idle { command: IdleCommand::Restore } => {
+            let options = api.load_current_theme()?.map(|t| t.options()).unwrap_or_default();
+            api.regenerate_colors(&options)?;
+            print_outcome(json, json!({ "dimmed": false }), "Restored theme")?;
+        }
+        Command::Init => init::run()?,
+        Command::Doctor => doctor::run(json, api.runner().as_ref())?,
+        Command::Alias(_) => unreachable!("aliases are resolved above"),
+    }
+    Ok(())
+}
+
+fn run_preset(api: &mut ChromashApi, command: PresetCommand, json: bool) -> Result<()> {
+    match command {
+        PresetCommand::Apply { random: true, pinned_only, .. } => {
+            let name = api.apply_random_preset(pinned_only)?;
+            print_outcome(json, json!({ "preset": name }), format_args!("Applied preset: {}", name))?;
+        }
+        PresetCommand::Apply { name, fuzzy, colors_only, exact, theme, .. } => {
+            let requested = name.unwrap_or_default();
+            let name = if api.get_preset_dir(&requested).is_ok() {
+                requested
+            } else {
+                match api.closest_preset(&requested)? {
+                    Some(closest) if fuzzy => {
+                        if !json {
+                            println!("Using closest match: {}", closest);
+                        }
+                        closest
+                    }
+                    Some(closest) => {
+                        return Err(ChromashError::NotFound(format!("Preset: {} (did you mean {}?)", requested, closest)));
+                    }
+                    None => return Err(ChromashError::NotFound(format!("Preset: {}", requested))),
+                }
+            };
+            if let Some(origin) = api.untrusted_origin(&name)? {
+                if !confirm(&format!("Preset {} was downloaded from {}. Apply it?", name, origin)) {
+                    println!("Not applied: {}", name);
+                    return Ok(());
+                }
+                api.trust_preset(&name)?;
+            }
+            let message = if exact {
+                api.apply_preset_exact(&name, colors_only)?;
+                "Replayed preset"
+            } else if colors_only {
+                api.apply_preset_colors(&name, theme.options())?;
+                "Applied preset colors"
+            } else {
+                api.apply_preset(&name, theme.options())?;
+                "Applied preset"
+            };
+            if json {
+                return print_json(&json!({ "preset": name, "palette": api.current_palette()? }));
+            }
+            println!("{}: {}", message, name);
+        }
+        PresetCommand::Save { name, bundle } => {
+            api.save_current_as_preset(&name)?;
+            let bundled = if bundle { Some(api.bundle_preset_wallpaper(&name)?) } else { None };
+            print_outcome(json, json!({ "preset": name, "bundled": bundled }), format_args!("Saved preset: {}", name))?;
+        }
+        PresetCommand::Bundle { name } => {
+            let bundled = api.bundle_preset_wallpaper(&name)?;
+            let message = format!("Bundled wallpaper for {} at {}", name, bundled.display());
+            print_outcome(json, json!({ "preset": name, "bundled": bundled }), message)?;
+        }
+        PresetCommand::Delete { name } => {
+            let deleted = api.delete_preset(&name)?;
+            let message = match (deleted, api.purge) {
+                (true, true) => "Deleted preset",
+                (true, false) => "Moved preset to the trash",
+                (false, _) => "Preset not found",
+            };
+            let value = json!({ "preset": name, "deleted": deleted, "trashed": deleted && !api.purge });
+            print_outcome(json, value, format_args!("{}: {}", message, name))?;
+        }
+        PresetCommand::Export { name, output, wallpaper } => {
+            let output = match output {
+                Some(output) => paths::expand(&output),
+                None => PathBuf::from(format!("{}.chromash", api.sanitize_name(&name)?)),
+            };
+            api.export_preset(&name, &output, wallpaper)?;
+            let message = format!("Exported preset {} to {}", name, output.display());
+            print_outcome(json, json!({ "preset": name, "file": output }), message)?;
+        }
+        PresetCommand::Import { source, force } => {
+            let name = if download::is_url(&source) {
+                api.import_preset_url(&source, force)?
+            } else {
+                api.import_preset(&paths::expand(&source), force)?
+            };
+            print_outcome(json, json!({ "preset": name }), format_args!("Imported preset: {}", name))?;
+        }
+        PresetCommand::Share { name } => {
+            let share = api.share_preset(&name)?;
+            print_outcome(json, json!({ "preset": name, "share": share }), share)?;
+        }
+        PresetCommand::ImportString { string } => {
+            let name = api.apply_shared(&string)?;
+            print_outcome(json, json!({ "preset": name }), format_args!("Applied shared preset: {}", name))?;
+        }
+        PresetCommand::History { name } => {
+            let history = api.preset_history(&name)?;
+            if json {
+                return print_json(&history);
+            }
+            if history.is_empty() {
+                println!("No previous versions of preset: {}", name);
+            }
+            for (i, version) in history.iter().enumerate() {
+                println!("{}: {} ({})", i + 1, version.source.as_deref().unwrap_or("-"), format_timestamp(version.modified));
+            }
+        }
+        PresetCommand::Revert { name, to } => {
+            api.revert_preset(&name, to)?;
+            let message = format!("Reverted preset {} to version {}", name, to);
+            print_outcome(json, json!({ "preset": name, "version": to }), message)?;
+        }
+        PresetCommand::Rename { old, new } => {
+            api.rename_preset(&old, &new)?;
+            let message = format!("Renamed preset: {} -> {}", old, new);
+            print_outcome(json, json!({ "preset": new, "renamed_from": old }), message)?;
+        }
+        PresetCommand::Pin { name } => set_pinned(api, &name, true, json)?,
+        PresetCommand::Unpin { name } => set_pinned(api, &name, false, json)?,
+        PresetCommand::Tag { name, tag } => {
+            let changed = api.tag_preset(&name, &tag)?;
+            let message = if changed {
+                format!("Tagged preset {}: {}", name, tag)
+            } else {
+                format!("Preset {} already tagged: {}", name, tag)
+            };
+            print_outcome(json, json!({ "preset": name, "tag": tag, "changed": changed }), message)?;
+        }
+        PresetCommand::Describe { name, text } => {
+            let description = text.join(" ");
+            api.describe_preset(&name, &description)?;
+            let message = if description.trim().is_empty() {
+                format!("Cleared description of preset: {}", name)
+            } else {
+                format!("Described preset: {}", name)
+            };
+            let description = Some(description.trim()).filter(|d| !d.is_empty());
+            print_outcome(json, json!({ "preset": name, "description": description }), message)?;
+        }
+        PresetCommand::Untag { name, tag } => {
+            let changed = api.untag_preset(&name, &tag)?;
+            let message = if changed {
+                format!("Removed tag from preset {}: {}", name, tag)
+            } else {
+                format!("Preset {} not tagged: {}", name, tag)
+            };
+            print_outcome(json, json!({ "preset": name, "tag": tag, "changed": changed }), message)?;
+        }
+    }
+    Ok(())
+}
+
+fn set_pinned(api: &mut ChromashApi, name: &str, pinned: bool, json: bool) -> Result<()> {
+    let changed = api.set_preset_pinned(name, pinned)?;
+    let message = if changed {
+        format!("{} preset: {}", if pinned { "Pinned" } else { "Unpinned" }, name)
+    } else {
+        format!("Preset {} already {}", name, if pinned { "pinned" } else { "unpinned" })
+    };
+    print_outcome(json, json!({ "preset": name, "pinned": pinned, "changed": changed }), message)
+}
+
+const AFTER_HELP: &str = "\
+Launcher menu:
+  chromash menu | rofi -dmenu -show-icons | chromash menu --apply
+  chromash menu --format wofi | wofi --dmenu --allow-images | chromash menu --apply
+
+Waybar:
+  \"custom/chromash\": { \"exec\": \"chromash waybar-module\", \"return-type\": \"json\",
+    \"on-click\": \"chromash waybar-module next\", \"on-click-middle\": \"chromash undo\",
+    \"on-click-right\": \"chromash waybar-module toggle-mode\" }
+
+Image previews:
+  tui, presets --long and wallpaper list draw images with the kitty or sixel
+  protocol when the terminal supports it, colored blocks otherwise;
+  set CHROMASH_GRAPHICS=kitty|sixel|blocks to override.
+
+Scheme types:
+  content, expressive, fidelity, fruit-salad, monochrome,
+  neutral, rainbow, tonal-spot
+
+System presets:
+  Presets in $XDG_DATA_DIRS/chromash/presets are listed read-only;
+  a user preset with the same name takes precedence.
+
+Exit codes:
+  0 success, 1 other error, 2 not found, 3 external program failed,
+  4 IO error, 5 bad arguments, 6 invalid config or JSON, 7 theme locked,
+  8 another chromash is applying";
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::{ChromashError, Result};
+
+/// Errors from hyprctl that mean the compositor socket wasn't ready, not that the request
+/// was wrong.
+const TRANSIENT_ERRORS: &[&str] = &["couldn't connect", "connection refused", "resource temporarily unavailable"];
+
+/// An external program that failed, with enough detail to reproduce it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Failure {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Exit code; `None` if the program was killed by a signal or timed out.
+    pub status: Option<i32>,
+    pub timed_out: bool,
+    /// stderr, or stdout if stderr was empty.
+    pub output: String,
+}
+
+impl Failure {
+    fn new(command: &Command, status: Option<i32>, output: String) -> Self {
+        Self {
+            program: command.get_program().to_string_lossy().into_owned(),
+            args: command.get_args().map(|a| a.to_string_lossy().into_owned()).collect(),
+            status,
+            timed_out: false,
+            output,
+        }
+    }
+
+    /// Whether running the command again might succeed.
+    fn is_transient(&self) -> bool {
+        let output = self.output.to_lowercase();
+        self.timed_out || TRANSIENT_ERRORS.iter().any(|e| output.contains(e))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        write!(f, "`")?;
+        match self.status {
+            _ if self.timed_out => write!(f, " timed out")?,
+            Some(code) => write!(f, " exited with status {}", code)?,
+            None => write!(f, " was killed by a signal")?,
+        }
+        if !self.output.is_empty() {
+            write!(f, ": {}", self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the external programs chromash needs: the palette generator, the wallpaper
+/// backend, hyprctl, template hooks and the daemon's listeners.
+pub trait CommandRunner: Send + Sync {
+    /// Runs `command` and returns its stdout, killing it if it takes longer than `timeout`.
+    fn run(&self, command: &mut Command, timeout: Duration) -> Result<String>;
+
+    /// Starts `command` in the background and leaves it running, for daemons like swaybg.
+    fn spawn(&self, command: &mut Command) -> Result<()>;
+
+    /// Runs `command` on chromash's own stdout and stderr and waits however long it takes,
+    /// for user commands like template post hooks.
+    fn status(&self, command: &mut Command) -> Result<()>;
+
+    /// Runs `command` until it exits, passing each line of its stdout to `on_line`, for
+    /// listeners like swayidle.
+    fn stream(&self, command: &mut Command, on_line: &mut dyn FnMut(String)) -> Result<()>;
+
+    /// Like `run`, but tries up to `retries` more times while the failure looks transient
+    /// (a timeout or a socket that isn't accepting connections yet).
+    fn run_with_retries(&self, command: &mut Command, timeout: Duration, retries: u32) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            match self.run(command, timeout) {
+                Err(ChromashError::Command(failure)) if attempt < retries && failure.is_transient() => {
+                    attempt += 1;
+                    log::debug!("{}; retrying ({}/{})", failure, attempt, retries);
+                    thread::sleep(Duration::from_millis(200 * attempt as u64));
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A runner with the timeout each of its commands gets.
+pub struct Timed<'a> {
+    pub runner: &'a dyn CommandRunner,
+    pub timeout: Duration,
+}
+
+impl Timed<'_> {
+    pub fn run(&self, command: &mut Command) -> Result<String> {
+        self.runner.run(command, self.timeout)
+    }
+}
+
+/// Runs commands for real.
+pub struct System;
+
+impl CommandRunner for System {
+    fn run(&self, command: &mut Command, timeout: Duration) -> Result<String> {
+        run(command, timeout)
+    }
+
+    fn spawn(&self, command: &mut Command) -> Result<()> {
+        command.stdout(Stdio::null()).stderr(Stdio::null()).spawn()
+            .map_err(|e| ChromashError::Process(format!("Failed to start {}: {}", command.get_program().to_string_lossy(), e)))?;
+        Ok(())
+    }
+
+    fn status(&self, command: &mut Command) -> Result<()> {
+        let status = command.status()
+            .map_err(|e| ChromashError::Process(format!("Failed to run {}: {}", command.get_program().to_string_lossy(), e)))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(ChromashError::Command(Failure::new(command, status.code(), String::new())))
+        }
+    }
+
+    fn stream(&self, command: &mut Command, on_line: &mut dyn FnMut(String)) -> Result<()> {
+        let mut child = command.stdout(Stdio::piped()).stderr(Stdio::null()).spawn()
+            .map_err(|e| ChromashError::Process(format!("Failed to start {}: {}", command.get_program().to_string_lossy(), e)))?;
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines() {
+                on_line(line?);
+            }
+        }
+        child.wait()?;
+        Ok(())
+    }
+}
+
+/// Runs nothing: records each command line and answers with the output set for its
+/// program, empty by default. For tests that check what chromash would run; clones share
+/// the record, so one can be kept while another is handed to `ChromashApi::set_runner`.
+#[derive(Default, Clone)]
+pub struct Recorder {
+    calls: Arc<Mutex<Vec<String>>>,
+    outputs: BTreeMap<String, String>,
+}
+
+impl Recorder {
+    /// Answers every run of `program` with `stdout`.
+    pub fn respond(mut self, program: &str, stdout: &str) -> Self {
+        self.outputs.insert(program.to_string(), stdout.to_string());
+        self
+    }
+
+    /// The command lines run or spawned so far, oldest first.
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn record(&self, command: &Command) -> String {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let line = std::iter::once(program.clone())
+            .chain(command.get_args().map(|a| a.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.calls.lock().unwrap_or_else(|e| e.into_inner()).push(line);
+        program
+    }
+}
+
+impl CommandRunner for Recorder {
+    fn run(&self, command: &mut Command, _timeout: Duration) -> Result<String> {
+        let program = self.record(command);
+        Ok(self.outputs.get(&program).cloned().unwrap_or_default())
+    }
+
+    fn spawn(&self, command: &mut Command) -> Result<()> {
+        self.record(command);
+        Ok(())
+    }
+
+    fn status(&self, command: &mut Command) -> Result<()> {
+        self.record(command);
+        Ok(())
+    }
+
+    fn stream(&self, command: &mut Command, on_line: &mut dyn FnMut(String)) -> Result<()> {
+        let program = self.record(command);
+        self.outputs.get(&program).into_iter().flat_map(|output| output.lines()).for_each(|line| on_line(line.to_string()));
+        Ok(())
+    }
+}
+
+/// Runs `command` and returns its stdout, killing it if it takes longer than `timeout`.
+pub fn run(command: &mut Command, timeout: Duration) -> Result<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ChromashError::Process(format!("Failed to run {}: {}", command.get_program().to_string_lossy(), e)))?;
+    // Drain both pipes while waiting so a chatty program can't block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = wait(&mut child, timeout)?;
+    // After a timeout, whatever the program spawned may still hold the pipes open
+    let (stdout, stderr) = match status {
+        Some(_) => (stdout.join().unwrap_or_default(), stderr.join().unwrap_or_default()),
+        None => Default::default(),
+    };
+
+    match status {
+        Some(status) if status.success() => Ok(stdout),
+        status => {
+            let output = if stderr.trim().is_empty() { stdout } else { stderr };
+            let mut failure = Failure::new(command, status.and_then(|s| s.code()), output.trim().to_string());
+            failure.timed_out = status.is_none();
+            Err(ChromashError::Command(failure))
+        }
+    }
+}
+
+/// `CommandRunner::run_with_retries` for commands run outside `ChromashApi`.
+pub fn run_with_retries(command: &mut Command, timeout: Duration, retries: u32) -> Result<String> {
+    System.run_with_retries(command, timeout, retries)
+}
+
+/// Waits for `child` to exit; kills it and returns `None` once `timeout` has passed.
+fn wait(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorder_records_and_answers() {
+        let recorder = Recorder::default().respond("hyprctl", "[]").respond("swayidle", "idle dim\nidle restore\n");
+        let shared = recorder.clone();
+        assert_eq!(recorder.run(Command::new("hyprctl").args(["-j", "monitors"]), Duration::from_secs(1)).unwrap(), "[]");
+        assert_eq!(recorder.run(&mut Command::new("matugen"), Duration::from_secs(1)).unwrap(), "");
+        recorder.spawn(Command::new("swaybg").args(["-i", "a b.png"])).unwrap();
+        let mut lines = Vec::new();
+        recorder.stream(&mut Command::new("swayidle"), &mut |line| lines.push(line)).unwrap();
+        assert_eq!(lines, ["idle dim", "idle restore"]);
+        assert_eq!(shared.calls(), ["hyprctl -j monitors", "matugen", "swaybg -i a b.png", "swayidle"]);
+    }
+
+    #[test]
+    fn system_reports_failures() {
+        let error = System.run(Command::new("sh").args(["-c", "echo out; echo oops >&2; exit 3"]), Duration::from_secs(5)).unwrap_err();
+        let ChromashError::Command(failure) = error else { panic!("expected a command failure, got {}", error) };
+        assert_eq!(failure.status, Some(3));
+        assert!(!failure.timed_out);
+        assert_eq!(failure.output, "oops");
+        assert_eq!(System.run(Command::new("sh").args(["-c", "echo out"]), Duration::from_secs(5)).unwrap(), "out\n");
+    }
+
+    #[test]
+    fn system_kills_after_timeout() {
+        let error = System.run(Command::new("sleep").arg("5"), Duration::from_millis(100)).unwrap_err();
+        let ChromashError::Command(failure) = error else { panic!("expected a command failure, got {}", error) };
+        assert!(failure.timed_out);
+        assert!(failure.is_transient());
+    }
+
+    #[test]
+    fn system_streams_lines() {
+        let mut lines = Vec::new();
+        System.stream(Command::new("printf").arg("a\\nb\\n"), &mut |line| lines.push(line)).unwrap();
+        assert_eq!(lines, ["a", "b"]);
+        assert!(System.status(Command::new("sh").args(["-c", "exit 1"])).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{ChromashApi, Result};
+
+pub const COMMANDS: [&str; 32] = [
+    "color", "random-theme", "toggle", "set-color", "accent", "wallpaper", "wallpaper-only", "match",
+    "presets", "preset", "theme", "palette", "history", "stats", "undo", "redo", "lock", "unlock", "tui",
+    "menu", "daemon", "subscribe", "watch", "waybar-module", "idle", "hub", "profile", "completions",
+    "init", "install-desktop", "doctor", "help",
+];
+
+pub const PRESET_COMMANDS: [&str; 16] = [
+    "apply", "save", "delete", "bundle", "export", "import", "share", "import-string", "history",
+    "revert", "rename", "describe", "pin", "unpin", "tag", "untag",
+];
+
+/// Preset subcommands whose next argument is an existing preset name.
+const PRESET_NAME_COMMANDS: [&str; 14] = [
+    "apply", "delete", "bundle", "export", "share", "history", "revert", "rename", "describe",
+    "pin", "unpin", "tag", "untag", "save",
+];
+
+pub const SCHEMES: [&str; 8] = [
+    "content", "expressive", "fidelity", "fruit-salad", "monochrome", "neutral", "rainbow", "tonal-spot",
+];
+
+const BASH: &str = r#"_chromash() {
+    local IFS=$'\n'
+    COMPREPLY=($(chromash __complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+complete -o default -F _chromash chromash
+"#;
+
+const ZSH: &str = r#"#compdef chromash
+_chromash() {
+    local -a candidates
+    candidates=("${(@f)$(chromash __complete "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    (( ${#candidates[@]} )) && [[ -n ${candidates[1]} ]] && compadd -- "${candidates[@]}"
+}
+_chromash "$@"
+"#;
+
+const FISH: &str = r#"complete -c chromash -f -a '(chromash __complete (commandline -opc)[2..-1] (commandline -ct))'
+"#;
+
+/// Completion script for `shell`, which calls back into `chromash __complete`.
+pub fn script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None,
+    }
+}
+
+/// Candidates for the last of `words` (the word being completed), given the words before it.
+pub fn complete(api: &ChromashApi, words: &[String]) -> Result<Vec<String>> {
+    let (current, previous) = match words.split_last() {
+        Some((current, previous)) => (current.as_str(), previous),
+        None => ("", words),
+    };
+    let words: Vec<&str> = previous.iter().map(String::as_str).collect();
+
+    let candidates: Vec<String> = match (words.as_slice(), words.last().copied()) {
+        (_, Some("--mode" | "-m")) => to_strings(&["light", "dark"]),
+        (_, Some("--scheme" | "-s")) => to_strings(&SCHEMES),
+        (["menu", ..], Some("--format")) => to_strings(&["rofi", "wofi", "plain"]),
+        (_, Some("--format")) => to_strings(&["table", "json", "names"]),
+        (_, Some("--tag")) => tags(api)?,
+        (_, Some("--monitor")) => api.active_monitors(),
+        (_, Some("--backend")) => to_strings(crate::backend::Backend::NAMES),
+        (_, Some("--transition")) => to_strings(crate::config::SwwwConfig::TRANSITION_TYPES),
+        ([], _) => to_strings(&COMMANDS).into_iter().chain(api.config().aliases.keys().cloned()).collect(),
+        (["preset"], _) => to_strings(&PRESET_COMMANDS),
+        (["preset", "tag" | "untag", _], _) => tags(api)?,
+        (["preset", command], _) if PRESET_NAME_COMMANDS.contains(command) => preset_names(api)?,
+        (["wallpaper"], _) => ["list", "--random"].iter().map(|s| s.to_string()).chain(wallpapers(api)).collect(),
+        (["wallpaper-only"], _) => wallpapers(api),
+        (["match", _], _) => to_strings(&["--ui"]),
+        (["hub"], _) => to_strings(&["search", "install"]),
+        (["profile"], _) => to_strings(&["list", "switch", "off"]),
+        (["profile", "switch"], _) => api.config().profiles.keys().cloned().collect(),
+        (["menu", ..], _) => to_strings(&["--format", "--apply"]),
+        (["palette"], _) => to_strings(&["--tones"]),
+        (["toggle"], _) => to_strings(&["light", "dark"]),
+        (["set-color"], _) => roles(api)?,
+        (["random-theme", ..], _) => to_strings(&["--seed", "--mode", "--scheme", "--contrast"]),
+        (["idle"], _) => to_strings(&["dim", "restore"]),
+        (["waybar-module"], _) => to_strings(&["--once", "next", "prev", "toggle-mode"]),
+        (["completions"], _) => to_strings(&["bash", "zsh", "fish"]),
+        _ => Vec::new(),
+    };
+    Ok(candidates.into_iter().filter(|c| c.starts_with(current)).collect())
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+fn preset_names(api: &ChromashApi) -> Result<Vec<String>> {
+    Ok(api.list_presets()?.into_iter().filter(|p| !p.snapshot).map(|p| p.name).collect())
+}
+
+fn tags(api: &ChromashApi) -> Result<Vec<String>> {
+    let mut tags: Vec<String> = api.list_presets()?.into_iter().flat_map(|p| p.tags).collect();
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
+
+fn roles(api: &ChromashApi) -> Result<Vec<String>> {
+    Ok(api.current_palette()?.map(|palette| palette.roles(palette.mode()).keys().cloned().collect()).unwrap_or_default())
+}
+
+fn wallpapers(api: &ChromashApi) -> Vec<String> {
+    api.list_wallpapers().iter().map(|p| p.display().to_string()).collect()
+}
@@ -0,0 +1,447 @@
+use std::collections::BTreeMap;
+use std::fs;
+use serde::Deserialize;
+
+use crate::{completions, hub, profile};
+use crate::{unknown_value, ChromashError, ColorMode, Config, Result, SchemeType, ThemeOptions};
+
+/// User settings read from `~/.config/chromash/config.toml` (or `$CHROMASH_CONFIG_DIR/config.toml`).
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ChromashConfig {
+    /// Overrides `$XDG_PICTURES_DIR/Wallpapers`; `~` and `$VARS` are expanded.
+    pub wallpaper_dir: Option<String>,
+    /// Where wallpapers are copied for the backend to show, instead of `~/.config/hypr/hyprpaper`.
+    pub hyprpaper_dir: Option<String>,
+    /// Where presets are saved, instead of `presets` in the config directory.
+    pub presets_dir: Option<String>,
+    /// Scheme used when none is given, instead of guessing from the wallpaper.
+    pub scheme: Option<String>,
+    /// Mode used when none is given, instead of guessing from the wallpaper.
+    pub mode: Option<String>,
+    /// Contrast used when none is given, from -1.0 to 1.0.
+    pub contrast: Option<f64>,
+    /// Wallpaper program (hyprpaper, swww, swaybg, feh or macos); detected from the session if unset.
+    pub backend: Option<String>,
+    /// What generates palettes: matugen (the default), wallust (images only; renders its
+    /// own templates too) or native (built in, nothing to install).
+    pub generator: Option<String>,
+    /// Cron expression -> chromash command line, run by the daemon, e.g.
+    /// `"0 9 * * mon-fri" = "preset apply work"`.
+    pub schedule: BTreeMap<String, String>,
+    pub battery: Option<BatteryConfig>,
+    #[serde(rename = "season")]
+    pub seasons: Vec<SeasonConfig>,
+    pub mqtt: Option<MqttConfig>,
+    pub idle: Option<IdleConfig>,
+    pub lockscreen: Option<LockscreenConfig>,
+    pub night_light: Option<NightLightConfig>,
+    pub monitor_accents: Option<MonitorAccentsConfig>,
+    pub gtk: Option<GtkConfig>,
+    pub swww: SwwwConfig,
+    pub hyprpaper: HyprpaperConfig,
+    pub hub: HubConfig,
+    pub daemon: DaemonConfig,
+    pub snapshots: SnapshotConfig,
+    pub log: LogConfig,
+    pub instance: InstanceConfig,
+    pub commands: CommandsConfig,
+    pub cache: CacheConfig,
+    pub extraction: ExtractionConfig,
+    /// When applying takes the `--minimal` path besides when asked to: `auto` (on battery
+    /// in power-saver mode), `always` or `never`.
+    pub minimal: Minimal,
+    /// Shorthand -> command line, e.g. `dark = "color 1e1e2e --mode dark"`, split into
+    /// arguments with shell quoting and backslash escapes.
+    pub aliases: BTreeMap<String, String>,
+    /// Role -> color it keeps whatever the theme, e.g. `pin.primary = "#ff7a00"`.
+    pub pin: BTreeMap<String, String>,
+    /// Matugen template name -> settings that template always gets, e.g. `[apps.kitty]`
+    /// with `mode = "dark"` keeps the terminal dark when the desktop goes light.
+    pub apps: BTreeMap<String, AppConfig>,
+    /// Name -> config keys that replace the ones above while the profile is active, e.g.
+    /// `[profiles.presentation]` with `contrast = 0.5` and its own `[profiles.presentation.apps]`.
+    pub profiles: BTreeMap<String, toml::Table>,
+}
+
+/// Overrides for one application's template; unset fields follow the desktop.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub mode: Option<String>,
+    pub scheme: Option<String>,
+    /// Contrast from -1.0 to 1.0, instead of the desktop's.
+    pub contrast: Option<f64>,
+}
+
+impl AppConfig {
+    pub fn mode(&self) -> Option<ColorMode> {
+        self.mode.as_deref().and_then(ColorMode::from_str)
+    }
+
+    pub fn scheme(&self) -> Option<SchemeType> {
+        self.scheme.as_deref().and_then(SchemeType::from_str)
+    }
+}
+
+/// Low-power preset the daemon switches to while running on battery.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatteryConfig {
+    pub preset: String,
+    /// Only switch once the charge drops to this percentage; any discharge if unset.
+    pub threshold: Option<u8>,
+}
+
+/// A date range (`MM-DD`, inclusive, may wrap around the new year) with its own
+/// wallpaper subdirectory and/or preset.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SeasonConfig {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// Subdirectory of the wallpaper directory to pick wallpapers from.
+    pub directory: Option<String>,
+    pub preset: Option<String>,
+}
+
+/// Broker used to publish theme changes and receive apply commands.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttConfig {
+    #[serde(default = "default_mqtt_host")]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Prefix for `<topic>/theme` (published, retained) and `<topic>/set` (commands).
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+fn default_mqtt_host() -> String { "localhost".into() }
+fn default_mqtt_port() -> u16 { 1883 }
+fn default_mqtt_topic() -> String { "chromash".into() }
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HubConfig {
+    /// URL of the JSON index of shared presets.
+    pub index: String,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        Self { index: hub::DEFAULT_INDEX.into() }
+    }
+}
+
+/// Automatic snapshot presets recorded on every apply.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub enabled: bool,
+    pub keep: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self { enabled: false, keep: 20 }
+    }
+}
+
+/// Debug log written to `~/.local/state/chromash/chromash.log`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    pub file: bool,
+}
+
+/// Settings of `chromash daemon`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// `daily` applies a random wallpaper once a day.
+    pub shuffle: Option<String>,
+    /// Local time (`HH:MM`) the daily shuffle happens at.
+    pub shuffle_at: String,
+    /// `favorites` (the wallpapers of pinned presets) or a directory under the wallpaper
+    /// directory to shuffle from; the whole collection if unset.
+    pub shuffle_from: Option<String>,
+    /// While a scheduled action picks random wallpapers, how many upcoming picks to queue
+    /// and extract seeds and palettes for in advance; 0 turns it off.
+    pub precache: usize,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self { shuffle: None, shuffle_at: "00:00".into(), shuffle_from: None, precache: 3 }
+    }
+}
+
+/// What to do when another chromash is already applying a theme.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct InstanceConfig {
+    /// Wait for it to finish; fail immediately if false.
+    pub wait: bool,
+    /// Seconds to wait before giving up.
+    pub timeout: u64,
+}
+
+impl Default for InstanceConfig {
+    fn default() -> Self {
+        Self { wait: true, timeout: 30 }
+    }
+}
+
+/// How seed colors are picked from wallpapers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ExtractionConfig {
+    /// `low` always extracts as with `--fast`.
+    pub quality: Quality,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    #[default]
+    High,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Minimal {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Palettes kept in `~/.cache/chromash` so re-applying a theme skips matugen.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub palettes: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { palettes: true }
+    }
+}
+
+/// Limits on the external programs chromash runs.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CommandsConfig {
+    /// Seconds before hyprctl and other quick helpers are killed.
+    pub timeout: u64,
+    /// Extra attempts when hyprctl times out or can't reach the compositor.
+    pub retries: u32,
+    /// Seconds before matugen or wallust is killed; large images take a while.
+    pub matugen_timeout: u64,
+}
+
+impl Default for CommandsConfig {
+    fn default() -> Self {
+        Self { timeout: 5, retries: 2, matugen_timeout: 120 }
+    }
+}
+
+/// Dimmed variant the daemon applies after `timeout` minutes without input.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IdleConfig {
+    pub timeout: u64,
+    pub mode: String,
+    pub scheme: Option<String>,
+    pub contrast: f64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self { timeout: 10, mode: "dark".into(), scheme: None, contrast: -0.5 }
+    }
+}
+
+impl IdleConfig {
+    pub fn dim_options(&self) -> ThemeOptions {
+        ThemeOptions {
+            mode: ColorMode::from_str(&self.mode),
+            scheme: self.scheme.as_deref().and_then(SchemeType::from_str),
+            contrast: Some(self.contrast),
+            ..ThemeOptions::default()
+        }
+    }
+}
+
+/// Lock screen background rendered from the wallpaper whenever a theme is applied.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LockscreenConfig {
+    /// Blur radius in pixels of a 1920px wide image.
+    pub blur: f32,
+    /// How much darker than the wallpaper, from 0.0 to 1.0.
+    pub dim: f32,
+    /// Strength of the accent gradient rising from the bottom edge, from 0.0 to 1.0.
+    pub gradient: f32,
+    /// Point an existing `hyprlock.conf` and swaylock config at the image.
+    pub configure: bool,
+}
+
+impl Default for LockscreenConfig {
+    fn default() -> Self {
+        Self { blur: 24.0, dim: 0.35, gradient: 0.3, configure: true }
+    }
+}
+
+/// libadwaita named colors written from the palette whenever a theme is applied.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GtkConfig {
+    /// Import the stylesheet from `~/.config/gtk-4.0/gtk.css`.
+    pub install: bool,
+}
+
+impl Default for GtkConfig {
+    fn default() -> Self {
+        Self { install: true }
+    }
+}
+
+/// Screen color temperature switched along with the theme's mode.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NightLightConfig {
+    /// hyprsunset or gammastep; detected if unset.
+    pub program: Option<String>,
+    /// Kelvin while a dark theme is applied.
+    pub temperature: u32,
+    /// Kelvin while a light theme is applied; unset means the screen's normal colors.
+    pub day_temperature: Option<u32>,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        Self { program: None, temperature: 4000, day_temperature: None }
+    }
+}
+
+/// A palette per monitor, generated from the wallpaper each one shows, for templates
+/// (`{{monitors.DP-1.colors.primary.default.hex}}`) and Hyprland variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MonitorAccentsConfig {
+    /// Write `$chromash_<monitor>_<role>` variables to `~/.config/hypr/chromash-monitors.conf`.
+    pub hyprland: bool,
+}
+
+impl Default for MonitorAccentsConfig {
+    fn default() -> Self {
+        Self { hyprland: true }
+    }
+}
+
+/// hyprpaper settings written into chromash's block of `hyprpaper.conf`; unset ones are
+/// left to the rest of the file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct HyprpaperConfig {
+    /// Show Hyprland's splash text over the wallpaper.
+    pub splash: Option<bool>,
+    /// How far up the splash is drawn, in percent of the screen height.
+    pub splash_offset: Option<f64>,
+    /// hyprpaper's IPC socket; with it off, every wallpaper change restarts hyprpaper.
+    pub ipc: Option<bool>,
+}
+
+/// How the swww backend animates wallpaper changes; unset fields keep swww's defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SwwwConfig {
+    /// One of `TRANSITION_TYPES`.
+    pub transition_type: Option<String>,
+    /// Seconds.
+    pub transition_duration: Option<f64>,
+    /// Where `grow` and `outer` start: `center`, `top-left` and so on, or `x,y`.
+    pub transition_pos: Option<String>,
+    pub transition_fps: Option<u32>,
+}
+
+impl SwwwConfig {
+    pub const TRANSITION_TYPES: &'static [&'static str] = &[
+        "none", "simple", "fade", "left", "right", "top", "bottom", "wipe", "wave", "grow", "center", "any", "outer", "random",
+    ];
+
+    /// `overrides`, with the fields it leaves unset taken from `self`.
+    pub fn merge(&self, overrides: SwwwConfig) -> SwwwConfig {
+        SwwwConfig {
+            transition_type: overrides.transition_type.or_else(|| self.transition_type.clone()),
+            transition_duration: overrides.transition_duration.or(self.transition_duration),
+            transition_pos: overrides.transition_pos.or_else(|| self.transition_pos.clone()),
+            transition_fps: overrides.transition_fps.or(self.transition_fps),
+        }
+    }
+
+    /// Arguments for `swww img`.
+    pub fn args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+        if let Some(kind) = &self.transition_type {
+            if !Self::TRANSITION_TYPES.contains(&kind.as_str()) {
+                return Err(crate::unknown_value("transition type", kind, Self::TRANSITION_TYPES));
+            }
+            args.extend(["--transition-type".to_string(), kind.clone()]);
+        }
+        if let Some(duration) = self.transition_duration {
+            args.extend(["--transition-duration".to_string(), duration.to_string()]);
+        }
+        if let Some(pos) = &self.transition_pos {
+            args.extend(["--transition-pos".to_string(), pos.clone()]);
+        }
+        if let Some(fps) = self.transition_fps {
+            args.extend(["--transition-fps".to_string(), fps.to_string()]);
+        }
+        Ok(args)
+    }
+}
+
+impl ChromashConfig {
+    /// Fills in the configured default mode and scheme where `options` leaves them unset.
+    pub fn with_defaults(&self, options: ThemeOptions) -> ThemeOptions {
+        ThemeOptions {
+            mode: options.mode.or_else(|| self.mode.as_deref().and_then(ColorMode::from_str)),
+            scheme: options.scheme.or_else(|| self.scheme.as_deref().and_then(SchemeType::from_str)),
+            contrast: options.contrast.or(self.contrast.map(|c| c.clamp(-1.0, 1.0))),
+            fast: options.fast || self.extraction.quality == Quality::Low,
+            ..options
+        }
+    }
+    
+    /// The config file with the active profile's keys laid over it.
+    pub fn load() -> Result<Self> {
+        let path = Config::config_file();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let mut table: toml::Table = toml::from_str(&fs::read_to_string(&path)?)?;
+        if let Some(name) = profile::active() {
+            let overrides = table.get("profiles").and_then(|profiles| profiles.get(&name)).and_then(toml::Value::as_table).cloned();
+            match overrides {
+                Some(overrides) => profile::merge(&mut table, overrides),
+                None => log::warn!("Profile {} isn't defined in {}; using the config without it", name, path.display()),
+            }
+        }
+        let config: Self = table.try_into()?;
+        // Caught here rather than silently falling back to guessing from the wallpaper
+        let invalid = |e: ChromashError| ChromashError::General(format!("{} in {}", e, path.display()));
+        if let Some(mode) = config.mode.as_deref().filter(|mode| ColorMode::from_str(mode).is_none()) {
+            return Err(invalid(unknown_value("mode", mode, &["light", "dark"])));
+        }
+        if let Some(scheme) = config.scheme.as_deref().filter(|scheme| SchemeType::from_str(scheme).is_none()) {
+            return Err(invalid(unknown_value("scheme", scheme, &completions::SCHEMES)));
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Datelike, Local, Timelike};
+
+use crate::{ChromashError, Result};
+
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A standard five-field cron expression (minute hour day-of-month month day-of-week).
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(ChromashError::General(format!(
+                "Invalid cron expression '{}': expected 5 fields", expr
+            )));
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7, &DAY_NAMES, 0)?;
+        // Both 0 and 7 mean Sunday
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_field(fields[0], 0, 59, &[], 0)?,
+            hours: parse_field(fields[1], 0, 23, &[], 0)?,
+            days: parse_field(fields[2], 1, 31, &[], 0)?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES, 1)?,
+            weekdays,
+            // Like cron, a field starting with `*` (such as `*/2`) counts as unrestricted
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let bit = |mask: u64, n: u32| mask & (1 << n) != 0;
+        let day = bit(self.days, time.day());
+        let weekday = bit(self.weekdays, time.weekday().num_days_from_sunday());
+        // Like cron: when both day fields are restricted, either one may match
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        bit(self.minutes, time.minute())
+            && bit(self.hours, time.hour())
+            && bit(self.months, time.month())
+            && day_matches
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], name_offset: u32) -> Result<u64> {
+    let invalid = || ChromashError::General(format!("Invalid cron field: {}", field));
+    let value = |s: &str| -> Result<u32> {
+        let lower = s.to_lowercase();
+        if let Some(idx) = names.iter().position(|n| *n == lower) {
+            return Ok(idx as u32 + name_offset);
+        }
+        let n: u32 = s.parse().map_err(|_| invalid())?;
+        if n < min || n > max { Err(invalid()) } else { Ok(n) }
+    };
+
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (r, s.parse::<u32>().map_err(|_| invalid())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (value(a)?, value(b)?)
+        } else {
+            let n = value(range)?;
+            // "5/15" means "5-max/15"
+            (n, if part.contains('/') { max } else { n })
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn matches(expr: &str, time: DateTime<Local>) -> bool {
+        CronSchedule::parse(expr).unwrap().matches(&time)
+    }
+
+    /// The first minute after `after` the daemon's once-a-minute check would fire on.
+    fn next_fire(expr: &str, after: DateTime<Local>) -> DateTime<Local> {
+        let schedule = CronSchedule::parse(expr).unwrap();
+        let mut time = after + Duration::minutes(1);
+        while !schedule.matches(&time) {
+            time += Duration::minutes(1);
+            assert!(time < after + Duration::days(5 * 366), "{} never fires", expr);
+        }
+        time
+    }
+
+    #[test]
+    fn steps_ranges_lists_and_names() {
+        let expr = "*/15 9-17 * jan,JUL mon-fri";
+        assert!(matches(expr, at(2026, 1, 5, 9, 45)));
+        assert!(matches(expr, at(2026, 7, 6, 17, 30)));
+        assert!(!matches(expr, at(2026, 1, 5, 9, 50)));
+        assert!(!matches(expr, at(2026, 1, 5, 18, 0)));
+        assert!(!matches(expr, at(2026, 6, 1, 9, 0)));
+        assert!(!matches(expr, at(2026, 1, 4, 9, 0)));
+        assert!(matches("5/20 * * * *", at(2026, 1, 1, 0, 45)));
+        assert!(!matches("5/20 * * * *", at(2026, 1, 1, 0, 0)));
+        // Sunday is both 0 and 7
+        assert!(matches("0 12 * * 7", at(2026, 6, 7, 12, 0)));
+        assert!(matches("0 12 * * sun", at(2026, 6, 7, 12, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        for expr in ["60 * * * *", "* 24 * * *", "* * 0 * *", "* * 32 * *", "* * * 13 *", "* * * * 8",
+                     "*/0 * * * *", "5-1 * * * *", "* * * foo *", "* * * *", "* * * * * *"] {
+            assert!(CronSchedule::parse(expr).is_err(), "{} was accepted", expr);
+        }
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // The 13th, or any Friday
+        assert!(matches("0 0 13 * fri", at(2026, 2, 13, 0, 0)));
+        assert!(matches("0 0 13 * fri", at(2026, 4, 13, 0, 0)));
+        assert!(matches("0 0 13 * fri", at(2026, 6, 5, 0, 0)));
+        assert!(!matches("0 0 13 * fri", at(2026, 6, 3, 0, 0)));
+        // A stepped `*` is unrestricted, so both have to match: Mondays on odd days
+        assert!(matches("0 0 */2 * mon", at(2026, 6, 1, 0, 0)));
+        assert!(!matches("0 0 */2 * mon", at(2026, 6, 8, 0, 0)));
+        assert!(!matches("0 0 */2 * mon", at(2026, 6, 3, 0, 0)));
+    }
+
+    #[test]
+    fn next_fire_crosses_month_ends() {
+        assert_eq!(next_fire("0 0 1 * *", at(2026, 1, 31, 12, 0)), at(2026, 2, 1, 0, 0));
+        assert_eq!(next_fire("30 12 31 * *", at(2026, 4, 1, 0, 0)), at(2026, 5, 31, 12, 30));
+        assert_eq!(next_fire("0 0 29 feb *", at(2026, 3, 1, 0, 0)), at(2028, 2, 29, 0, 0));
+        assert_eq!(next_fire("59 23 * * *", at(2026, 12, 31, 23, 59)), at(2027, 1, 1, 23, 59));
+    }
+}
@@ -0,0 +1,357 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime};
+
+use crate::battery;
+use crate::dbus;
+use crate::hyprland;
+use crate::mqtt;
+use crate::profile;
+use crate::rpc;
+use crate::command::CommandRunner;
+use crate::config::{BatteryConfig, DaemonConfig, IdleConfig, MqttConfig};
+use crate::cron::CronSchedule;
+use crate::cli::execute;
+use crate::{paths, split_command_line, write_atomic, ChromashApi, ChromashError, Config, CurrentTheme, Result, ThemeOptions};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+struct ScheduledAction {
+    expr: String,
+    schedule: CronSchedule,
+    action: String,
+}
+
+/// Events delivered to the daemon loop from background listeners.
+enum DaemonEvent {
+    MonitorAdded(String),
+    /// A chromash command line received from an external source.
+    Command { origin: &'static str, line: String },
+    /// A JSON-RPC request from a client of the daemon socket, or a D-Bus call made into one.
+    Rpc(rpc::Call),
+}
+
+/// Tracks whether the low-power preset is active and what it replaced.
+#[derive(Default)]
+struct BatteryWatcher {
+    active: bool,
+    previous: Option<CurrentTheme>,
+}
+
+/// Runs in the foreground, executing scheduled actions, seasons, the daily shuffle and
+/// battery profiles from the config, the idle dim variant, commands received over MQTT, JSON-RPC and D-Bus, and
+/// re-applying the wallpaper when Hyprland reports a new monitor. Between ticks it
+/// precaches the next random wallpapers of scheduled slideshows.
+pub fn run(api: &mut ChromashApi) -> Result<()> {
+    let mut actions = scheduled_actions(api)?;
+    // Surface invalid season dates and shuffle settings at startup rather than on every tick
+    api.active_season()?;
+    let mut shuffle_at = shuffle_time(&api.config().daemon)?;
+    let mut active_profile = profile::active();
+
+    let (tx, rx) = mpsc::channel();
+    let rpc_tx = tx.clone();
+    rpc::serve(move |call| {
+        let _ = rpc_tx.send(DaemonEvent::Rpc(call));
+    })?;
+    let dbus_tx = tx.clone();
+    let served = dbus::serve(move |call| {
+        let _ = dbus_tx.send(DaemonEvent::Rpc(call));
+    });
+    if let Err(e) = served {
+        log::warn!("{}", e);
+    }
+    println!("Chromash daemon started ({} scheduled actions)", actions.len());
+
+    if let Some(mqtt_config) = api.config().mqtt.clone() {
+        spawn_mqtt_listener(mqtt_config, api.runner(), tx.clone());
+    }
+    if let Some(idle_config) = api.config().idle.clone() {
+        spawn_idle_listener(idle_config, api.runner(), tx.clone());
+    }
+    spawn_hyprland_listener(tx);
+
+    let mut battery_watcher = BatteryWatcher::default();
+    let mut precached = Vec::new();
+    let mut last_minute = None;
+    loop {
+        let now = Local::now();
+        let minute = now.timestamp() / 60;
+        if last_minute != Some(minute) {
+            last_minute = Some(minute);
+            let current_profile = profile::active();
+            if current_profile != active_profile {
+                active_profile = current_profile;
+                reload_profile(api, &mut actions, &mut shuffle_at);
+            }
+            check_season(api);
+            if let Some(at) = shuffle_at {
+                check_shuffle(api, at, &now);
+            }
+            for entry in actions.iter().filter(|a| a.schedule.matches(&now)) {
+                run_action(api, &entry.action, &entry.expr);
+            }
+            precache_next(api, &actions, &mut precached);
+        }
+        if let Some(config) = api.config().battery.clone() {
+            battery_watcher.update(api, &config);
+        }
+        wait_for_events(api, &rx);
+    }
+}
+
+fn scheduled_actions(api: &ChromashApi) -> Result<Vec<ScheduledAction>> {
+    api.config().schedule.iter()
+        .map(|(expr, action)| Ok(ScheduledAction {
+            expr: expr.clone(),
+            schedule: CronSchedule::parse(expr)?,
+            action: action.clone(),
+        }))
+        .collect()
+}
+
+/// Picks up the config of a profile switched to from outside the daemon: its schedule,
+/// shuffle and battery settings. Listeners started at launch (MQTT, idle) keep theirs.
+/// A profile with invalid settings keeps the previous ones running.
+fn reload_profile(api: &mut ChromashApi, actions: &mut Vec<ScheduledAction>, shuffle_at: &mut Option<NaiveTime>) {
+    let reloaded = api.reload_config()
+        .and_then(|_| Ok((scheduled_actions(api)?, shuffle_time(&api.config().daemon)?)));
+    match reloaded {
+        Ok((new_actions, new_shuffle_at)) => {
+            *actions = new_actions;
+            *shuffle_at = new_shuffle_at;
+            println!("[profile] {} ({} scheduled actions)", profile::active().as_deref().unwrap_or("(none)"), actions.len());
+        }
+        Err(e) => log::error!("Failed to load the config for the new profile: {}", e),
+    }
+}
+
+/// Sleeps for one poll interval, handling any events that arrive meanwhile.
+fn wait_for_events(api: &mut ChromashApi, rx: &Receiver<DaemonEvent>) {
+    match rx.recv_timeout(POLL_INTERVAL) {
+        Ok(DaemonEvent::MonitorAdded(name)) => {
+            println!("[hyprland] monitor added: {}", name);
+            if let Err(e) = api.refresh_wallpaper() {
+                log::error!("Failed to re-apply wallpaper: {}", e);
+            }
+        }
+        Ok(DaemonEvent::Command { origin, line }) => run_action(api, &line, origin),
+        Ok(DaemonEvent::Rpc(call)) => {
+            log::debug!("[rpc] {}", call.request.method);
+            let _ = call.reply.send(rpc::handle(api, &call.request));
+        }
+        Err(RecvTimeoutError::Timeout) => {}
+        // All listeners are gone; fall back to plain polling
+        Err(RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+    }
+}
+
+fn spawn_mqtt_listener(config: MqttConfig, runner: Arc<dyn CommandRunner>, tx: Sender<DaemonEvent>) {
+    thread::spawn(move || {
+        let result = mqtt::listen_commands(&config, runner.as_ref(), |line| {
+            let _ = tx.send(DaemonEvent::Command { origin: "mqtt", line });
+        });
+        if let Err(e) = result {
+            log::error!("MQTT listener stopped: {}", e);
+        }
+    });
+}
+
+/// Runs swayidle (ext-idle-notify) with hooks that echo `idle dim|restore` back to us.
+fn spawn_idle_listener(config: IdleConfig, runner: Arc<dyn CommandRunner>, tx: Sender<DaemonEvent>) {
+    thread::spawn(move || {
+        let timeout = (config.timeout * 60).to_string();
+        let mut command = Command::new("swayidle");
+        command.args(["-w", "timeout", &timeout, "echo idle dim", "resume", "echo idle restore"]);
+        let result = runner.stream(&mut command, &mut |line| {
+            let _ = tx.send(DaemonEvent::Command { origin: "idle", line });
+        });
+        if let Err(e) = result {
+            log::error!("swayidle stopped: {}", e);
+        }
+    });
+}
+
+fn spawn_hyprland_listener(tx: Sender<DaemonEvent>) {
+    if hyprland::instance_dir().is_none() {
+        return;
+    }
+    thread::spawn(move || {
+        let result = hyprland::listen_events(|event, data| {
+            if event == "monitoradded" {
+                let _ = tx.send(DaemonEvent::MonitorAdded(data.to_string()));
+            }
+        });
+        if let Err(e) = result {
+            log::error!("Hyprland event listener stopped: {}", e);
+        }
+    });
+}
+
+/// Applies a season's collection when its date range begins. The last season is
+/// persisted so restarts within a season don't override the user's choices.
+fn check_season(api: &mut ChromashApi) {
+    let season = match api.active_season() {
+        Ok(season) => season.cloned(),
+        Err(e) => {
+            log::error!("Failed to evaluate seasons: {}", e);
+            return;
+        }
+    };
+    let name = season.as_ref().map(|s| s.name.as_str()).unwrap_or_default();
+    let last = fs::read_to_string(Config::season_file()).unwrap_or_default();
+    if name == last.trim() {
+        return;
+    }
+    let written = fs::create_dir_all(Config::config_dir()).map_err(Into::into)
+        .and_then(|_| write_atomic(&Config::season_file(), name));
+    if let Err(e) = written {
+        log::error!("Failed to record season: {}", e);
+    }
+    if let Some(season) = season {
+        println!("[season] entering {}", season.name);
+        if let Err(e) = api.apply_season(&season) {
+            log::error!("Failed to apply season {}: {}", season.name, e);
+        }
+    }
+}
+
+/// When the daily shuffle happens, if it's on.
+fn shuffle_time(config: &DaemonConfig) -> Result<Option<NaiveTime>> {
+    match config.shuffle.as_deref() {
+        None => Ok(None),
+        Some("daily") => NaiveTime::parse_from_str(&config.shuffle_at, "%H:%M")
+            .map(Some)
+            .map_err(|_| ChromashError::General(format!("Invalid shuffle_at: {} (expected HH:MM, e.g. 07:30)", config.shuffle_at))),
+        Some(other) => Err(crate::unknown_value("shuffle", other, &["daily"])),
+    }
+}
+
+/// Applies a random wallpaper once the day's shuffle time has passed. The day and the
+/// pick are persisted, so restarts within the day keep the theme (and the user's choices
+/// since), and the next pick differs from the last one.
+fn check_shuffle(api: &mut ChromashApi, at: NaiveTime, now: &DateTime<Local>) {
+    // Before the shuffle time it's still the previous day's pick
+    let today = now.date_naive();
+    let day = if now.time() >= at { Some(today) } else { today.pred_opt() };
+    let Some(day) = day else { return };
+    let last = fs::read_to_string(Config::shuffle_file()).unwrap_or_default();
+    let mut lines = last.lines();
+    if lines.next().and_then(|d| d.parse::<NaiveDate>().ok()) == Some(day) {
+        return;
+    }
+    let previous = lines.next().map(PathBuf::from);
+
+    let mut candidates = match shuffle_candidates(api, api.config().daemon.shuffle_from.as_deref()) {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            log::error!("Failed to list wallpapers to shuffle: {}", e);
+            return;
+        }
+    };
+    if candidates.len() > 1 {
+        candidates.retain(|path| Some(path) != previous.as_ref());
+    }
+    if candidates.is_empty() {
+        log::warn!("No wallpapers to shuffle");
+        return;
+    }
+    let wallpaper = &candidates[(crate::random_u64() % candidates.len() as u64) as usize];
+    let record = format!("{}\n{}\n", day, wallpaper.display());
+    let written = fs::create_dir_all(Config::state_dir()).map_err(Into::into)
+        .and_then(|_| write_atomic(&Config::shuffle_file(), record));
+    if let Err(e) = written {
+        log::error!("Failed to record the daily shuffle: {}", e);
+    }
+    println!("[shuffle] {}", wallpaper.display());
+    if let Err(e) = api.apply_wallpaper(wallpaper.to_str(), true, ThemeOptions::default()) {
+        log::error!("Failed to apply the daily wallpaper: {}", e);
+    }
+}
+
+/// Tops up the queue of random wallpapers and caches the seed and palette of the next one
+/// not yet cached, so slideshow rotations swap instantly. One per tick keeps the daemon
+/// responsive; `precached` remembers which queued ones are done.
+fn precache_next(api: &ChromashApi, actions: &[ScheduledAction], precached: &mut Vec<PathBuf>) {
+    let count = api.config().daemon.precache;
+    let slideshow = actions.iter().any(|entry| split_command_line(&entry.action).iter().any(|arg| arg == "--random"));
+    // On battery in power-saver mode the work waits until the rotation needs it
+    if count == 0 || !slideshow || api.minimal() {
+        return;
+    }
+    let queue = match api.fill_queue(count) {
+        Ok(queue) => queue,
+        Err(e) => {
+            log::warn!("Failed to queue upcoming wallpapers: {}", e);
+            return;
+        }
+    };
+    precached.retain(|path| queue.contains(path));
+    let Some(next) = queue.into_iter().find(|path| !precached.contains(path)) else { return };
+    log::info!("Precaching {}", next.display());
+    if let Err(e) = api.precache(&next) {
+        log::warn!("Failed to precache {}: {}", next.display(), e);
+    }
+    precached.push(next);
+}
+
+/// Wallpapers of pinned presets for `favorites`, the images in a directory under the
+/// wallpaper directory, or the whole collection.
+fn shuffle_candidates(api: &ChromashApi, from: Option<&str>) -> Result<Vec<PathBuf>> {
+    match from {
+        Some("favorites") => Ok(api.list_presets()?.into_iter()
+            .filter(|preset| preset.pinned)
+            .filter_map(|preset| preset.wallpaper)
+            .filter(|wallpaper| wallpaper.is_file())
+            .collect()),
+        Some(dir) => {
+            let mut found = Vec::new();
+            crate::collect_images(&api.wallpaper_dir().join(paths::expand(dir)), &mut found);
+            found.sort();
+            Ok(found)
+        }
+        None => Ok(api.list_wallpapers()),
+    }
+}
+
+fn run_action(api: &mut ChromashApi, action: &str, trigger: &str) {
+    let mut args = vec!["chromash".to_string()];
+    args.extend(split_command_line(action));
+    println!("[{}] {}", trigger, action);
+    if let Err(e) = execute(api, &args) {
+        log::error!("Action '{}' failed: {}", action, e);
+    }
+}
+
+impl BatteryWatcher {
+    fn update(&mut self, api: &mut ChromashApi, config: &BatteryConfig) {
+        let Some(state) = battery::read_state() else { return };
+        let low_power = state.discharging
+            && match (config.threshold, state.capacity) {
+                (Some(threshold), Some(capacity)) => capacity <= threshold,
+                _ => true,
+            };
+
+        if low_power && !self.active {
+            self.active = true;
+            self.previous = api.load_current_theme().ok().flatten();
+            println!("[battery] switching to preset {}", config.preset);
+            if let Err(e) = api.apply_preset(&config.preset, ThemeOptions::default()) {
+                log::error!("Failed to apply low-power preset: {}", e);
+            }
+        } else if !state.discharging && self.active {
+            self.active = false;
+            if let Some(previous) = self.previous.take() {
+                println!("[battery] restoring {}", previous.source);
+                if let Err(e) = api.apply_source(&previous.source, previous.options()) {
+                    log::error!("Failed to restore theme: {}", e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::mpsc;
+use std::thread;
+use serde_json::{json, Value};
+use zbus::blocking::connection;
+use zbus::object_server::SignalEmitter;
+use zbus::{fdo, interface};
+
+use crate::rpc::{self, Call, Request};
+use crate::{ChromashError, Result};
+
+const BUS_NAME: &str = "org.chromash.Theme";
+const OBJECT_PATH: &str = "/org/chromash/Theme";
+
+/// The `org.chromash.Theme` interface. Calls are answered like the JSON-RPC methods of the
+/// same name, by the daemon's `ChromashApi`, and results are the same JSON as strings.
+struct ThemeService {
+    on_call: Box<dyn Fn(Call) + Send + Sync>,
+}
+
+impl ThemeService {
+    fn call(&self, method: &str, params: Value) -> fdo::Result<String> {
+        let (reply, response) = mpsc::channel();
+        (self.on_call)(Call { request: Request::new(method, params), reply });
+        let response = response.recv().map_err(|_| fdo::Error::Failed("The daemon stopped".into()))?;
+        let result = response.into_result().map_err(fdo::Error::Failed)?;
+        Ok(result.to_string())
+    }
+}
+
+#[interface(name = "org.chromash.Theme")]
+impl ThemeService {
+    /// Generates and applies a theme from a hex color; returns what was applied.
+    #[zbus(out_args("applied"))]
+    fn apply_color(&self, hex: &str) -> fdo::Result<String> {
+        self.call("apply", json!({ "color": hex }))
+    }
+
+    /// Sets a wallpaper and applies colors extracted from it.
+    #[zbus(out_args("applied"))]
+    fn apply_wallpaper(&self, path: &str) -> fdo::Result<String> {
+        self.call("apply", json!({ "wallpaper": path }))
+    }
+
+    /// Applies a saved preset. Downloaded presets must have been applied once with chromash.
+    #[zbus(out_args("applied"))]
+    fn apply_preset(&self, name: &str) -> fdo::Result<String> {
+        self.call("apply", json!({ "preset": name }))
+    }
+
+    /// The current theme, palette and wallpaper.
+    #[zbus(out_args("state"))]
+    fn get_current_theme(&self) -> fdo::Result<String> {
+        self.call("state", Value::Null)
+    }
+
+    /// Sent with the new theme, palette and wallpaper whenever the theme changes.
+    #[zbus(signal)]
+    async fn theme_changed(emitter: &SignalEmitter<'_>, state: &str) -> zbus::Result<()>;
+}
+
+/// Owns `org.chromash.Theme` on the session bus and serves the interface at
+/// `/org/chromash/Theme`, passing calls to `on_call`. Keeps running in the background.
+pub fn serve(on_call: impl Fn(Call) + Send + Sync + 'static) -> Result<()> {
+    let service = ThemeService { on_call: Box::new(on_call) };
+    let connection = connection::Builder::session()
+        .and_then(|builder| builder.name(BUS_NAME))
+        .and_then(|builder| builder.serve_at(OBJECT_PATH, service))
+        .and_then(|builder| builder.build())
+        .map_err(|e| ChromashError::General(format!("Failed to register {} on the session bus: {}", BUS_NAME, e)))?;
+    log::info!("Serving {} on the session bus", BUS_NAME);
+    // The watcher holds the connection, keeping the name for as long as the daemon runs
+    thread::spawn(move || {
+        let emitter = match SignalEmitter::new(connection.inner(), OBJECT_PATH) {
+            Ok(emitter) => emitter,
+            Err(e) => {
+                log::error!("Failed to set up D-Bus signals: {}", e);
+                return;
+            }
+        };
+        rpc::watch_theme(|state| {
+            let state = serde_json::to_string(&state).unwrap_or_default();
+            if let Err(e) = zbus::block_on(ThemeService::theme_changed(&emitter, &state)) {
+                log::warn!("Failed to send ThemeChanged: {}", e);
+            }
+            true
+        });
+    });
+    Ok(())
+}
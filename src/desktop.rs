@@ -0,0 +1,110 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{write_atomic, Config, Result};
+
+/// A launcher entry: file name, name, what it does, chromash's arguments and whether it
+/// needs a terminal.
+struct Entry {
+    file: &'static str,
+    name: &'static str,
+    comment: &'static str,
+    args: &'static str,
+    terminal: bool,
+}
+
+/// The main entry opens the TUI; the others run one command and exit. Besides being
+/// actions of the main entry, those get entries of their own for launchers and menus
+/// (rofi, wofi) that don't show actions.
+const ENTRIES: &[Entry] = &[
+    Entry { file: "chromash.desktop", name: "Chromash", comment: "Browse wallpapers and presets", args: "tui", terminal: true },
+    Entry { file: "chromash-random-wallpaper.desktop", name: "Random wallpaper", comment: "Set a random wallpaper and theme from it", args: "wallpaper --random", terminal: false },
+    Entry { file: "chromash-toggle-mode.desktop", name: "Toggle dark mode", comment: "Switch the theme between light and dark", args: "toggle", terminal: false },
+];
+
+/// Actions of the main entry: action id and the entry it runs.
+const ACTIONS: &[(&str, &str)] = &[
+    ("random-wallpaper", "chromash-random-wallpaper.desktop"),
+    ("toggle-mode", "chromash-toggle-mode.desktop"),
+    ("open-tui", "chromash.desktop"),
+];
+
+const ICON: &str = "preferences-desktop-theme";
+
+/// Writes the launcher entries into the applications directory, running the chromash
+/// binary at `exe`. Returns the files written.
+pub fn install(exe: &Path, dry_run: bool) -> Result<Vec<PathBuf>> {
+    let dir = Config::applications_dir();
+    let command = command_line(exe);
+    let mut written = Vec::new();
+    for entry in ENTRIES {
+        let path = dir.join(entry.file);
+        if dry_run {
+            println!("[dry-run] write {}", path.display());
+            continue;
+        }
+        let mut content = desktop_entry(entry, &command);
+        if entry.file == "chromash.desktop" {
+            content.push_str(&actions(&command));
+        }
+        fs::create_dir_all(&dir)?;
+        log::debug!("Writing {}", path.display());
+        write_atomic(&path, content)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+fn desktop_entry(entry: &Entry, command: &str) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n\
+         Comment={}\n\
+         Exec={} {}\n\
+         Icon={}\n\
+         Terminal={}\n\
+         Categories=Settings;DesktopSettings;\n\
+         Keywords=wallpaper;theme;colors;\n",
+        entry.name, entry.comment, command, entry.args, ICON, entry.terminal)
+}
+
+fn actions(command: &str) -> String {
+    let ids: Vec<&str> = ACTIONS.iter().map(|(id, _)| *id).collect();
+    let mut content = format!("Actions={};\n", ids.join(";"));
+    for (id, file) in ACTIONS {
+        let Some(entry) = ENTRIES.iter().find(|entry| entry.file == *file) else { continue };
+        let name = if entry.file == "chromash.desktop" { "Open TUI" } else { entry.name };
+        content.push_str(&format!("\n[Desktop Action {}]\nName={}\nExec={} {}\n", id, name, command, entry.args));
+    }
+    content
+}
+
+/// `exe`, quoted for an `Exec` key, behind `env` for the config root and backend of the
+/// chromash being installed from.
+fn command_line(exe: &Path) -> String {
+    let environment: Vec<String> = ["CHROMASH_CONFIG_DIR", "CHROMASH_BACKEND"].iter()
+        .filter_map(|name| env::var(name).ok().filter(|value| !value.is_empty()).map(|value| quote(&format!("{}={}", name, value))))
+        .collect();
+    let exe = quote(&exe.display().to_string());
+    if environment.is_empty() {
+        exe
+    } else {
+        format!("env {} {}", environment.join(" "), exe)
+    }
+}
+
+/// Quotes `arg` by the desktop entry spec's rules if it needs it.
+fn quote(arg: &str) -> String {
+    if !arg.contains(|c: char| c.is_whitespace() || "\"'\\`$<>|&;*?#()".contains(c)) {
+        return arg.to_string();
+    }
+    let escaped: String = arg.chars()
+        .flat_map(|c| match c {
+            '"' | '`' | '$' | '\\' => vec!['\\', c],
+            c => vec![c],
+        })
+        .collect();
+    format!("\"{}\"", escaped)
+}
@@ -0,0 +1,242 @@
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+use image::{ImageFormat, RgbImage};
+use serde::Serialize;
+
+use crate::command::CommandRunner;
+use crate::config::ChromashConfig;
+use crate::backend::{Backend, Session};
+use crate::{hyprland, paths, templates, ChromashError, Config, Result};
+
+/// How long a program gets to print its version.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize)]
+struct Check {
+    status: Status,
+    message: String,
+    /// What to do about a warning or failure.
+    fix: Option<&'static str>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Self { status: Status::Ok, message: message.into(), fix: None }
+    }
+    fn warn(message: impl Into<String>, fix: &'static str) -> Self {
+        Self { status: Status::Warn, message: message.into(), fix: Some(fix) }
+    }
+    fn fail(message: impl Into<String>, fix: &'static str) -> Self {
+        Self { status: Status::Fail, message: message.into(), fix: Some(fix) }
+    }
+}
+
+/// Checks external tools, directories and the session chromash depends on, printing a
+/// fix for each problem, or all checks as JSON. Fails if any required check fails.
+pub fn run(json: bool, runner: &dyn CommandRunner) -> Result<()> {
+    let config = ChromashConfig::load();
+    let mut checks = vec![
+        palette_generator(config.as_ref().ok().and_then(|c| c.generator.as_deref()), runner),
+        monitor_tool(runner),
+        wallpaper_backend(config.as_ref().ok().and_then(|c| c.backend.as_deref())),
+        hyprland_session(),
+        writable_dir("Config directory", Config::config_dir()),
+        writable_dir("Wallpaper cache", config.as_ref().ok()
+            .and_then(|c| c.hyprpaper_dir.as_deref())
+            .map_or_else(Config::default_hyprpaper_dir, paths::expand)),
+        image_support(),
+        matugen_templates(),
+    ];
+    match &config {
+        Ok(config) => {
+            checks.push(Check::ok(format!("Config {}", describe_config_file())));
+            if config.idle.is_some() {
+                checks.push(optional_tool("swayidle", "Install swayidle or remove the [idle] section"));
+            }
+            if let Some(night_light) = &config.night_light {
+                checks.push(match crate::nightlight::Program::select(night_light.program.as_deref()) {
+                    Ok(program) => optional_tool(program.as_str(), "Install it or remove the [night_light] section"),
+                    Err(e) => Check::warn(format!("Night light: {}", e), "Install hyprsunset or gammastep and set program to it, or remove the [night_light] section"),
+                });
+            }
+            if config.mqtt.is_some() {
+                checks.push(optional_tool("mosquitto_pub", "Install mosquitto clients or remove the [mqtt] section"));
+            }
+        }
+        Err(e) => checks.push(Check::fail(format!("Config: {}", e), "Fix the syntax error in config.toml")),
+    }
+    checks.push(optional_tool("curl", "Install curl to download presets from URLs and the hub"));
+    checks.push(optional_tool("tar", "Install tar to export and import preset archives"));
+
+    let failures = checks.iter().filter(|check| matches!(check.status, Status::Fail)).count();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        for check in &checks {
+            let label = match check.status {
+                Status::Ok => "ok",
+                Status::Warn => "warn",
+                Status::Fail => "FAIL",
+            };
+            println!("[{:>4}] {}", label, check.message);
+            if let Some(fix) = check.fix {
+                println!("       -> {}", fix);
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(ChromashError::General(format!("{} check(s) failed", failures)));
+    }
+    Ok(())
+}
+
+/// Absolute path of `name` on `$PATH`, if it is an executable file.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| {
+            fs::metadata(candidate).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+}
+
+fn required_tool(name: &str, fix: &'static str, runner: &dyn CommandRunner) -> Check {
+    match find_executable(name) {
+        Some(path) => Check::ok(format!("{} found at {}{}", name, path.display(), version_of(name, runner))),
+        None => Check::fail(format!("{} not found on PATH", name), fix),
+    }
+}
+
+/// The program monitors are listed with in this session.
+fn monitor_tool(runner: &dyn CommandRunner) -> Check {
+    match Session::detect() {
+        Session::Sway => required_tool("swaymsg", "Install sway; swaymsg is used to list monitors", runner),
+        Session::X11 => optional_tool("xrandr", "Install xrandr to set wallpapers per monitor"),
+        Session::Wayland => Check::warn(
+            "Monitors can't be listed in this session",
+            "Wallpapers apply to every output; --monitor needs Hyprland or sway",
+        ),
+        // Inside Hyprland its socket is used; hyprctl only when that can't be found
+        Session::MacOs => Check::ok("macOS: wallpapers apply to every display"),
+        Session::Hyprland => optional_tool("hyprctl", "Install hyprctl to list monitors when Hyprland's socket is missing"),
+        Session::Unknown => required_tool("hyprctl", "Install Hyprland; hyprctl is used to list monitors", runner),
+    }
+}
+
+fn optional_tool(name: &str, fix: &'static str) -> Check {
+    match find_executable(name) {
+        Some(path) => Check::ok(format!("{} found at {}", name, path.display())),
+        None => Check::warn(format!("{} not found on PATH", name), fix),
+    }
+}
+
+fn version_of(name: &str, runner: &dyn CommandRunner) -> String {
+    runner.run(Command::new(name).arg("--version"), VERSION_TIMEOUT).ok()
+        .and_then(|stdout| stdout.lines().next().map(str::to_string))
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| format!(" ({})", line.trim()))
+        .unwrap_or_default()
+}
+
+fn wallpaper_backend(configured: Option<&str>) -> Check {
+    let backend = match Backend::select(configured) {
+        Ok(backend) => backend,
+        Err(e) => return Check::fail(format!("Wallpaper backend: {}", e), "Use hyprpaper, swww, swaybg, feh or macos"),
+    };
+    match find_executable(backend.program()) {
+        Some(path) => Check::ok(format!("Wallpaper backend {} at {}", backend.as_str(), path.display())),
+        None => Check::fail(
+            format!("Wallpaper backend {} not found on PATH", backend.program()),
+            "Install it, or pick an installed one with --backend or `backend` in config.toml",
+        ),
+    }
+}
+
+fn palette_generator(configured: Option<&str>, runner: &dyn CommandRunner) -> Check {
+    match crate::generator::select(configured) {
+        Ok(generator) => match generator.program() {
+            Some("matugen") => required_tool("matugen", "Install matugen (https://github.com/InioX/matugen) or set generator = \"native\"; without one only `wallpaper-only` works", runner),
+            Some(program) => required_tool(program, "Install it or set another generator; without one only `wallpaper-only` works", runner),
+            None => Check::ok(format!("Colors are generated by the {} generator", generator.name())),
+        },
+        Err(e) => Check::fail(format!("Generator: {}", e), "Use matugen, wallust or native"),
+    }
+}
+
+fn hyprland_session() -> Check {
+    match hyprland::instance_dir() {
+        Some(dir) if dir.join(".socket.sock").exists() => Check::ok(format!("Hyprland session at {}", dir.display())),
+        Some(dir) => Check::warn(
+            format!("HYPRLAND_INSTANCE_SIGNATURE is set but {} has no socket", dir.display()),
+            "Run chromash from inside the running Hyprland session",
+        ),
+        None if Session::detect() != Session::Unknown => Check::ok(format!("{:?} session", Session::detect())),
+        None => Check::warn(
+            "Not running inside a graphical session",
+            "Wallpapers apply to detected monitors only inside Hyprland, sway or X11",
+        ),
+    }
+}
+
+fn writable_dir(label: &str, dir: PathBuf) -> Check {
+    let probe = dir.join(format!(".chromash-doctor-{}", std::process::id()));
+    let writable = fs::create_dir_all(&dir).and_then(|_| fs::write(&probe, b"")).is_ok();
+    let _ = fs::remove_file(&probe);
+    if writable {
+        Check::ok(format!("{} {} is writable", label, dir.display()))
+    } else {
+        Check::fail(format!("{} {} is not writable", label, dir.display()), "Check ownership and permissions of the directory")
+    }
+}
+
+/// Round-trips a tiny image through each enabled codec.
+fn image_support() -> Check {
+    let sample = RgbImage::from_pixel(2, 2, image::Rgb([40, 80, 120]));
+    let mut broken = Vec::new();
+    for (format, name) in [(ImageFormat::Png, "png"), (ImageFormat::Jpeg, "jpeg"), (ImageFormat::Bmp, "bmp")] {
+        let mut bytes = Cursor::new(Vec::new());
+        let round_trip = sample.write_to(&mut bytes, format).is_ok()
+            && image::load_from_memory_with_format(bytes.get_ref(), format).is_ok();
+        if !round_trip {
+            broken.push(name);
+        }
+    }
+    if broken.is_empty() {
+        Check::ok("Image decoding supports png, jpeg and bmp (gif and webp are passed to matugen only)")
+    } else {
+        Check::fail(format!("Image decoding failed for {}", broken.join(", ")), "Rebuild chromash with the image features enabled")
+    }
+}
+
+fn matugen_templates() -> Check {
+    match templates::load_templates() {
+        Ok(entries) if entries.is_empty() => Check::warn(
+            "No templates in the matugen config",
+            "Add [templates.<name>] entries to ~/.config/matugen/config.toml so colors reach your apps",
+        ),
+        Ok(entries) => Check::ok(format!("{} matugen template(s) configured", entries.len())),
+        Err(e) => Check::fail(format!("matugen config: {}", e), "Fix ~/.config/matugen/config.toml"),
+    }
+}
+
+fn describe_config_file() -> String {
+    let path = Config::config_file();
+    if path.exists() {
+        format!("{} loaded", path.display())
+    } else {
+        format!("{} not present, using defaults", path.display())
+    }
+}
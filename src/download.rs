@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use crate::command::CommandRunner;
+use crate::{progress, ChromashError, Result};
+
+/// Largest file chromash will download, in bytes.
+pub const MAX_DOWNLOAD_SIZE: u64 = 20 * 1024 * 1024;
+
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("https://") || s.starts_with("http://")
+}
+
+/// Downloads `url` to `dest` with curl, refusing anything larger than `max_bytes`.
+pub fn fetch(url: &str, dest: &Path, max_bytes: u64, runner: &dyn CommandRunner) -> Result<()> {
+    log::debug!("Downloading {} to {}", url, dest.display());
+    let mut curl = Command::new("curl");
+    curl.args(["--fail", "--silent", "--show-error", "--location", "--proto", "=https,http"])
+        .args(["--max-filesize", &max_bytes.to_string(), "--max-time", "120", "-o"])
+        .arg(dest)
+        .arg(url);
+    // Report progress from the size of the partially written file
+    let bar = progress::bytes(format!("Downloading {}", url));
+    let result = thread::scope(|scope| {
+        // A little past curl's own --max-time, which reports the clearer error
+        let download = scope.spawn(|| runner.run(&mut curl, Duration::from_secs(130)));
+        while !download.is_finished() {
+            bar.set_position(fs::metadata(dest).map_or(0, |m| m.len()));
+            thread::sleep(Duration::from_millis(100));
+        }
+        download.join().unwrap_or_else(|_| Err(ChromashError::General("Download stopped".into())))
+    });
+    bar.finish_and_clear();
+    if let Err(e) = result {
+        let _ = fs::remove_file(dest);
+        let reason = match e {
+            ChromashError::Command(failure) => failure.output,
+            e => e.to_string(),
+        };
+        return Err(ChromashError::Process(format!("Download of {} failed: {}", url, reason)));
+    }
+    // Servers that omit Content-Length bypass --max-filesize
+    if fs::metadata(dest)?.len() > max_bytes {
+        let _ = fs::remove_file(dest);
+        return Err(ChromashError::General(format!("Download exceeds {} bytes: {}", max_bytes, url)));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+/// Levenshtein distance between two strings, compared case-insensitively by character.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `query`, if any is within a third of the query's length
+/// (at least two edits), so unrelated names aren't suggested.
+pub fn closest<'a, I>(query: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max_distance = (query.chars().count() / 3).max(2);
+    candidates.into_iter()
+        .map(|candidate| (distance(query, candidate), candidate))
+        .filter(|&(d, _)| d <= max_distance)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_ignores_case() {
+        assert_eq!(distance("Cozy", "cozy"), 0);
+        assert_eq!(distance("cozzy", "cozy"), 1);
+        assert_eq!(distance("", "dark"), 4);
+    }
+
+    #[test]
+    fn closest_prefers_the_fewest_edits() {
+        let presets = ["cozy", "calm", "dark", "drake"];
+        assert_eq!(closest("cozzy", presets), Some("cozy"));
+        assert_eq!(closest("drak", presets), Some("drake"));
+        assert_eq!(closest("DARK", presets), Some("dark"));
+        // Ties go to the first candidate
+        assert_eq!(closest("cazy", ["calm", "cozy", "lazy"]), Some("cozy"));
+    }
+
+    #[test]
+    fn closest_skips_unrelated_names() {
+        assert_eq!(closest("ocean", ["cozy", "dark"]), None);
+        assert_eq!(closest("cozy", std::iter::empty()), None);
+    }
+}
@@ -0,0 +1,392 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::palette::{Palette, TONAL_PALETTES, TONES};
+use crate::command::Timed;
+use crate::{thumbnail, ChromashError, ColorMode, Result, SchemeType};
+
+/// What a palette is generated from.
+#[derive(Debug, Clone)]
+pub enum Seed {
+    /// `#rrggbb`
+    Color(String),
+    Image(PathBuf),
+}
+
+impl Seed {
+    pub fn image(&self) -> Option<&Path> {
+        match self {
+            Self::Image(path) => Some(path),
+            Self::Color(_) => None,
+        }
+    }
+
+    /// The source arguments of a matugen command line, e.g. `color hex #6750a4`.
+    pub fn matugen_args(&self) -> Vec<OsString> {
+        match self {
+            Self::Color(hex) => vec!["color".into(), "hex".into(), hex.into()],
+            Self::Image(path) => vec!["image".into(), path.into()],
+        }
+    }
+
+    /// The seed color of an image is picked the way chromash picks the accent of a
+    /// wallpaper.
+    fn rgb(&self) -> Result<[u8; 3]> {
+        match self {
+            Self::Color(hex) => thumbnail::parse_hex(hex)
+                .ok_or_else(|| ChromashError::General(format!("Invalid hex color: {}", hex))),
+            Self::Image(path) => crate::average_color(path, false).map(|(r, g, b)| [r, g, b]),
+        }
+    }
+}
+
+/// Turns a seed into a palette. Generators that bring their own template ecosystem render
+/// it while generating; the templates in `~/.config/matugen` are rendered by matugen, or
+/// by chromash from the palette for every other generator.
+pub trait Generator: Send {
+    /// What `generator` is set to in config.toml.
+    fn name(&self) -> &'static str;
+
+    /// The program generating runs, if any.
+    fn program(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Where to get `program`.
+    fn homepage(&self) -> &'static str {
+        ""
+    }
+
+    /// Whether generating with `render` writes the matugen templates, so chromash doesn't.
+    fn renders_matugen_templates(&self) -> bool {
+        false
+    }
+
+    /// Whether generating with `render` writes templates of the generator's own, so a
+    /// cached palette can't stand in for running it.
+    fn has_own_templates(&self) -> bool {
+        false
+    }
+
+    /// What generating would do, for dry runs.
+    fn describe(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> String;
+
+    /// Generates the palette for `seed`, writing the generator's own templates too with
+    /// `render`, and running any program with `runner`. `None` if the generator rendered
+    /// its templates but reported no palette.
+    fn generate(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>, render: bool, runner: &Timed) -> Result<Option<Palette>>;
+}
+
+pub const NAMES: &[&str] = &["matugen", "wallust", "native"];
+
+/// `configured`, or matugen.
+pub fn select(configured: Option<&str>) -> Result<Box<dyn Generator>> {
+    let Some(name) = configured else {
+        return Ok(Box::new(Matugen));
+    };
+    match name.to_lowercase().as_str() {
+        "matugen" => Ok(Box::new(Matugen)),
+        "wallust" => Ok(Box::new(Wallust)),
+        "native" => Ok(Box::new(Native)),
+        _ => Err(crate::unknown_value("generator", name, NAMES)),
+    }
+}
+
+/// The matugen CLI, which renders the templates in its own config.
+pub struct Matugen;
+
+impl Matugen {
+    fn command(seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Command {
+        let mut command = Command::new("matugen");
+        command.args(["-m", mode.as_str(), "-t", scheme.as_str()])
+            .args(seed.matugen_args());
+        if let Some(contrast) = contrast {
+            command.args(["--contrast", &contrast.to_string()]);
+        }
+        command.args(["--json", "hex"]);
+        command
+    }
+}
+
+impl Generator for Matugen {
+    fn name(&self) -> &'static str {
+        "matugen"
+    }
+
+    fn program(&self) -> Option<&'static str> {
+        Some("matugen")
+    }
+
+    fn homepage(&self) -> &'static str {
+        "https://github.com/InioX/matugen"
+    }
+
+    fn renders_matugen_templates(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> String {
+        format!("run {:?}", Self::command(seed, mode, scheme, contrast))
+    }
+
+    fn generate(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>, render: bool, runner: &Timed) -> Result<Option<Palette>> {
+        let mut command = Self::command(seed, mode, scheme, contrast);
+        if !render {
+            command.arg("--dry-run");
+        }
+        log::debug!("Running {:?}", command);
+        let output = runner.run(&mut command)?;
+        Ok(Palette::from_matugen_output(&output))
+    }
+}
+
+/// The wallust CLI, which renders the templates in `~/.config/wallust`. wallust reports no
+/// palette, so chromash's own (for the matugen templates, waybar and the lock screen)
+/// comes from the native generator with the same seed.
+pub struct Wallust;
+
+impl Wallust {
+    fn command(image: &Path, mode: ColorMode, contrast: Option<f64>) -> Command {
+        let mut command = Command::new("wallust");
+        command.arg("run").arg(image)
+            .args(["--palette", mode.as_str(), "--quiet"]);
+        if contrast.is_some_and(|c| c > 0.0) {
+            command.arg("--check-contrast");
+        }
+        command
+    }
+
+    fn image(seed: &Seed) -> Result<&Path> {
+        seed.image().ok_or_else(|| ChromashError::General(
+            "wallust only generates colors from images; set generator = \"matugen\" or \"native\" in config.toml for color themes".into()))
+    }
+}
+
+impl Generator for Wallust {
+    fn name(&self) -> &'static str {
+        "wallust"
+    }
+
+    fn program(&self) -> Option<&'static str> {
+        Some("wallust")
+    }
+
+    fn homepage(&self) -> &'static str {
+        "https://codeberg.org/explosion-mental/wallust"
+    }
+
+    fn has_own_templates(&self) -> bool {
+        true
+    }
+
+    fn describe(&self, seed: &Seed, mode: ColorMode, _scheme: SchemeType, contrast: Option<f64>) -> String {
+        match Self::image(seed) {
+            Ok(image) => format!("run {:?}", Self::command(image, mode, contrast)),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn generate(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>, render: bool, runner: &Timed) -> Result<Option<Palette>> {
+        let image = Self::image(seed)?;
+        if render {
+            let mut command = Self::command(image, mode, contrast);
+            log::debug!("Running {:?}", command);
+            runner.run(&mut command)?;
+        }
+        Native.generate(seed, mode, scheme, contrast, false, runner)
+    }
+}
+
+/// Material 3 style palettes computed by chromash itself, with no program to install.
+/// Tones are CIELAB lightness rather than matugen's HCT tone, so colors come out close to
+/// matugen's but not identical.
+pub struct Native;
+
+impl Generator for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn describe(&self, seed: &Seed, _mode: ColorMode, scheme: SchemeType, _contrast: Option<f64>) -> String {
+        match seed {
+            Seed::Color(hex) => format!("generate a {} palette from {}", scheme.as_str(), hex),
+            Seed::Image(path) => format!("generate a {} palette from {}", scheme.as_str(), path.display()),
+        }
+    }
+
+    fn generate(&self, seed: &Seed, _mode: ColorMode, scheme: SchemeType, contrast: Option<f64>, _render: bool, _runner: &Timed) -> Result<Option<Palette>> {
+        Ok(Some(native_palette(seed.rgb()?, scheme, contrast.unwrap_or(0.0))))
+    }
+}
+
+/// Material 3 roles: role -> (tonal palette, tone in light mode, tone in dark mode).
+const ROLES: &[(&str, &str, u8, u8)] = &[
+    ("primary", "primary", 40, 80),
+    ("on_primary", "primary", 100, 20),
+    ("primary_container", "primary", 90, 30),
+    ("on_primary_container", "primary", 10, 90),
+    ("primary_fixed", "primary", 90, 90),
+    ("primary_fixed_dim", "primary", 80, 80),
+    ("on_primary_fixed", "primary", 10, 10),
+    ("on_primary_fixed_variant", "primary", 30, 30),
+    ("inverse_primary", "primary", 80, 40),
+    ("surface_tint", "primary", 40, 80),
+    ("secondary", "secondary", 40, 80),
+    ("on_secondary", "secondary", 100, 20),
+    ("secondary_container", "secondary", 90, 30),
+    ("on_secondary_container", "secondary", 10, 90),
+    ("secondary_fixed", "secondary", 90, 90),
+    ("secondary_fixed_dim", "secondary", 80, 80),
+    ("on_secondary_fixed", "secondary", 10, 10),
+    ("on_secondary_fixed_variant", "secondary", 30, 30),
+    ("tertiary", "tertiary", 40, 80),
+    ("on_tertiary", "tertiary", 100, 20),
+    ("tertiary_container", "tertiary", 90, 30),
+    ("on_tertiary_container", "tertiary", 10, 90),
+    ("tertiary_fixed", "tertiary", 90, 90),
+    ("tertiary_fixed_dim", "tertiary", 80, 80),
+    ("on_tertiary_fixed", "tertiary", 10, 10),
+    ("on_tertiary_fixed_variant", "tertiary", 30, 30),
+    ("error", "error", 40, 80),
+    ("on_error", "error", 100, 20),
+    ("error_container", "error", 90, 30),
+    ("on_error_container", "error", 10, 90),
+    ("background", "neutral", 98, 6),
+    ("on_background", "neutral", 10, 90),
+    ("surface", "neutral", 98, 6),
+    ("on_surface", "neutral", 10, 90),
+    ("surface_dim", "neutral", 87, 6),
+    ("surface_bright", "neutral", 98, 24),
+    ("surface_container_lowest", "neutral", 100, 4),
+    ("surface_container_low", "neutral", 96, 10),
+    ("surface_container", "neutral", 94, 12),
+    ("surface_container_high", "neutral", 92, 17),
+    ("surface_container_highest", "neutral", 90, 22),
+    ("inverse_surface", "neutral", 20, 90),
+    ("inverse_on_surface", "neutral", 95, 20),
+    ("shadow", "neutral", 0, 0),
+    ("scrim", "neutral", 0, 0),
+    ("surface_variant", "neutral_variant", 90, 30),
+    ("on_surface_variant", "neutral_variant", 30, 80),
+    ("outline", "neutral_variant", 50, 60),
+    ("outline_variant", "neutral_variant", 80, 30),
+];
+
+/// CIELAB hue of Material's error red.
+const ERROR_HUE: f64 = 35.0;
+
+/// Hue (relative to the seed's, except for error) and chroma of each of `TONAL_PALETTES`,
+/// after Material's scheme definitions. `seed_chroma` is the seed's own chroma.
+fn tonal_palettes(scheme: SchemeType, seed_chroma: f64) -> [(f64, f64); 6] {
+    let c = seed_chroma;
+    let [primary, secondary, tertiary, neutral, neutral_variant] = match scheme {
+        SchemeType::TonalSpot => [(0.0, 36.0), (0.0, 16.0), (60.0, 24.0), (0.0, 6.0), (0.0, 8.0)],
+        SchemeType::Content | SchemeType::Fidelity => {
+            [(0.0, c), (0.0, (c - 32.0).max(c * 0.5)), (60.0, (c * 0.6).max(24.0)), (0.0, c / 8.0), (0.0, c / 8.0 + 4.0)]
+        }
+        SchemeType::Expressive => [(240.0, 40.0), (15.0, 24.0), (60.0, 32.0), (15.0, 8.0), (15.0, 12.0)],
+        SchemeType::FruitSalad => [(-50.0, 48.0), (-50.0, 36.0), (0.0, 36.0), (0.0, 10.0), (0.0, 16.0)],
+        SchemeType::Monochrome => [(0.0, 0.0); 5],
+        SchemeType::Neutral => [(0.0, 12.0), (0.0, 8.0), (60.0, 16.0), (0.0, 2.0), (0.0, 2.0)],
+        SchemeType::Rainbow => [(0.0, 48.0), (0.0, 16.0), (60.0, 24.0), (0.0, 0.0), (0.0, 0.0)],
+    };
+    [primary, secondary, tertiary, neutral, neutral_variant, (f64::NAN, 84.0)]
+}
+
+/// Both modes' roles and every tonal palette for `seed`. `contrast` (-1.0 to 1.0) spreads
+/// role tones away from the middle or pulls them towards it.
+fn native_palette(seed: [u8; 3], scheme: SchemeType, contrast: f64) -> Palette {
+    let (_, seed_chroma, seed_hue) = lch(seed);
+    let hues: Vec<(&str, f64, f64)> = TONAL_PALETTES.iter()
+        .zip(tonal_palettes(scheme, seed_chroma))
+        .map(|(name, (rotation, chroma))| {
+            let hue = if rotation.is_nan() { ERROR_HUE } else { (seed_hue + rotation).rem_euclid(360.0) };
+            (*name, hue, chroma)
+        })
+        .collect();
+    let color = |name: &str, tone: f64| {
+        let (_, hue, chroma) = hues.iter().find(|(palette, _, _)| *palette == name).copied().unwrap_or(("", 0.0, 0.0));
+        hex(tone_color(hue, chroma, tone))
+    };
+
+    let mut palette = Palette::default();
+    for &(name, _, _) in &hues {
+        let tones = TONES.iter().map(|&tone| (tone.to_string(), color(name, tone as f64))).collect();
+        palette.palettes.insert(name.to_string(), tones);
+    }
+    let spread = 1.0 + contrast.clamp(-1.0, 1.0) * 0.2;
+    let contrasted = |tone: u8| (50.0 + (tone as f64 - 50.0) * spread).clamp(0.0, 100.0);
+    for &(role, name, light, dark) in ROLES {
+        palette.colors.light.insert(role.to_string(), color(name, contrasted(light)));
+        palette.colors.dark.insert(role.to_string(), color(name, contrasted(dark)));
+    }
+    for roles in [&mut palette.colors.light, &mut palette.colors.dark] {
+        roles.insert("source_color".to_string(), hex(seed));
+    }
+    palette
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// D65 white point.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+const EPSILON: f64 = 216.0 / 24389.0;
+const KAPPA: f64 = 24389.0 / 27.0;
+
+/// sRGB -> CIE LCh: lightness from 0 to 100, chroma and hue in degrees.
+fn lch(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    let xyz = [
+        0.4124 * r + 0.3576 * g + 0.1805 * b,
+        0.2126 * r + 0.7152 * g + 0.0722 * b,
+        0.0193 * r + 0.1192 * g + 0.9505 * b,
+    ];
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let t = xyz[i] / WHITE[i];
+        if t > EPSILON { t.cbrt() } else { (KAPPA * t + 16.0) / 116.0 }
+    });
+    let (a, b) = (500.0 * (fx - fy), 200.0 * (fy - fz));
+    (116.0 * fy - 16.0, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.0))
+}
+
+/// CIE LCh -> linear sRGB, which is out of the 0-1 range for colors sRGB can't show.
+fn linear_rgb(lightness: f64, chroma: f64, hue: f64) -> [f64; 3] {
+    let hue = hue.to_radians();
+    let fy = (lightness + 16.0) / 116.0;
+    let f = [fy + chroma * hue.cos() / 500.0, fy, fy - chroma * hue.sin() / 200.0];
+    let [x, y, z] = [0, 1, 2].map(|i| {
+        let t = if f[i].powi(3) > EPSILON { f[i].powi(3) } else { (116.0 * f[i] - 16.0) / KAPPA };
+        t * WHITE[i]
+    });
+    [
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    ]
+}
+
+/// The color of lightness `tone` and `hue` with as much of `chroma` as sRGB can show.
+pub fn tone_color(hue: f64, chroma: f64, tone: f64) -> [u8; 3] {
+    let in_gamut = |chroma| linear_rgb(tone, chroma, hue).iter().all(|c| (-1e-4..=1.0001).contains(c));
+    let chroma = if in_gamut(chroma) {
+        chroma
+    } else {
+        let (mut low, mut high) = (0.0, chroma);
+        for _ in 0..16 {
+            let mid = (low + high) / 2.0;
+            if in_gamut(mid) { low = mid } else { high = mid }
+        }
+        low
+    };
+    linear_rgb(tone, chroma, hue).map(|c| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+        (c * 255.0).round() as u8
+    })
+}
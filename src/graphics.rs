@@ -0,0 +1,216 @@
+use std::env;
+use std::fmt::Write as _;
+use std::io::{Cursor, IsTerminal, Write};
+use std::path::Path;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{imageops::FilterType, ImageFormat, ImageReader, RgbImage};
+use ratatui::crossterm::terminal;
+
+use crate::{ChromashError, Result};
+
+/// Base64 payload size per kitty graphics escape, as the protocol requires.
+const KITTY_CHUNK: usize = 4096;
+
+/// How images are drawn in the terminal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    /// Truecolor `▀` half blocks, which work everywhere.
+    Blocks,
+}
+
+/// Picks the protocol from `$CHROMASH_GRAPHICS` (`kitty`, `sixel` or `blocks`), or from
+/// the terminal identified by the environment.
+pub fn detect() -> Protocol {
+    match env::var("CHROMASH_GRAPHICS").as_deref() {
+        Ok("kitty") => return Protocol::Kitty,
+        Ok("sixel") => return Protocol::Sixel,
+        Ok("blocks") => return Protocol::Blocks,
+        _ => {}
+    }
+    // tmux swallows graphics escapes unless passthrough is configured
+    if env::var_os("TMUX").is_some() {
+        return Protocol::Blocks;
+    }
+    let term = env::var("TERM").unwrap_or_default();
+    let program = env::var("TERM_PROGRAM").unwrap_or_default();
+    if env::var_os("KITTY_WINDOW_ID").is_some()
+        || term == "xterm-kitty"
+        || term == "xterm-ghostty"
+        || program == "WezTerm"
+        || program == "ghostty"
+    {
+        Protocol::Kitty
+    } else if term.starts_with("foot") || term.contains("sixel") || term == "mlterm" || program == "iTerm.app" {
+        Protocol::Sixel
+    } else {
+        Protocol::Blocks
+    }
+}
+
+/// Pixel size of a terminal cell, assuming 8x16 when the terminal doesn't report it.
+pub fn cell_size() -> (u32, u32) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            ((size.width / size.columns) as u32, (size.height / size.rows) as u32)
+        }
+        _ => (8, 16),
+    }
+}
+
+/// Largest size in cells that fits `max_cols` x `max_rows` and keeps the image's aspect ratio.
+pub fn fit(image: &RgbImage, max_cols: u16, max_rows: u16) -> (u16, u16) {
+    let (width, height) = image.dimensions();
+    let (cell_width, cell_height) = cell_size();
+    if width == 0 || height == 0 {
+        return (0, 0);
+    }
+    let scale = (max_cols as f64 * cell_width as f64 / width as f64)
+        .min(max_rows as f64 * cell_height as f64 / height as f64);
+    let cols = (width as f64 * scale / cell_width as f64).round().clamp(1.0, max_cols as f64);
+    let rows = (height as f64 * scale / cell_height as f64).round().clamp(1.0, max_rows as f64);
+    (cols as u16, rows as u16)
+}
+
+/// Escape sequence (or, for blocks, colored text) that draws `image` over `cols` x `rows`
+/// cells starting at the cursor.
+pub fn encode(protocol: Protocol, image: &RgbImage, cols: u16, rows: u16) -> Result<String> {
+    match protocol {
+        Protocol::Kitty => kitty(image, cols, rows),
+        Protocol::Sixel => {
+            let (cell_width, cell_height) = cell_size();
+            let scaled = image::imageops::resize(image, cols as u32 * cell_width, rows as u32 * cell_height, FilterType::Triangle);
+            Ok(sixel(&scaled))
+        }
+        Protocol::Blocks => Ok(blocks(image, cols, rows)),
+    }
+}
+
+/// Removes images placed with the kitty protocol; other protocols draw into the text grid
+/// and disappear when it is redrawn.
+pub fn clear(protocol: Protocol) -> &'static str {
+    match protocol {
+        Protocol::Kitty => "\x1b_Ga=d,q=2\x1b\\",
+        _ => "",
+    }
+}
+
+/// Prints an image file inline, at most `max_cols` x `max_rows` cells. Does nothing when
+/// stdout isn't a terminal, so piped output stays plain text.
+pub fn print_file(path: &Path, max_cols: u16, max_rows: u16) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    if !stdout.is_terminal() {
+        return Ok(());
+    }
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()
+        .map_err(|e| ChromashError::General(format!("Failed to decode {}: {}", path.display(), e)))?
+        .into_rgb8();
+    let (cols, rows) = fit(&image, max_cols, max_rows);
+    let protocol = detect();
+    // Every protocol leaves the cursor on the image's last row
+    let mut output = encode(protocol, &image, cols, rows)?;
+    output.push('\n');
+    stdout.write_all(output.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn kitty(image: &RgbImage, cols: u16, rows: u16) -> Result<String> {
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| ChromashError::General(format!("Failed to encode preview: {}", e)))?;
+    let data = STANDARD.encode(png.into_inner());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut output = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            let _ = write!(output, "\x1b_Ga=T,f=100,q=2,c={},r={},m={};{}\x1b\\", cols, rows, more, chunk);
+        } else {
+            let _ = write!(output, "\x1b_Gm={};{}\x1b\\", more, chunk);
+        }
+    }
+    Ok(output)
+}
+
+/// Encodes with a fixed 6x6x6 color cube, one band of six pixel rows at a time.
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+    let index = |x: u32, y: u32| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        level(r) * 36 + level(g) * 6 + level(b)
+    };
+
+    let mut output = format!("\x1bPq\"1;1;{};{}", width, height);
+    for i in 0..216 {
+        let _ = write!(output, "#{};2;{};{};{}", i, i / 36 * 20, i / 6 % 6 * 20, i % 6 * 20);
+    }
+    for top in (0..height).step_by(6) {
+        let rows = (height - top).min(6);
+        let band: Vec<usize> = (0..rows).flat_map(|dy| (0..width).map(move |x| (x, top + dy)))
+            .map(|(x, y)| index(x, y))
+            .collect();
+        let mut used = [false; 216];
+        for &color in &band {
+            used[color] = true;
+        }
+        let mut first = true;
+        for color in (0..216).filter(|&c| used[c]) {
+            if !first {
+                output.push('$');
+            }
+            first = false;
+            let _ = write!(output, "#{}", color);
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width as usize {
+                let bits = (0..rows as usize)
+                    .filter(|&dy| band[dy * width as usize + x] == color)
+                    .fold(0u8, |bits, dy| bits | 1 << dy);
+                let symbol = (63 + bits) as char;
+                run = match run {
+                    Some((current, count)) if current == symbol => Some((current, count + 1)),
+                    Some((current, count)) => {
+                        push_run(&mut output, current, count);
+                        Some((symbol, 1))
+                    }
+                    None => Some((symbol, 1)),
+                };
+            }
+            if let Some((symbol, count)) = run {
+                push_run(&mut output, symbol, count);
+            }
+        }
+        output.push('-');
+    }
+    output.push_str("\x1b\\");
+    output
+}
+
+fn push_run(output: &mut String, symbol: char, count: usize) {
+    if count > 3 {
+        let _ = write!(output, "!{}{}", count, symbol);
+    } else {
+        output.extend(std::iter::repeat_n(symbol, count));
+    }
+}
+
+fn blocks(image: &RgbImage, cols: u16, rows: u16) -> String {
+    let scaled = image::imageops::resize(image, cols as u32, rows as u32 * 2, FilterType::Triangle);
+    let mut output = String::new();
+    for y in 0..rows as u32 {
+        if y > 0 {
+            output.push('\n');
+        }
+        for x in 0..cols as u32 {
+            let [r, g, b] = scaled.get_pixel(x, y * 2).0;
+            let [br, bg, bb] = scaled.get_pixel(x, y * 2 + 1).0;
+            let _ = write!(output, "\x1b[38;2;{};{};{};48;2;{};{};{}m▀", r, g, b, br, bg, bb);
+        }
+        output.push_str("\x1b[0m");
+    }
+    output
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::Path;
+
+use crate::palette::Palette;
+use crate::{write_atomic, Result};
+
+/// Lines between these markers in `gtk.css` belong to chromash.
+const BEGIN_MARKER: &str = "/* >>> chromash colors (edits here are overwritten) >>> */";
+const END_MARKER: &str = "/* <<< chromash colors <<< */";
+
+/// libadwaita named color -> Material roles to take it from, first one the palette has.
+const NAMED_COLORS: &[(&str, &[&str])] = &[
+    ("accent_color", &["primary"]),
+    ("accent_bg_color", &["primary"]),
+    ("accent_fg_color", &["on_primary"]),
+    ("destructive_color", &["error"]),
+    ("destructive_bg_color", &["error"]),
+    ("destructive_fg_color", &["on_error"]),
+    ("error_color", &["error"]),
+    ("error_bg_color", &["error"]),
+    ("error_fg_color", &["on_error"]),
+    ("window_bg_color", &["surface", "background"]),
+    ("window_fg_color", &["on_surface", "on_background"]),
+    ("view_bg_color", &["surface_container_lowest", "surface", "background"]),
+    ("view_fg_color", &["on_surface", "on_background"]),
+    ("headerbar_bg_color", &["surface_container", "surface", "background"]),
+    ("headerbar_fg_color", &["on_surface", "on_background"]),
+    ("headerbar_border_color", &["outline_variant", "outline"]),
+    ("headerbar_backdrop_color", &["surface", "background"]),
+    ("sidebar_bg_color", &["surface_container_low", "surface", "background"]),
+    ("sidebar_fg_color", &["on_surface", "on_background"]),
+    ("sidebar_backdrop_color", &["surface_container_low", "surface", "background"]),
+    ("sidebar_border_color", &["outline_variant", "outline"]),
+    ("secondary_sidebar_bg_color", &["surface_container", "surface", "background"]),
+    ("secondary_sidebar_fg_color", &["on_surface", "on_background"]),
+    ("secondary_sidebar_backdrop_color", &["surface_container", "surface", "background"]),
+    ("card_bg_color", &["surface_container", "surface_variant"]),
+    ("card_fg_color", &["on_surface", "on_surface_variant"]),
+    ("dialog_bg_color", &["surface_container_high", "surface_variant"]),
+    ("dialog_fg_color", &["on_surface", "on_surface_variant"]),
+    ("popover_bg_color", &["surface_container_high", "surface_variant"]),
+    ("popover_fg_color", &["on_surface", "on_surface_variant"]),
+    ("thumbnail_bg_color", &["surface_container_high", "surface_variant"]),
+    ("thumbnail_fg_color", &["on_surface", "on_surface_variant"]),
+];
+
+/// `@define-color` rules setting libadwaita's named colors from `palette` in its mode.
+pub fn css(palette: &Palette) -> String {
+    let roles = palette.roles(palette.mode());
+    let mut css = String::from("/* libadwaita named colors written by chromash */\n");
+    for (name, candidates) in NAMED_COLORS {
+        if let Some(hex) = candidates.iter().find_map(|role| roles.get(*role)) {
+            css.push_str(&format!("@define-color {} {};\n", name, hex));
+        }
+    }
+    css
+}
+
+/// Imports `stylesheet` from `gtk_css`, GTK 4's user stylesheet, which applies over every
+/// app's own. Returns whether the file changed.
+pub fn install(stylesheet: &Path, gtk_css: &Path) -> Result<bool> {
+    let existing = fs::read_to_string(gtk_css).unwrap_or_default();
+    let block = format!("@import url(\"file://{}\");\n", stylesheet.display());
+    let merged = merge(&existing, &block);
+    if merged == existing {
+        return Ok(false);
+    }
+    if let Some(parent) = gtk_css.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    log::debug!("Importing {} from {}", stylesheet.display(), gtk_css.display());
+    write_atomic(gtk_css, merged)?;
+    Ok(true)
+}
+
+/// Replaces the managed block of `existing` with `block`, or adds it. CSS only allows
+/// `@import` before other rules, so a new block goes first.
+fn merge(existing: &str, block: &str) -> String {
+    let managed = format!("{}\n{}{}\n", BEGIN_MARKER, block, END_MARKER);
+    if let (Some(begin), Some(end)) = (existing.find(BEGIN_MARKER), existing.find(END_MARKER)) {
+        if begin < end {
+            let after = existing[end + END_MARKER.len()..].strip_prefix('\n').unwrap_or(&existing[end + END_MARKER.len()..]);
+            return format!("{}{}{}", &existing[..begin], managed, after);
+        }
+    }
+    if existing.trim().is_empty() {
+        managed
+    } else {
+        format!("{}\n{}", managed, existing)
+    }
+}
@@ -0,0 +1,113 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use serde::Serialize;
+
+use crate::{state, write_atomic, Config, CurrentTheme, Result};
+
+/// Number of applied themes kept in the history file.
+pub const HISTORY_LIMIT: usize = 500;
+
+/// Applied themes, oldest first. Lines that fail to parse are skipped.
+pub fn load() -> Result<Vec<CurrentTheme>> {
+    let path = Config::history_file();
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_to_string(&path)?
+        .lines()
+        .filter_map(|line| state::parse_theme(line).ok())
+        .collect())
+}
+
+/// Appends an applied theme, dropping the oldest entries beyond `HISTORY_LIMIT`.
+/// A newly applied theme also invalidates anything that was undone before it.
+pub fn record(theme: &CurrentTheme) -> Result<()> {
+    let mut entries = load()?;
+    entries.push(theme.clone());
+    save(&entries)?;
+    save_redo(&[])
+}
+
+/// Rewrites the history file with `entries`, oldest first.
+pub fn save(entries: &[CurrentTheme]) -> Result<()> {
+    let start = entries.len().saturating_sub(HISTORY_LIMIT);
+    let mut content = String::new();
+    for entry in &entries[start..] {
+        content.push_str(&serde_json::to_string(entry)?);
+        content.push('\n');
+    }
+    fs::create_dir_all(Config::config_dir())?;
+    write_atomic(&Config::history_file(), content)?;
+    Ok(())
+}
+
+/// Themes stepped back from with `undo`, most recently undone last.
+pub fn load_redo() -> Result<Vec<CurrentTheme>> {
+    let path = Config::redo_file();
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let stack: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    stack.into_iter()
+        .map(state::theme_from_value)
+        .collect()
+}
+
+pub fn save_redo(stack: &[CurrentTheme]) -> Result<()> {
+    let path = Config::redo_file();
+    if stack.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(());
+    }
+    let start = stack.len().saturating_sub(HISTORY_LIMIT);
+    write_atomic(&path, serde_json::to_string_pretty(&stack[start..])?)?;
+    Ok(())
+}
+
+/// Usage summary of the history for `chromash stats`.
+#[derive(Serialize)]
+pub struct Stats {
+    pub applied: usize,
+    pub since: Option<u64>,
+    /// Average seconds a theme stayed applied, excluding the current one.
+    pub average_lifetime: Option<u64>,
+    pub presets: Vec<(String, usize)>,
+    pub schemes: Vec<(String, usize)>,
+    pub modes: Vec<(String, usize)>,
+    pub wallpapers: Vec<(String, usize)>,
+}
+
+impl Stats {
+    pub fn from_entries(entries: &[CurrentTheme]) -> Self {
+        let lifetimes: Vec<u64> = entries.windows(2)
+            .map(|pair| pair[1].timestamp.saturating_sub(pair[0].timestamp))
+            .collect();
+        let average_lifetime = (!lifetimes.is_empty())
+            .then(|| lifetimes.iter().sum::<u64>() / lifetimes.len() as u64);
+        Self {
+            applied: entries.len(),
+            since: entries.first().map(|e| e.timestamp),
+            average_lifetime,
+            presets: ranked(entries.iter().filter_map(|e| e.preset_name.clone())),
+            schemes: ranked(entries.iter().filter_map(|e| e.scheme)
+                .map(|s| s.as_str().trim_start_matches("scheme-").to_string())),
+            modes: ranked(entries.iter().filter_map(|e| e.mode).map(|m| m.as_str().to_string())),
+            wallpapers: ranked(entries.iter()
+                .filter_map(|e| e.wallpaper().map(|w| w.display().to_string()))),
+        }
+    }
+}
+
+/// Counts occurrences, most frequent first and then alphabetically.
+fn ranked(values: impl Iterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by_key(|(_, count)| Reverse(*count));
+    ranked
+}
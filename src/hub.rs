@@ -0,0 +1,59 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+
+use crate::command::CommandRunner;
+use crate::download;
+use crate::{scratch_dir, ChromashApi, ChromashError, Result};
+
+pub const DEFAULT_INDEX: &str = "https://raw.githubusercontent.com/Youwes09/Chromash/main/hub/index.json";
+const MAX_INDEX_SIZE: u64 = 1024 * 1024;
+
+/// A shared preset listed in the hub index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HubEntry {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Download location of the `.chromash` archive.
+    pub url: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HubIndex {
+    presets: Vec<HubEntry>,
+}
+
+pub fn fetch_index(index_url: &str, runner: &dyn CommandRunner) -> Result<Vec<HubEntry>> {
+    let staging = scratch_dir("hub")?;
+    let index_file = staging.join("index.json");
+    let result = (|| {
+        download::fetch(index_url, &index_file, MAX_INDEX_SIZE, runner)?;
+        let index: HubIndex = serde_json::from_str(&fs::read_to_string(&index_file)?)?;
+        Ok(index.presets)
+    })();
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Entries whose name, description or tags contain `query` (case-insensitive).
+pub fn search(entries: Vec<HubEntry>, query: &str) -> Vec<HubEntry> {
+    let query = query.to_lowercase();
+    entries.into_iter()
+        .filter(|e| {
+            e.name.to_lowercase().contains(&query)
+                || e.description.to_lowercase().contains(&query)
+                || e.tags.iter().any(|t| t.to_lowercase().contains(&query))
+        })
+        .collect()
+}
+
+/// Downloads and installs a hub preset by name; returns the installed preset name.
+pub fn install(api: &ChromashApi, index_url: &str, name: &str) -> Result<String> {
+    let entry = fetch_index(index_url, api.runner().as_ref())?
+        .into_iter()
+        .find(|e| e.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| ChromashError::NotFound(format!("Hub preset: {}", name)))?;
+    api.import_preset_url(&entry.url, false)
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crate::config::CommandsConfig;
+use crate::command::CommandRunner;
+use crate::{sandbox, ChromashError, Result};
+
+/// Directory holding the sockets of the running Hyprland instance.
+pub fn instance_dir() -> Option<PathBuf> {
+    // hyprctl is a stub in the sandbox; the real sockets must not be reached around it
+    if sandbox::active() {
+        return None;
+    }
+    let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = env::var("XDG_RUNTIME_DIR")
+        .map(|d| PathBuf::from(d).join("hypr").join(&signature))
+        .ok()
+        .filter(|d| d.is_dir());
+    // Hyprland < 0.40 kept its sockets under /tmp
+    Some(runtime_dir.unwrap_or_else(|| PathBuf::from("/tmp/hypr").join(signature)))
+}
+
+/// Connects to the event socket and calls `on_event(name, data)` for every event line.
+/// Blocks until the socket is closed.
+pub fn listen_events(mut on_event: impl FnMut(&str, &str)) -> Result<()> {
+    let dir = instance_dir()
+        .ok_or_else(|| ChromashError::NotFound("Hyprland instance (HYPRLAND_INSTANCE_SIGNATURE)".into()))?;
+    let stream = UnixStream::connect(dir.join(".socket2.sock"))?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if let Some((event, data)) = line.split_once(">>") {
+            on_event(event, data);
+        }
+    }
+    Ok(())
+}
+
+/// Names of the connected monitors, from Hyprland's JSON monitor list.
+pub fn monitors(limits: &CommandsConfig, runner: &dyn CommandRunner) -> Result<Vec<String>> {
+    let json = match socket("socket.sock") {
+        Some(socket) => request(&socket, "j/monitors", limits)?,
+        // Outside the session's environment only hyprctl knows where the socket is
+        None => {
+            log::debug!("Running hyprctl -j monitors");
+            runner.run_with_retries(
+                Command::new("hyprctl").args(["-j", "monitors"]),
+                Duration::from_secs(limits.timeout),
+                limits.retries,
+            )?
+        }
+    };
+    let monitors: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+    Ok(monitors.iter().filter_map(|m| m["name"].as_str().map(str::to_string)).collect())
+}
+
+/// Sends `requests` (e.g. `preload <path>`) to hyprpaper's IPC socket, or in one
+/// `hyprctl --batch` call if the socket can't be found, and fails unless each is answered
+/// with `ok`.
+pub fn hyprpaper(requests: &[String], limits: &CommandsConfig, runner: &dyn CommandRunner) -> Result<()> {
+    let replies = match socket("hyprpaper.sock") {
+        Some(socket) => requests.iter()
+            .map(|r| request(&socket, r, limits))
+            .collect::<Result<Vec<_>>>()?
+            .join("\n"),
+        None => {
+            if requests.iter().any(|r| r.contains(';')) {
+                return Err(ChromashError::General("hyprctl --batch can't pass arguments containing ';'".into()));
+            }
+            let batch = requests.iter().map(|r| format!("hyprpaper {}", r)).collect::<Vec<_>>().join(" ; ");
+            log::debug!("Running hyprctl --batch {}", batch);
+            runner.run_with_retries(
+                Command::new("hyprctl").args(["--batch", &batch]),
+                Duration::from_secs(limits.timeout),
+                limits.retries,
+            )?
+        }
+    };
+    match replies.lines().map(str::trim).find(|line| !line.is_empty() && *line != "ok") {
+        Some(reply) => Err(ChromashError::Process(format!("hyprpaper: {}", reply))),
+        None => Ok(()),
+    }
+}
+
+/// Whether hyprpaper's IPC socket accepts connections yet.
+pub fn hyprpaper_listening() -> bool {
+    socket("hyprpaper.sock").is_some_and(|socket| UnixStream::connect(socket).is_ok())
+}
+
+/// `.<name>` in the instance directory, if it exists.
+fn socket(name: &str) -> Option<PathBuf> {
+    Some(instance_dir()?.join(format!(".{}", name))).filter(|path| path.exists())
+}
+
+/// Writes `message` to the request socket at `path` and reads the whole reply. Each
+/// connection carries one request; refused connections are retried like hyprctl's.
+fn request(path: &Path, message: &str, limits: &CommandsConfig) -> Result<String> {
+    log::debug!("Sending {:?} to {}", message, path.display());
+    let timeout = Duration::from_secs(limits.timeout);
+    let mut attempt = 0;
+    let mut stream = loop {
+        match UnixStream::connect(path) {
+            Ok(stream) => break stream,
+            Err(e) if attempt < limits.retries => {
+                attempt += 1;
+                log::debug!("Connecting to {} failed: {}; retrying ({}/{})", path.display(), e, attempt, limits.retries);
+                std::thread::sleep(Duration::from_millis(200 * attempt as u64));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(message.as_bytes())?;
+    let _ = stream.shutdown(Shutdown::Write);
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply)
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use crate::config::HyprpaperConfig;
+
+/// Lines between these markers in `hyprpaper.conf` belong to chromash; the rest is the user's.
+pub const BEGIN_MARKER: &str = "# >>> chromash managed wallpapers (edits here are overwritten) >>>";
+pub const END_MARKER: &str = "# <<< chromash managed wallpapers <<<";
+
+/// Header of the files chromash wrote before it kept a marked block.
+const LEGACY_HEADER: &str = "# hyprpaper configuration - managed by chromash";
+
+/// Replaces the managed block of `existing` with `block`, keeping every other setting
+/// except those `block` sets itself, such as `splash` from chromash's config. A config
+/// without markers has its `preload` and `wallpaper` entries removed, since they would
+/// compete with chromash's, and gets the block appended.
+pub fn merge(existing: &str, block: &str) -> String {
+    let managed = format!("{}\n{}{}\n", BEGIN_MARKER, block, END_MARKER);
+    let owned: Vec<&str> = block.lines().map(key).filter(|k| !k.is_empty()).collect();
+    let unowned = |text: &str| -> String {
+        text.split_inclusive('\n').filter(|line| !owned.contains(&key(line))).collect()
+    };
+    if let (Some(begin), Some(end)) = (existing.find(BEGIN_MARKER), existing.find(END_MARKER)) {
+        if begin < end {
+            let after = existing[end + END_MARKER.len()..].strip_prefix('\n').unwrap_or(&existing[end + END_MARKER.len()..]);
+            return format!("{}{}{}", unowned(&existing[..begin]), managed, unowned(after));
+        }
+    }
+
+    let mut kept = String::new();
+    let mut in_wallpaper_block = false;
+    for line in existing.lines() {
+        let trimmed = line.trim();
+        if in_wallpaper_block {
+            in_wallpaper_block = !trimmed.starts_with('}');
+            continue;
+        }
+        let key = key(trimmed);
+        // Before the owned keys, which always include `wallpaper`, so the block's body goes too
+        if key == "wallpaper" && trimmed.ends_with('{') {
+            in_wallpaper_block = true;
+            continue;
+        }
+        if trimmed == LEGACY_HEADER || key == "preload" || (key == "wallpaper" && trimmed.contains('=')) || owned.contains(&key) {
+            continue;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    let kept = kept.trim_end();
+    if kept.is_empty() {
+        managed
+    } else {
+        format!("{}\n\n{}", kept, managed)
+    }
+}
+
+/// The setting a config line assigns or opens, e.g. `splash` for `splash = false`.
+fn key(line: &str) -> &str {
+    line.split(['=', '{']).next().unwrap_or_default().trim()
+}
+
+/// Lines of the managed block for the settings chromash's config sets, in the order
+/// hyprpaper documents them; unset ones are left to the user.
+pub fn settings(config: &HyprpaperConfig) -> String {
+    let mut lines = String::new();
+    if let Some(splash) = config.splash {
+        lines.push_str(&format!("splash = {}\n", splash));
+    }
+    if let Some(offset) = config.splash_offset {
+        lines.push_str(&format!("splash_offset = {}\n", offset));
+    }
+    if let Some(ipc) = config.ipc {
+        lines.push_str(&format!("ipc = {}\n", if ipc { "on" } else { "off" }));
+    }
+    if !lines.is_empty() {
+        lines.push('\n');
+    }
+    lines
+}
+
+/// The setting lines in the managed block of `config`, to tell whether they changed.
+pub fn managed_settings(config: &str) -> Vec<&str> {
+    let Some(start) = config.find(BEGIN_MARKER) else { return Vec::new() };
+    let block = &config[start + BEGIN_MARKER.len()..];
+    block[..block.find(END_MARKER).unwrap_or(block.len())].lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !matches!(key(line), "preload" | "wallpaper"))
+        .collect()
+}
+
+/// Whether `config` leaves hyprpaper's IPC socket on (the default).
+pub fn ipc_enabled(config: &str) -> bool {
+    !config.lines().any(|line| {
+        let Some((key, value)) = line.split_once('=') else { return false };
+        key.trim() == "ipc" && matches!(value.trim(), "off" | "false" | "0" | "no")
+    })
+}
+
+/// Wallpapers in the managed block: one per monitor, plus a fallback (`wallpaper = ,path`)
+/// for monitors without their own, such as ones plugged in later.
+#[derive(Debug, Clone, Default)]
+pub struct Assignments {
+    pub monitors: BTreeMap<String, String>,
+    pub fallback: Option<String>,
+}
+
+impl Assignments {
+    /// Reads the managed block of `config`; anything outside it is left to the user.
+    pub fn parse(config: &str) -> Self {
+        let mut assignments = Self::default();
+        let Some(start) = config.find(BEGIN_MARKER) else { return assignments };
+        let block = &config[start..];
+        let block = &block[..block.find(END_MARKER).unwrap_or(block.len())];
+        for line in block.lines() {
+            let Some((key, value)) = line.split_once('=') else { continue };
+            if key.trim() != "wallpaper" {
+                continue;
+            }
+            match value.trim().split_once(',') {
+                Some(("", path)) => assignments.fallback = Some(path.trim().to_string()),
+                Some((monitor, path)) => {
+                    assignments.monitors.insert(monitor.trim().to_string(), path.trim().to_string());
+                }
+                None => {}
+            }
+        }
+        assignments
+    }
+
+    /// Distinct wallpaper paths in use.
+    pub fn paths(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = self.monitors.values().chain(&self.fallback).map(String::as_str).collect();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// hyprpaper IPC requests that show these wallpapers on a running hyprpaper and drop the
+    /// ones no longer shown. The fallback goes first so monitor-specific ones override it.
+    pub fn ipc_requests(&self) -> Vec<String> {
+        let mut requests: Vec<String> = self.paths().iter().map(|path| format!("preload {}", path)).collect();
+        if let Some(fallback) = &self.fallback {
+            requests.push(format!("wallpaper ,{}", fallback));
+        }
+        for (monitor, path) in &self.monitors {
+            requests.push(format!("wallpaper {},{}", monitor, path));
+        }
+        requests.push("unload unused".into());
+        requests
+    }
+
+    /// Contents of the managed block: a `preload` per wallpaper, then the assignments.
+    pub fn block(&self) -> String {
+        let mut block: String = self.paths().iter().map(|path| format!("preload = {}\n", path)).collect();
+        block.push('\n');
+        for (monitor, path) in &self.monitors {
+            block.push_str(&format!("wallpaper = {},{}\n", monitor, path));
+        }
+        if let Some(fallback) = &self.fallback {
+            block.push_str(&format!("wallpaper = ,{}\n", fallback));
+        }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn managed(block: &str) -> String {
+        format!("{}\n{}{}\n", BEGIN_MARKER, block, END_MARKER)
+    }
+
+    #[test]
+    fn merge_replaces_unmarked_wallpapers() {
+        let existing = "\
+# hyprpaper configuration - managed by chromash
+preload = /old.png
+wallpaper = DP-1,/old.png
+splash = true
+wallpaper {
+    monitor = DP-1
+    path = /old.png
+}
+# my comment
+ipc = on
+";
+        let block = "splash = false\n\npreload = /new.png\n\nwallpaper = ,/new.png\n";
+        assert_eq!(merge(existing, block), format!("# my comment\nipc = on\n\n{}", managed(block)));
+        assert_eq!(merge("", block), managed(block));
+    }
+
+    #[test]
+    fn merge_keeps_user_lines_around_markers() {
+        let existing = format!("ipc = off\nsplash = true\n{}splash_offset = 2\n# after\n",
+            managed("preload = /old.png\nwallpaper = ,/old.png\n"));
+        let block = "splash = false\n\npreload = /new.png\nwallpaper = ,/new.png\n";
+        let merged = merge(&existing, block);
+        assert_eq!(merged, format!("ipc = off\n{}splash_offset = 2\n# after\n", managed(block)));
+        assert_eq!(merge(&merged, block), merged);
+        assert_eq!(managed_settings(&merged), ["splash = false"]);
+        assert!(!ipc_enabled(&merged));
+    }
+
+    #[test]
+    fn assignments_round_trip() {
+        let mut assignments = Assignments::default();
+        assignments.monitors.insert("DP-1".into(), "/a.png".into());
+        assignments.monitors.insert("HDMI-A-1".into(), "/b.png".into());
+        assignments.fallback = Some("/a.png".into());
+        // Wallpapers outside the managed block are the user's business
+        let config = merge("wallpaper = DP-2,/mine.png\n", &assignments.block());
+        let parsed = Assignments::parse(&format!("wallpaper = DP-2,/mine.png\n{}", config));
+        assert_eq!(parsed.monitors, assignments.monitors);
+        assert_eq!(parsed.fallback.as_deref(), Some("/a.png"));
+        assert_eq!(parsed.block(), assignments.block());
+        assert_eq!(parsed.ipc_requests(), [
+            "preload /a.png", "preload /b.png", "wallpaper ,/a.png",
+            "wallpaper DP-1,/a.png", "wallpaper HDMI-A-1,/b.png", "unload unused",
+        ]);
+        assert!(Assignments::parse("wallpaper = ,/a.png\n").fallback.is_none());
+    }
+}
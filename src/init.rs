@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::command::CommandRunner;
+use crate::doctor::find_executable;
+use crate::{confirm, ChromashApi, ColorMode, Config, Result, SchemeType, ThemeOptions};
+
+const SCHEMES: &str = "content, expressive, fidelity, fruit-salad, monochrome, neutral, rainbow, tonal-spot";
+
+/// Interactive first-run setup: writes `config.toml`, creates the directories chromash
+/// uses, optionally installs the daemon as a systemd user service and applies a test theme.
+pub fn run() -> Result<()> {
+    println!("Chromash setup\n");
+    let config_file = Config::config_file();
+    if config_file.exists() && !confirm(&format!("{} already exists. Overwrite it?", config_file.display())) {
+        println!("Keeping the existing configuration");
+        return Ok(());
+    }
+
+    let default_dir = Config::wallpaper_dir().display().to_string();
+    let wallpaper_dir = prompt("Wallpaper directory", &default_dir);
+    let scheme = loop {
+        let answer = prompt(&format!("Default scheme ({}, or auto)", SCHEMES), "auto");
+        if answer == "auto" || SchemeType::from_str(&answer).is_some() {
+            break answer;
+        }
+        println!("Unknown scheme: {}", answer);
+    };
+    let mode = loop {
+        let answer = prompt("Default mode (light, dark, or auto)", "auto");
+        if answer == "auto" || ColorMode::from_str(&answer).is_some() {
+            break answer;
+        }
+        println!("Unknown mode: {}", answer);
+    };
+    match find_executable("hyprpaper") {
+        Some(path) => println!("Wallpaper backend: hyprpaper ({})", path.display()),
+        None => println!("Wallpaper backend: hyprpaper was not found; install it before applying wallpapers"),
+    }
+
+    let mut content = String::from("# Created by chromash init\n");
+    if wallpaper_dir != default_dir {
+        content.push_str(&format!("wallpaper_dir = {}\n", toml_string(&wallpaper_dir)));
+    }
+    if scheme != "auto" {
+        content.push_str(&format!("scheme = {}\n", toml_string(&scheme)));
+    }
+    if mode != "auto" {
+        content.push_str(&format!("mode = {}\n", toml_string(&mode)));
+    }
+    fs::create_dir_all(Config::config_dir())?;
+    fs::write(&config_file, content)?;
+    println!("\nWrote {}", config_file.display());
+    // Read back, so directories come from the config rather than the defaults
+    let mut api = ChromashApi::new()?;
+    for dir in [api.presets_dir(), api.hyprpaper_dir(), api.wallpaper_dir()] {
+        fs::create_dir_all(&dir)?;
+    }
+
+    if confirm("Install and start the chromash daemon as a systemd user service?") {
+        install_service(api.runner().as_ref())?;
+    }
+    if confirm("Apply a test theme now?") {
+        let applied = match api.first_wallpaper()? {
+            Some(wallpaper) => api.apply_wallpaper(wallpaper.to_str(), true, ThemeOptions::default()),
+            None => api.apply_color("6750a4", ThemeOptions::default()),
+        };
+        match applied {
+            Ok(_) => println!("Test theme applied; run `chromash undo` to go back"),
+            Err(e) => println!("Test apply failed: {}\nRun `chromash doctor` to diagnose", e),
+        }
+    }
+    Ok(())
+}
+
+fn prompt(question: &str, default: &str) -> String {
+    print!("{} [{}]: ", question, default);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    let _ = std::io::stdin().read_line(&mut answer);
+    match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    }
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn install_service(runner: &dyn CommandRunner) -> Result<()> {
+    let exe = env::current_exe()?;
+    let unit_dir = Config::home().join(".config/systemd/user");
+    let unit = unit_dir.join("chromash.service");
+    fs::create_dir_all(&unit_dir)?;
+    fs::write(&unit, service_unit(&exe))?;
+    println!("Wrote {}", unit.display());
+
+    runner.status(Command::new("systemctl").args(["--user", "enable", "--now", "chromash.service"]))?;
+    println!("Enabled chromash.service");
+    Ok(())
+}
+
+fn service_unit(exe: &Path) -> String {
+    // Keep the daemon on the same config root and backend as the one being set up
+    let environment: String = ["CHROMASH_CONFIG_DIR", "CHROMASH_BACKEND"].iter()
+        .filter_map(|name| env::var(name).ok().map(|value| format!("Environment={}={}\n", name, value)))
+        .collect();
+    format!(
+        "[Unit]\n\
+         Description=Chromash theme daemon\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\n\
+         [Service]\n\
+         {}ExecStart={} daemon\n\
+         Restart=on-failure\n\n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        environment,
+        exe.display()
+    )
+}
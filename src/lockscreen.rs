@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use image::{imageops::FilterType, GenericImageView, ImageReader, Rgb};
+
+use crate::config::LockscreenConfig;
+use crate::{write_atomic, ChromashError, Config, Result};
+
+/// Lines between these markers in the lock screen configs belong to chromash.
+const BEGIN_MARKER: &str = "# >>> chromash lockscreen (edits here are overwritten) >>>";
+const END_MARKER: &str = "# <<< chromash lockscreen <<<";
+
+/// Width the blur works at; wider wallpapers are blurred scaled down, then scaled back up,
+/// which looks the same and takes a fraction of the time.
+const WORK_WIDTH: u32 = 1920;
+
+/// Renders `wallpaper` blurred, dimmed and with `accent` rising from the bottom edge.
+pub fn render(wallpaper: &Path, accent: [u8; 3], config: &LockscreenConfig, output: &Path) -> Result<()> {
+    let img = ImageReader::open(wallpaper)?.with_guessed_format()?.decode()
+        .map_err(|e| ChromashError::General(format!("Failed to decode: {}", e)))?;
+    let (width, height) = img.dimensions();
+    let work = if width > WORK_WIDTH {
+        img.resize(WORK_WIDTH, u32::MAX, FilterType::Triangle)
+    } else {
+        img
+    };
+    let scale = work.width() as f32 / WORK_WIDTH as f32;
+    let mut blurred = work.fast_blur((config.blur * scale).max(0.0)).into_rgb8();
+    if blurred.width() != width {
+        blurred = image::imageops::resize(&blurred, width, height, FilterType::Triangle);
+    }
+
+    let dim = 1.0 - config.dim.clamp(0.0, 1.0);
+    let gradient = config.gradient.clamp(0.0, 1.0);
+    for (_, y, Rgb(pixel)) in blurred.enumerate_pixels_mut() {
+        // Quadratic, so the accent stays near the bottom edge and fades out well below the clock
+        let t = gradient * (y as f32 / height.max(1) as f32).powi(2);
+        for (channel, accent) in pixel.iter_mut().zip(accent) {
+            *channel = ((*channel as f32 * dim) * (1.0 - t) + accent as f32 * t).round() as u8;
+        }
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    blurred.save(output)
+        .map_err(|e| ChromashError::General(format!("Failed to write lock screen image: {}", e)))
+}
+
+/// Points the hyprlock and swaylock configs that exist at `image`. Returns the files changed.
+pub fn configure(image: &Path) -> Result<Vec<PathBuf>> {
+    let image = image.display();
+    let configs = [
+        (Config::home().join(".config/hypr/hyprlock.conf"), format!("background {{\n    monitor =\n    path = {}\n}}\n", image)),
+        (Config::home().join(".config/swaylock/config"), format!("image={}\n", image)),
+    ];
+    let mut changed = Vec::new();
+    for (path, block) in configs {
+        let Ok(existing) = fs::read_to_string(&path) else { continue };
+        let merged = merge(&existing, &block);
+        if merged != existing {
+            log::debug!("Pointing {} at the lock screen image", path.display());
+            write_atomic(&path, merged)?;
+            changed.push(path);
+        }
+    }
+    Ok(changed)
+}
+
+/// Replaces the managed block of `existing` with `block`, or appends it: both hyprlock and
+/// swaylock let later settings win, so the block goes last.
+fn merge(existing: &str, block: &str) -> String {
+    let managed = format!("{}\n{}{}\n", BEGIN_MARKER, block, END_MARKER);
+    if let (Some(begin), Some(end)) = (existing.find(BEGIN_MARKER), existing.find(END_MARKER)) {
+        if begin < end {
+            let after = existing[end + END_MARKER.len()..].strip_prefix('\n').unwrap_or(&existing[end + END_MARKER.len()..]);
+            return format!("{}{}{}", &existing[..begin], managed, after);
+        }
+    }
+    let kept = existing.trim_end();
+    if kept.is_empty() {
+        managed
+    } else {
+        format!("{}\n\n{}", kept, managed)
+    }
+}
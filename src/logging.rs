@@ -0,0 +1,77 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::progress;
+
+/// Logs to stderr at the level chosen on the command line and, optionally, to a
+/// file at debug level so failed applies can be investigated afterwards.
+struct Logger {
+    stderr_level: LevelFilter,
+}
+
+/// The debug log file, once `log_to_file` has opened it.
+static FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.stderr_level || (FILE.get().is_some() && metadata.level() <= Level::Debug)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.stderr_level {
+            progress::suspend(|| match record.level() {
+                Level::Error | Level::Warn => eprintln!("{}: {}", record.level().as_str().to_lowercase(), record.args()),
+                _ => eprintln!("{}", record.args()),
+            });
+        }
+        if let Some(file) = FILE.get() {
+            if record.level() <= Level::Debug {
+                if let Ok(mut file) = file.lock() {
+                    let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+                    let _ = writeln!(file, "{} {:<5} {}", now, record.level(), record.args());
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = FILE.get() {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// Maps `-v` counts to a stderr level: warnings by default, errors only when quiet.
+pub fn level_for(verbosity: i8) -> LevelFilter {
+    match verbosity {
+        i8::MIN..=-1 => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Installs the global logger, logging to stderr only until `log_to_file`.
+pub fn init(stderr_level: LevelFilter) {
+    if log::set_boxed_logger(Box::new(Logger { stderr_level })).is_ok() {
+        log::set_max_level(stderr_level);
+    }
+}
+
+/// Also appends debug output to `path`. Separate from `init`, since the config that asks
+/// for it is loaded after the first messages are logged.
+pub fn log_to_file(path: &Path) {
+    let file = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(path));
+    if let Ok(file) = file {
+        if FILE.set(Mutex::new(file)).is_ok() {
+            log::set_max_level(log::max_level().max(LevelFilter::Debug));
+        }
+    }
+}
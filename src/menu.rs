@@ -0,0 +1,83 @@
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use crate::{paths, ChromashApi, ChromashError, Result, ThemeOptions};
+
+const PRESET_PREFIX: &str = "preset: ";
+const WALLPAPER_PREFIX: &str = "wallpaper: ";
+
+/// A line in the menu and the image shown next to it.
+struct Entry {
+    label: String,
+    icon: Option<PathBuf>,
+}
+
+/// Prints presets and wallpapers for a dmenu-style picker. `format` is `rofi` (icons
+/// through rofi's `\0icon\x1f` row options), `wofi` (`img:` lines) or `plain`.
+pub fn list(api: &ChromashApi, format: &str) -> Result<()> {
+    if !["rofi", "wofi", "plain"].contains(&format) {
+        return Err(ChromashError::Usage(format!("Unknown menu format: {} (expected rofi, wofi or plain)", format)));
+    }
+    let mut stdout = io::stdout().lock();
+    for entry in entries(api)? {
+        let line = match (format, entry.icon) {
+            ("rofi", Some(icon)) => format!("{}\0icon\x1f{}", entry.label, icon.display()),
+            ("wofi", Some(icon)) => format!("img:{}:text:{}", icon.display(), entry.label),
+            _ => entry.label,
+        };
+        writeln!(stdout, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Reads the entry the picker printed from stdin and applies it. Returns what was applied,
+/// or `None` if the picker was dismissed without a selection.
+pub fn apply(api: &mut ChromashApi) -> Result<Option<String>> {
+    let mut selection = String::new();
+    io::stdin().lock().read_line(&mut selection)?;
+    let selection = selection.trim_end_matches(['\r', '\n']);
+    // wofi may echo the whole `img:<path>:text:<label>` line back
+    let selection = selection.split_once(":text:").map_or(selection, |(_, label)| label);
+    if selection.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(name) = selection.strip_prefix(PRESET_PREFIX) {
+        // There is no terminal to confirm on, so downloaded presets must be trusted first
+        if let Some(origin) = api.untrusted_origin(name)? {
+            return Err(ChromashError::General(format!(
+                "Preset {} was downloaded from {}; apply it once with `chromash preset apply {}`", name, origin, name)));
+        }
+        api.apply_preset(name, ThemeOptions::default())?;
+        Ok(Some(format!("Applied preset: {}", name)))
+    } else if let Some(wallpaper) = selection.strip_prefix(WALLPAPER_PREFIX) {
+        let path = api.wallpaper_dir().join(paths::decode(wallpaper));
+        api.apply_wallpaper(Some(&paths::encode(&path)), true, ThemeOptions::default())?;
+        Ok(Some(format!("Applied wallpaper: {}", wallpaper)))
+    } else {
+        Err(ChromashError::NotFound(format!("Menu entry: {}", selection)))
+    }
+}
+
+fn entries(api: &ChromashApi) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for preset in api.list_presets()? {
+        if preset.snapshot {
+            continue;
+        }
+        let icon = match api.preset_thumbnail(&preset.name)? {
+            Some(thumbnail) => Some(thumbnail),
+            None => preset.resolve_wallpaper(&api.get_preset_dir(&preset.name)?),
+        };
+        entries.push(Entry { label: format!("{}{}", PRESET_PREFIX, preset.name), icon });
+    }
+    let wallpaper_dir = api.wallpaper_dir();
+    for wallpaper in api.list_wallpapers() {
+        let relative = wallpaper.strip_prefix(&wallpaper_dir).unwrap_or(&wallpaper);
+        entries.push(Entry {
+            label: format!("{}{}", WALLPAPER_PREFIX, paths::encode(relative)),
+            icon: Some(wallpaper.clone()),
+        });
+    }
+    Ok(entries)
+}
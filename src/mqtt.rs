@@ -0,0 +1,40 @@
+use std::process::Command;
+
+use crate::command::{CommandRunner, Timed};
+use crate::config::MqttConfig;
+use crate::{CurrentTheme, Result};
+
+/// Publishes the theme as a retained JSON message on `<topic>/theme`.
+pub fn publish_theme(config: &MqttConfig, theme: &CurrentTheme, runner: &Timed) -> Result<()> {
+    let payload = serde_json::to_string(theme)?;
+    let topic = format!("{}/theme", config.topic);
+    log::debug!("Publishing theme to {} on {}:{}", topic, config.host, config.port);
+    runner.run(Command::new("mosquitto_pub")
+        .args(connection_args(config))
+        .args(["-r", "-t", &topic, "-m", &payload]))?;
+    Ok(())
+}
+
+/// Subscribes to `<topic>/set` and calls `on_command` with each received command line.
+/// Blocks until the subscription ends.
+pub fn listen_commands(config: &MqttConfig, runner: &dyn CommandRunner, mut on_command: impl FnMut(String)) -> Result<()> {
+    let topic = format!("{}/set", config.topic);
+    let mut command = Command::new("mosquitto_sub");
+    command.args(connection_args(config)).args(["-t", &topic]);
+    runner.stream(&mut command, &mut |line| {
+        if !line.trim().is_empty() {
+            on_command(line);
+        }
+    })
+}
+
+fn connection_args(config: &MqttConfig) -> Vec<String> {
+    let mut args = vec!["-h".to_string(), config.host.clone(), "-p".to_string(), config.port.to_string()];
+    if let Some(username) = &config.username {
+        args.extend(["-u".to_string(), username.clone()]);
+    }
+    if let Some(password) = &config.password {
+        args.extend(["-P".to_string(), password.clone()]);
+    }
+    args
+}
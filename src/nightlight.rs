@@ -0,0 +1,102 @@
+use std::process::Command;
+use std::time::Duration;
+
+use crate::backend::{self, Session};
+use crate::command::CommandRunner;
+use crate::config::{CommandsConfig, NightLightConfig};
+use crate::{doctor, ChromashError, ColorMode, Result};
+
+/// The program that sets the screen's color temperature.
+#[derive(Debug, Clone, Copy)]
+pub enum Program {
+    Hyprsunset,
+    Gammastep,
+}
+
+impl Program {
+    pub const NAMES: &'static [&'static str] = &["hyprsunset", "gammastep"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Hyprsunset => "hyprsunset",
+            Self::Gammastep => "gammastep",
+        }
+    }
+
+    /// `configured`, then whichever is already running, then whichever is installed,
+    /// preferring hyprsunset under Hyprland.
+    pub fn select(configured: Option<&str>) -> Result<Self> {
+        if let Some(name) = configured {
+            return match name.to_lowercase().as_str() {
+                "hyprsunset" => Ok(Self::Hyprsunset),
+                "gammastep" => Ok(Self::Gammastep),
+                _ => Err(crate::unknown_value("night light program", name, Self::NAMES)),
+            };
+        }
+        if backend::is_running("hyprsunset") {
+            return Ok(Self::Hyprsunset);
+        }
+        if backend::is_running("gammastep") {
+            return Ok(Self::Gammastep);
+        }
+        let installed = |name| doctor::find_executable(name).is_some();
+        match Session::detect() {
+            Session::Hyprland if installed("hyprsunset") => Ok(Self::Hyprsunset),
+            _ if installed("gammastep") => Ok(Self::Gammastep),
+            _ if installed("hyprsunset") => Ok(Self::Hyprsunset),
+            _ => Err(ChromashError::NotFound("hyprsunset or gammastep for [night_light]".into())),
+        }
+    }
+
+    /// Sets the screen to `kelvin`, or back to its normal colors for `None`.
+    fn set(self, kelvin: Option<u32>, limits: &CommandsConfig, runner: &dyn CommandRunner) -> Result<()> {
+        let timeout = Duration::from_secs(limits.timeout);
+        match self {
+            // hyprsunset takes changes over Hyprland's IPC while it runs
+            Self::Hyprsunset if backend::is_running("hyprsunset") => {
+                let mut command = Command::new("hyprctl");
+                match kelvin {
+                    Some(kelvin) => command.args(["hyprsunset", "temperature", &kelvin.to_string()]),
+                    None => command.args(["hyprsunset", "identity"]),
+                };
+                log::debug!("Running {:?}", command);
+                runner.run_with_retries(&mut command, timeout, limits.retries).map(|_| ())
+            }
+            Self::Hyprsunset => match kelvin {
+                Some(kelvin) => spawn(Command::new("hyprsunset").args(["-t", &kelvin.to_string()]), runner),
+                None => Ok(()),
+            },
+            Self::Gammastep => {
+                // A running gammastep would keep applying its own schedule over ours
+                let _ = runner.run(Command::new("pkill").args(["-x", "gammastep"]), timeout);
+                match kelvin {
+                    // Stays running on Wayland, where gamma resets when the client exits
+                    Some(kelvin) => spawn(Command::new("gammastep").args(["-P", "-O", &kelvin.to_string()]), runner),
+                    None => runner.run(Command::new("gammastep").arg("-x"), timeout).map(|_| ()),
+                }
+            }
+        }
+    }
+}
+
+fn spawn(command: &mut Command, runner: &dyn CommandRunner) -> Result<()> {
+    log::debug!("Starting {:?}", command);
+    runner.spawn(command)
+}
+
+/// Moves the screen temperature along with a switch to `mode`: warmer for dark themes,
+/// normal (or `day_temperature`) for light ones. Themes in the same mode as `previous`
+/// leave it alone, unless a dark theme finds the program not running.
+pub fn sync(config: &NightLightConfig, mode: ColorMode, previous: Option<ColorMode>, limits: &CommandsConfig, runner: &dyn CommandRunner) -> Result<()> {
+    let program = Program::select(config.program.as_deref())?;
+    let kelvin = match mode {
+        ColorMode::Dark => Some(config.temperature),
+        ColorMode::Light => config.day_temperature,
+    };
+    let switched = previous.is_none_or(|previous| previous.as_str() != mode.as_str());
+    if !switched && (kelvin.is_none() || backend::is_running(program.as_str())) {
+        return Ok(());
+    }
+    log::info!("Setting {} to {}", program.as_str(), kelvin.map_or("normal colors".to_string(), |k| format!("{}K", k)));
+    program.set(kelvin, limits, runner)
+}
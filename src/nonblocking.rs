@@ -0,0 +1,84 @@
+//! Async access to [`ChromashApi`] for tokio applications, behind the `async` feature.
+//!
+//! Rather than `async` twins of each method (an `apply_wallpaper_async` and so on), the
+//! whole API moves into an [`AsyncApi`] with [`ChromashApi::into_async`]. An apply reads
+//! and writes the API throughout, so a `&mut ChromashApi` borrowed by one call could not be
+//! sent to tokio's blocking pool; owning it behind a mutex can. Each call then runs
+//! entirely on that pool, image decoding and external commands included, which keeps them
+//! off the caller's event loop without an async version of every step.
+//!
+//! ```no_run
+//! # async fn example() -> chromash::Result<()> {
+//! use chromash::{ChromashApi, ThemeOptions};
+//!
+//! let api = ChromashApi::new()?.into_async();
+//! api.apply_wallpaper(Some("/home/me/Pictures/Wallpapers/forest.png".into()), true, ThemeOptions::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::{paths, ChromashApi, ChromashError, CurrentTheme, Result, ThemeOptions};
+
+/// A `ChromashApi` for tokio applications such as a GUI or a long-running daemon. Applying
+/// a theme decodes images, runs the generator and waits on hyprctl for seconds; each call
+/// here does that on tokio's blocking pool, so the caller's event loop keeps running.
+///
+/// Clones share the same API, and calls on it run one at a time.
+#[derive(Clone)]
+pub struct AsyncApi {
+    api: Arc<Mutex<ChromashApi>>,
+}
+
+impl AsyncApi {
+    pub fn new(api: ChromashApi) -> Self {
+        Self { api: Arc::new(Mutex::new(api)) }
+    }
+
+    /// Runs any `ChromashApi` call on the blocking pool.
+    pub async fn run<T: Send + 'static>(&self, f: impl FnOnce(&mut ChromashApi) -> Result<T> + Send + 'static) -> Result<T> {
+        let api = Arc::clone(&self.api);
+        tokio::task::spawn_blocking(move || {
+            let mut api = api.lock().map_err(|_| ChromashError::General("An earlier call panicked while applying".to_string()))?;
+            f(&mut api)
+        })
+        .await
+        .map_err(|e| ChromashError::General(format!("Applying stopped: {}", e)))?
+    }
+
+    /// See `ChromashApi::apply_color`.
+    pub async fn apply_color(&self, color: impl Into<String>, options: ThemeOptions) -> Result<bool> {
+        let color = color.into();
+        self.run(move |api| api.apply_color(&color, options)).await
+    }
+
+    /// See `ChromashApi::apply_wallpaper`.
+    pub async fn apply_wallpaper(&self, path: Option<PathBuf>, extract_colors: bool, options: ThemeOptions) -> Result<bool> {
+        let path = path.map(paths::encode);
+        self.run(move |api| api.apply_wallpaper(path.as_deref(), extract_colors, options)).await
+    }
+
+    /// See `ChromashApi::apply_preset`.
+    pub async fn apply_preset(&self, name: impl Into<String>, overrides: ThemeOptions) -> Result<bool> {
+        let name = name.into();
+        self.run(move |api| api.apply_preset(&name, overrides)).await
+    }
+
+    /// Decodes `wallpaper` and caches its colors ahead of time, see `ChromashApi::precache`.
+    pub async fn precache(&self, wallpaper: PathBuf) -> Result<()> {
+        self.run(move |api| api.precache(&wallpaper)).await
+    }
+
+    pub async fn load_current_theme(&self) -> Result<Option<CurrentTheme>> {
+        self.run(|api| api.load_current_theme()).await
+    }
+}
+
+impl ChromashApi {
+    /// Moves the API to an `AsyncApi` for use from tokio.
+    pub fn into_async(self) -> AsyncApi {
+        AsyncApi::new(self)
+    }
+}
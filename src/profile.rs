@@ -0,0 +1,36 @@
+use std::env;
+use std::fs;
+
+use crate::{write_atomic, Config, Result};
+
+/// The active profile: `$CHROMASH_PROFILE`, then the one last switched to.
+pub fn active() -> Option<String> {
+    env::var("CHROMASH_PROFILE").ok()
+        .or_else(|| fs::read_to_string(Config::profile_file()).ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Makes `name` the active profile, or goes back to the base config for `None`.
+pub fn set(name: Option<&str>) -> Result<()> {
+    match name {
+        Some(name) => {
+            fs::create_dir_all(Config::config_dir())?;
+            write_atomic(&Config::profile_file(), format!("{}\n", name))
+        }
+        None if Config::profile_file().exists() => Ok(fs::remove_file(Config::profile_file())?),
+        None => Ok(()),
+    }
+}
+
+/// Overlays `overrides` on `base`: tables merge key by key, anything else is replaced.
+pub fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
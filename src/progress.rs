@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// All bars draw through this so log lines can be printed between redraws.
+static BARS: LazyLock<MultiProgress> = LazyLock::new(MultiProgress::new);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Progress is only shown on interactive terminals; off for `--json`, pipes and the TUI.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// A spinner for work of unknown length; call `finish_and_clear` when done.
+pub fn spinner(message: impl Into<String>) -> ProgressBar {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return ProgressBar::hidden();
+    }
+    let bar = BARS.add(ProgressBar::new_spinner());
+    bar.set_style(ProgressStyle::with_template("{spinner} {msg}").unwrap_or_else(|_| ProgressStyle::default_spinner()));
+    bar.set_message(message.into());
+    bar.enable_steady_tick(Duration::from_millis(100));
+    bar
+}
+
+/// A spinner that also shows a byte count, for downloads without a known size.
+pub fn bytes(message: impl Into<String>) -> ProgressBar {
+    let bar = spinner(message);
+    if let Ok(style) = ProgressStyle::with_template("{spinner} {msg} {bytes} ({bytes_per_sec})") {
+        bar.set_style(style);
+    }
+    bar
+}
+
+/// Runs `print` with bars hidden so its output doesn't interleave with them.
+pub fn suspend<R>(print: impl FnOnce() -> R) -> R {
+    BARS.suspend(print)
+}
@@ -0,0 +1,33 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::{write_atomic, Config, Result};
+
+/// Wallpapers drawn ahead of time for `wallpaper --random`, next first.
+pub fn load() -> Vec<PathBuf> {
+    fs::read_to_string(Config::queue_file())
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+pub fn save(queue: &[PathBuf]) -> Result<()> {
+    fs::create_dir_all(Config::state_dir())?;
+    let content: String = queue.iter().map(|path| format!("{}\n", path.display())).collect();
+    write_atomic(&Config::queue_file(), content)
+}
+
+/// Takes the next queued wallpaper that is still one of `wallpapers`, dropping any
+/// before it that were removed from the collection.
+pub fn pop(wallpapers: &[PathBuf]) -> Option<PathBuf> {
+    let mut queue = load();
+    let position = queue.iter().position(|path| wallpapers.contains(path))?;
+    let next = queue.remove(position);
+    queue.drain(..position);
+    if let Err(e) = save(&queue) {
+        log::warn!("Failed to update the wallpaper queue: {}", e);
+    }
+    Some(next)
+}
@@ -0,0 +1,504 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::palette::Palette;
+use crate::theme::Theme;
+use crate::{paths, state, unknown_value, ChromashApi, ChromashError, ColorMode, Config, CurrentTheme, Result, SchemeType, ThemeOptions};
+
+/// How often the current theme file is checked for changes to notify subscribers of.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Any chromash error, with its message; `data` has the error's `code()` and details.
+const APPLY_ERROR: i64 = -32000;
+/// `apply` of a downloaded preset nobody has confirmed yet; `chromash preset apply` asks.
+const UNTRUSTED_PRESET: i64 = -32001;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    pub method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+impl Request {
+    /// A request made inside chromash rather than read from the socket.
+    pub fn new(method: &str, params: Value) -> Self {
+        Self { jsonrpc: "2.0".into(), id: Some(Value::Null), method: method.into(), params }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RpcError>,
+}
+
+#[derive(Debug, Serialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl Response {
+    fn result(id: Value, result: Value) -> Self {
+        Self { jsonrpc: "2.0", id, result: Some(result), error: None }
+    }
+
+    fn error(id: Value, code: i64, message: impl Into<String>) -> Self {
+        Self { jsonrpc: "2.0", id, result: None, error: Some(RpcError { code, message: message.into(), data: None }) }
+    }
+
+    /// An `APPLY_ERROR` that `call` turns back into `error`.
+    fn failed(id: Value, error: &ChromashError) -> Self {
+        let detail = match error {
+            ChromashError::Io(e) => e.to_string(),
+            ChromashError::Json(e) => e.to_string(),
+            ChromashError::Toml(e) => e.to_string(),
+            ChromashError::Command(failure) => failure.to_string(),
+            ChromashError::Process(e) | ChromashError::NotFound(e) | ChromashError::Usage(e) | ChromashError::General(e) => e.clone(),
+            ChromashError::Locked | ChromashError::Busy => String::new(),
+        };
+        let mut data = json!({ "code": error.code(), "detail": detail });
+        if let ChromashError::Command(failure) = error {
+            data["failure"] = json!(failure);
+        }
+        Self { jsonrpc: "2.0", id, result: None, error: Some(RpcError { code: APPLY_ERROR, message: error.to_string(), data: Some(data) }) }
+    }
+    /// The result, or the error's message.
+    pub fn into_result(self) -> std::result::Result<Value, String> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(error.message),
+            (result, None) => Ok(result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// A request that needs the daemon's `ChromashApi`, with where to send the response.
+pub struct Call {
+    pub request: Request,
+    pub reply: Sender<Response>,
+}
+
+/// The current theme as sent to subscribers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThemeState {
+    pub theme: Option<CurrentTheme>,
+    pub palette: Option<Palette>,
+    pub wallpaper: Option<PathBuf>,
+}
+
+impl ThemeState {
+    /// Reads the theme from disk, so changes made by other chromash processes show up too.
+    pub fn load() -> Result<Self> {
+        let theme = match fs::read_to_string(Config::current_theme_file()) {
+            Ok(content) => Some(state::parse_theme(&content)?),
+            Err(_) => None,
+        };
+        let palette = Palette::load(&Config::palette_file())?;
+        let wallpaper = theme.as_ref().and_then(CurrentTheme::wallpaper);
+        Ok(Self { theme, palette, wallpaper })
+    }
+}
+
+/// Parameters of `apply` and `preview`: exactly one source plus optional overrides.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeParams {
+    wallpaper: Option<String>,
+    color: Option<String>,
+    preset: Option<String>,
+    mode: Option<String>,
+    scheme: Option<String>,
+    contrast: Option<f64>,
+    warmth: Option<f64>,
+    hue_shift: Option<f64>,
+    fast: bool,
+    monitor: Option<String>,
+    save_as: Option<String>,
+}
+
+impl ThemeParams {
+    fn options(&self) -> Result<ThemeOptions> {
+        let mode = self.mode.as_deref()
+            .map(|mode| ColorMode::from_str(mode).ok_or_else(|| unknown_value("mode", mode, &["light", "dark"])))
+            .transpose()?;
+        let scheme = self.scheme.as_deref()
+            .map(|scheme| SchemeType::from_str(scheme).ok_or_else(|| unknown_value("scheme", scheme, &crate::completions::SCHEMES)))
+            .transpose()?;
+        Ok(ThemeOptions {
+            mode,
+            scheme,
+            contrast: self.contrast.map(|c| c.clamp(-1.0, 1.0)),
+            warmth: self.warmth.map(|w| w.clamp(-50.0, 50.0)),
+            hue_shift: self.hue_shift,
+            fast: self.fast,
+            save_preset: self.save_as.is_some(),
+            preset_name: self.save_as.clone(),
+        })
+    }
+
+    fn theme(&self) -> Result<Theme> {
+        let mut theme = match (&self.wallpaper, &self.color, &self.preset) {
+            (Some(wallpaper), None, None) => Theme::from_wallpaper(paths::expand(wallpaper)),
+            (None, Some(color), None) => Theme::from_color(color),
+            (None, None, Some(preset)) => Theme::from_preset(preset),
+            _ => return Err(ChromashError::Usage("Give exactly one of wallpaper, color or preset".into())),
+        };
+        if let Some(monitor) = &self.monitor {
+            theme = theme.monitor(monitor);
+        }
+        Ok(theme.options(self.options()?))
+    }
+}
+
+/// Listens on `Config::socket_file()` for JSON-RPC 2.0 requests, one JSON object per line
+/// in each direction. Batches aren't supported. Methods:
+///
+/// - `apply` with `{"wallpaper": path}`, `{"color": hex}` or `{"preset": name}`, plus
+///   optional `mode`, `scheme`, `contrast`, `warmth`, `hue_shift`, `fast`, `monitor` and
+///   `save_as`. Returns what `Theme::apply` reports: the recorded theme, palette, wallpaper
+///   and files written.
+/// - `preview` with the same parameters: returns the palette the theme would get without
+///   applying anything.
+/// - `list` with an optional `{"kind": "presets" | "wallpapers"}` (default presets).
+/// - `state`: returns the current theme, palette and wallpaper.
+/// - `subscribe`: returns the current theme, palette and wallpaper, then sends a
+///   `theme_changed` notification with the same fields whenever the theme changes, until
+///   the client disconnects.
+///
+/// Errors use the standard codes, or -32000 with chromash's message when applying fails
+/// and -32001 for presets downloaded from elsewhere that haven't been applied before.
+/// Requests that need chromash itself are passed to `on_call` and answered in order.
+pub fn serve(on_call: impl Fn(Call) + Send + Sync + 'static) -> Result<()> {
+    let socket = Config::socket_file();
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(ChromashError::General(format!("Another chromash daemon is listening on {}", socket.display())));
+    }
+    if let Some(parent) = socket.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Left behind by a daemon that didn't shut down cleanly
+    let _ = fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)?;
+    log::info!("Listening for JSON-RPC on {}", socket.display());
+
+    let subscribers: Arc<Mutex<Vec<UnixStream>>> = Arc::default();
+    spawn_theme_watcher(Arc::clone(&subscribers));
+    let on_call = Arc::new(on_call);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Failed to accept JSON-RPC connection: {}", e);
+                    continue;
+                }
+            };
+            let on_call = Arc::clone(&on_call);
+            let subscribers = Arc::clone(&subscribers);
+            thread::spawn(move || {
+                if let Err(e) = serve_connection(stream, &*on_call, &subscribers) {
+                    log::debug!("JSON-RPC connection closed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn serve_connection(stream: UnixStream, on_call: &dyn Fn(Call), subscribers: &Mutex<Vec<UnixStream>>) -> Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut subscribed = false;
+    for line in BufReader::new(stream.try_clone()?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Value>(&line) {
+            Ok(value) => value,
+            Err(e) => {
+                send(&mut writer, &Response::error(Value::Null, PARSE_ERROR, e.to_string()))?;
+                continue;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let request = match serde_json::from_value::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                send(&mut writer, &Response::error(id, INVALID_REQUEST, "jsonrpc must be \"2.0\""))?;
+                continue;
+            }
+            Err(e) => {
+                send(&mut writer, &Response::error(id, INVALID_REQUEST, e.to_string()))?;
+                continue;
+            }
+        };
+        let notification = request.id.is_none();
+
+        if request.method == "subscribe" {
+            let response = match ThemeState::load().and_then(to_value) {
+                Ok(state) => Response::result(id, state),
+                Err(e) => Response::failed(id, &e),
+            };
+            // Holding the lock keeps notifications from arriving before the current state
+            let mut subscribers = subscribers.lock().unwrap_or_else(|e| e.into_inner());
+            if !notification {
+                send(&mut writer, &response)?;
+            }
+            subscribers.push(stream.try_clone()?);
+            subscribed = true;
+            continue;
+        }
+        let (reply, response) = mpsc::channel();
+        on_call(Call { request, reply });
+        let response = response.recv().map_err(|_| ChromashError::General("The daemon stopped".into()))?;
+        if !notification {
+            // Once subscribed, the watcher writes to this connection too; don't interleave
+            let _guard = subscribed.then(|| subscribers.lock().unwrap_or_else(|e| e.into_inner()));
+            send(&mut writer, &response)?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers a request that needs chromash; run on the daemon's thread.
+pub fn handle(api: &mut ChromashApi, request: &Request) -> Response {
+    let id = request.id.clone().unwrap_or(Value::Null);
+    if let Some((name, origin)) = untrusted_preset(api, request) {
+        return Response::error(id, UNTRUSTED_PRESET, format!("Preset {} was downloaded from {}; apply it with chromash first", name, origin));
+    }
+    if let Some(error) = missing_preset(api, request) {
+        return Response::failed(id, &error);
+    }
+    let result = match request.method.as_str() {
+        "apply" => params::<ThemeParams>(&request.params)
+            .map(|params| params.theme().and_then(|theme| theme.apply(api)).and_then(to_value)),
+        "state" => Ok(ThemeState::load().and_then(to_value)),
+        "preview" => params::<ThemeParams>(&request.params)
+            .map(|params| preview(api, &params).and_then(to_value)),
+        "list" => params::<ListParams>(&request.params)
+            .map(|params| match params.kind.as_deref().unwrap_or("presets") {
+                "presets" => api.list_presets().and_then(to_value),
+                "wallpapers" => to_value(api.list_wallpapers()),
+                kind => Err(unknown_value("kind", kind, &["presets", "wallpapers"])),
+            }),
+        method => return Response::error(id, METHOD_NOT_FOUND, format!("Unknown method: {}", method)),
+    };
+    match result {
+        Ok(Ok(result)) => Response::result(id, result),
+        Ok(Err(e)) => Response::failed(id, &e),
+        Err(e) => Response::error(id, INVALID_PARAMS, e),
+    }
+}
+
+/// The name and origin of the preset an `apply` request asks for, if it was downloaded and
+/// not confirmed yet; there is nobody here to ask.
+fn untrusted_preset(api: &ChromashApi, request: &Request) -> Option<(String, String)> {
+    if request.method != "apply" {
+        return None;
+    }
+    let name = params::<ThemeParams>(&request.params).ok()?.preset?;
+    let origin = api.untrusted_origin(&name).ok()??;
+    Some((name, origin))
+}
+
+/// The error for an `apply` of a preset that doesn't exist, suggesting the closest name
+/// like `chromash preset apply` does.
+fn missing_preset(api: &ChromashApi, request: &Request) -> Option<ChromashError> {
+    if request.method != "apply" {
+        return None;
+    }
+    let name = params::<ThemeParams>(&request.params).ok()?.preset?;
+    if api.get_preset_dir(&name).is_ok() {
+        return None;
+    }
+    Some(match api.closest_preset(&name).ok()? {
+        Some(closest) => ChromashError::NotFound(format!("Preset: {} (did you mean {}?)", name, closest)),
+        None => ChromashError::NotFound(format!("Preset: {}", name)),
+    })
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ListParams {
+    kind: Option<String>,
+}
+
+/// Parses `params`, treating a missing one as empty.
+fn params<T: for<'de> Deserialize<'de> + Default>(params: &Value) -> std::result::Result<T, String> {
+    match params {
+        Value::Null => Ok(T::default()),
+        params => serde_json::from_value(params.clone()).map_err(|e| e.to_string()),
+    }
+}
+
+fn to_value(value: impl Serialize) -> Result<Value> {
+    Ok(serde_json::to_value(value)?)
+}
+
+fn preview(api: &ChromashApi, params: &ThemeParams) -> Result<Palette> {
+    let options = params.options()?;
+    match (&params.wallpaper, &params.color, &params.preset) {
+        (Some(wallpaper), None, None) => {
+            api.preview_palette(&format!("wallpaper_{}", paths::encode(paths::expand(wallpaper))), options)
+        }
+        (None, Some(color), None) => api.preview_palette(&format!("color_{}", color), options),
+        (None, None, Some(preset)) => {
+            let (preset_dir, metadata) = api.load_preset(preset)?;
+            let overridden = options.mode.is_some() || options.scheme.is_some() || options.contrast.is_some()
+                || options.warmth.is_some() || options.hue_shift.is_some();
+            if !overridden {
+                if let Some(palette) = Palette::load(&preset_dir.join("palette.json"))? {
+                    return Ok(palette);
+                }
+            }
+            let source = metadata.source.clone()
+                .or_else(|| metadata.wallpaper.as_ref().map(|w| format!("wallpaper_{}", paths::encode(w))))
+                .ok_or_else(|| ChromashError::NotFound(format!("Source for preset: {}", preset)))?;
+            let options = ThemeOptions {
+                mode: options.mode.or(metadata.mode),
+                scheme: options.scheme.or(metadata.scheme),
+                contrast: options.contrast.or(metadata.contrast),
+                warmth: options.warmth.or(metadata.warmth),
+                hue_shift: options.hue_shift.or(metadata.hue_shift),
+                ..options
+            };
+            api.preview_palette(&source, options)
+        }
+        _ => Err(ChromashError::Usage("Give exactly one of wallpaper, color or preset".into())),
+    }
+}
+
+/// Sends `theme_changed` to every subscriber when the theme changes; subscribers that have
+/// gone away are dropped.
+fn spawn_theme_watcher(subscribers: Arc<Mutex<Vec<UnixStream>>>) {
+    thread::spawn(move || {
+        watch_theme(|state| {
+            let notification = json!({ "jsonrpc": "2.0", "method": "theme_changed", "params": state });
+            let mut subscribers = subscribers.lock().unwrap_or_else(|e| e.into_inner());
+            subscribers.retain_mut(|stream| send(stream, &notification).is_ok());
+            true
+        });
+    });
+}
+
+/// Calls `on_change` whenever the current theme file changes, whoever changed it, until it
+/// returns false.
+pub fn watch_theme(mut on_change: impl FnMut(ThemeState) -> bool) {
+    let modified = || fs::metadata(Config::current_theme_file()).and_then(|m| m.modified()).ok();
+    let mut last: Option<SystemTime> = modified();
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let current = modified();
+        if current == last {
+            continue;
+        }
+        last = current;
+        match ThemeState::load() {
+            Ok(state) => {
+                if !on_change(state) {
+                    return;
+                }
+            }
+            Err(e) => log::warn!("Failed to read the new theme: {}", e),
+        }
+    }
+}
+
+/// Calls `on_change` with the current theme, then again on every change, until it returns
+/// false. Subscribes through the daemon's socket if a daemon is running, and otherwise
+/// watches the theme file itself.
+pub fn subscribe(mut on_change: impl FnMut(Value) -> bool) -> Result<()> {
+    let Ok(mut stream) = UnixStream::connect(Config::socket_file()) else {
+        log::debug!("No daemon on {}; watching the theme file", Config::socket_file().display());
+        if on_change(to_value(ThemeState::load()?)?) {
+            watch_theme(|state| to_value(state).is_ok_and(&mut on_change));
+        }
+        return Ok(());
+    };
+    send(&mut stream, &json!({ "jsonrpc": "2.0", "id": 1, "method": "subscribe" }))?;
+    for line in BufReader::new(stream).lines() {
+        let mut message: Value = serde_json::from_str(&line?)?;
+        if let Some(error) = message.get("error") {
+            return Err(ChromashError::General(format!("Subscribing failed: {}", error["message"])));
+        }
+        // The response to `subscribe` carries the current theme, notifications the new one
+        let state = match message.get_mut("result") {
+            Some(result) => result.take(),
+            None => message["params"].take(),
+        };
+        if !on_change(state) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Sends one request to the running daemon and returns its result. `None` if no daemon is
+/// listening, or it left the request to this process because it needs confirming here.
+pub fn call(method: &str, params: Value) -> Result<Option<Value>> {
+    let Ok(mut stream) = UnixStream::connect(Config::socket_file()) else {
+        return Ok(None);
+    };
+    send(&mut stream, &json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }))?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let mut response: Value = serde_json::from_str(&line)
+        .map_err(|_| ChromashError::General("The daemon closed the connection without answering".into()))?;
+    match response.get("error") {
+        Some(error) if error["code"] == UNTRUSTED_PRESET => Ok(None),
+        Some(error) => Err(remote_error(error)),
+        None => Ok(Some(response["result"].take())),
+    }
+}
+
+/// The `ChromashError` the daemon answered with, so forwarded commands fail the same way
+/// as local ones.
+fn remote_error(error: &Value) -> ChromashError {
+    let message = error["message"].as_str().unwrap_or("The daemon failed").to_string();
+    let data = &error["data"];
+    let detail = data["detail"].as_str().map_or_else(|| message.clone(), str::to_string);
+    match data["code"].as_str() {
+        Some("io") => ChromashError::Io(std::io::Error::other(detail)),
+        Some("json") => ChromashError::Json(serde::de::Error::custom(detail)),
+        Some("config") => ChromashError::Toml(serde::de::Error::custom(detail)),
+        Some("process") => ChromashError::Process(detail),
+        Some("command") => match serde_json::from_value(data["failure"].clone()) {
+            Ok(failure) => ChromashError::Command(failure),
+            Err(_) => ChromashError::Process(detail),
+        },
+        Some("not_found") => ChromashError::NotFound(detail),
+        Some("locked") => ChromashError::Locked,
+        Some("busy") => ChromashError::Busy,
+        Some("usage") => ChromashError::Usage(detail),
+        _ => ChromashError::General(message),
+    }
+}
+
+fn send(stream: &mut UnixStream, message: &impl Serialize) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
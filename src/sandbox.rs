@@ -0,0 +1,86 @@
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{doctor, paths, Config, Result};
+
+/// Programs that change the desktop, restart services or reach the network. In a sandbox
+/// each is replaced by a stub that only records its command line.
+const STUBBED: &[&str] = &[
+    "hyprctl", "hyprpaper", "pkill", "swww", "swww-daemon", "swaybg", "feh", "swaymsg", "xrandr",
+    "swayidle", "systemctl", "mosquitto_pub", "mosquitto_sub", "hyprsunset", "gammastep",
+    "wallust", "curl", "osascript",
+];
+
+/// Whether `--sandbox` is in effect, here or in the chromash that started this one.
+pub fn active() -> bool {
+    env::var_os("CHROMASH_SANDBOX").is_some_and(|root| !root.is_empty())
+}
+
+/// Whether writing to `path` stays inside the sandbox; always true outside one.
+pub fn contains(path: &Path) -> bool {
+    match env::var_os("CHROMASH_SANDBOX").filter(|root| !root.is_empty()) {
+        Some(root) => path.starts_with(root) && !path.components().any(|c| c == Component::ParentDir),
+        None => true,
+    }
+}
+
+/// Moves everything chromash writes under `root`: its home (config, state, cache, trash and
+/// the hyprpaper config) and runtime directory become `root/home` and `root/run`, and
+/// recording stubs in `root/bin` go first on `PATH`. Wallpapers are still read from the
+/// real pictures directory. matugen only generates palettes, with `--dry-run`; chromash
+/// renders the templates itself, skipping any whose output lies outside `root`. Must run
+/// before anything reads the environment.
+pub fn enter(root: &Path) -> Result<()> {
+    let root = env::current_dir()?.join(root);
+    let (home, bin, run) = (root.join("home"), root.join("bin"), root.join("run"));
+    for dir in [&home, &bin, &run] {
+        fs::create_dir_all(dir)?;
+    }
+    let log = root.join("commands.log");
+    for name in STUBBED {
+        write_stub(&bin.join(name), &stub(name, &log, None))?;
+    }
+    let matugen = doctor::find_executable("matugen");
+    write_stub(&bin.join("matugen"), &stub("matugen", &log, matugen.as_deref()))?;
+
+    // Resolved before HOME moves, which would move `~/Pictures` along with it
+    let pictures = paths::var("XDG_PICTURES_DIR").map(PathBuf::from).unwrap_or_else(|| Config::home().join("Pictures"));
+    env::set_var("XDG_PICTURES_DIR", pictures);
+    env::set_var("HOME", &home);
+    env::set_var("XDG_RUNTIME_DIR", &run);
+    for name in ["XDG_CONFIG_HOME", "XDG_STATE_HOME", "XDG_CACHE_HOME", "XDG_DATA_HOME", "CHROMASH_CONFIG_DIR"] {
+        env::remove_var(name);
+    }
+    let path = env::var_os("PATH").unwrap_or_default();
+    env::set_var("PATH", env::join_paths(std::iter::once(bin).chain(env::split_paths(&path)))
+        .map_err(|e| crate::ChromashError::General(format!("Invalid sandbox directory: {}", e)))?);
+    env::set_var("CHROMASH_SANDBOX", &root);
+    log::info!("Sandboxed in {}; external commands are recorded in {}", root.display(), log.display());
+    Ok(())
+}
+
+/// A shell script appending `name` and its arguments to `log`, then handing off to `real`
+/// (with `--dry-run`) if given.
+fn stub(name: &str, log: &Path, real: Option<&Path>) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n\
+         # Recording stub written by chromash --sandbox\n\
+         {{ printf '%s' {}; for arg in \"$@\"; do printf ' %s' \"$arg\"; done; echo; }} >> {}\n",
+        shell_quote(name), shell_quote(&log.to_string_lossy()));
+    if let Some(real) = real {
+        script.push_str(&format!("exec {} \"$@\" --dry-run\n", shell_quote(&real.to_string_lossy())));
+    }
+    script
+}
+
+fn write_stub(path: &Path, script: &str) -> Result<()> {
+    fs::write(path, script)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::path::Path;
+use image::{imageops::FilterType, GenericImageView, ImageReader};
+
+use crate::{ChromashError, Result};
+
+/// Longest side screenshots are scaled down to. Nearest-neighbour scaling keeps flat
+/// regions flat instead of blending them into their borders.
+const SIZE: u32 = 256;
+/// Share of the image a color needs to be an accent rather than text anti-aliasing or an
+/// icon's speck of color.
+const MIN_ACCENT_SHARE: f64 = 0.002;
+/// Below this chroma (max - min channel) a color reads as grey.
+const MIN_ACCENT_CHROMA: u8 = 40;
+
+/// The colors a UI screenshot is built from.
+#[derive(Debug, Clone, Copy)]
+pub struct UiColors {
+    /// The background: the color covering the most flat area.
+    pub surface: [u8; 3],
+    /// The most saturated color covering a noticeable area.
+    pub accent: [u8; 3],
+}
+
+/// Pixels falling into one quantized color.
+#[derive(Default)]
+struct Bucket {
+    count: u32,
+    /// Pixels whose right and lower neighbours fall into the same bucket.
+    flat: u32,
+    sum: [u64; 3],
+}
+
+impl Bucket {
+    fn mean(&self) -> [u8; 3] {
+        let count = self.count.max(1) as u64;
+        self.sum.map(|channel| (channel / count) as u8)
+    }
+}
+
+fn chroma([r, g, b]: [u8; 3]) -> u8 {
+    r.max(g).max(b) - r.min(g).min(b)
+}
+
+fn quantize(pixel: [u8; 3]) -> [u8; 3] {
+    pixel.map(|channel| channel / 16)
+}
+
+/// Guesses the surface and accent of the UI in a screenshot: the largest flat region is
+/// taken as the surface, the most saturated color covering a noticeable area as the accent.
+pub fn analyze(path: &Path) -> Result<UiColors> {
+    let img = ImageReader::open(path)?.with_guessed_format()?.decode()
+        .map_err(|e| ChromashError::General(format!("Failed to decode: {}", e)))?;
+    let (width, height) = img.dimensions();
+    let img = if width > SIZE || height > SIZE { img.resize(SIZE, SIZE, FilterType::Nearest) } else { img };
+    let img = img.into_rgb8();
+
+    let mut buckets: HashMap<[u8; 3], Bucket> = HashMap::new();
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let key = quantize(pixel.0);
+        let same = |dx: u32, dy: u32| img.get_pixel_checked(x + dx, y + dy).is_some_and(|p| quantize(p.0) == key);
+        let bucket = buckets.entry(key).or_default();
+        bucket.count += 1;
+        if same(1, 0) && same(0, 1) {
+            bucket.flat += 1;
+        }
+        for (sum, channel) in bucket.sum.iter_mut().zip(pixel.0) {
+            *sum += channel as u64;
+        }
+    }
+
+    let (surface_key, surface) = buckets.iter()
+        .max_by_key(|(_, bucket)| bucket.flat)
+        .ok_or_else(|| ChromashError::General(format!("{} is empty", path.display())))?;
+    let total = img.pixels().len() as f64;
+    let accent = buckets.iter()
+        .filter(|(key, bucket)| *key != surface_key && bucket.count as f64 >= total * MIN_ACCENT_SHARE)
+        .map(|(_, bucket)| (bucket.mean(), bucket.count))
+        .filter(|(color, _)| chroma(*color) >= MIN_ACCENT_CHROMA)
+        // Saturation first, but a button's worth of color outweighs a slightly more vivid badge
+        .max_by(|(a, a_count), (b, b_count)| {
+            let score = |color: [u8; 3], count: u32| chroma(color) as f64 * (count as f64).ln();
+            score(*a, *a_count).total_cmp(&score(*b, *b_count))
+        })
+        .map(|(color, _)| color)
+        .ok_or_else(|| ChromashError::NotFound(format!(
+            "An accent color in {} (everything is grey; pick one with `chromash color`)", path.display())))?;
+    Ok(UiColors { surface: surface.mean(), accent })
+}
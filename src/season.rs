@@ -0,0 +1,35 @@
+use chrono::{Datelike, NaiveDate};
+
+use crate::config::SeasonConfig;
+use crate::{ChromashError, Result};
+
+/// Returns the first configured season whose date range contains `date`.
+pub fn active(seasons: &[SeasonConfig], date: NaiveDate) -> Result<Option<&SeasonConfig>> {
+    let today = date.month() * 100 + date.day();
+    for season in seasons {
+        let from = parse_month_day(&season.from)?;
+        let to = parse_month_day(&season.to)?;
+        let contains = if from <= to {
+            from <= today && today <= to
+        } else {
+            // Wraps around the new year, e.g. 12-20 to 01-05
+            today >= from || today <= to
+        };
+        if contains {
+            return Ok(Some(season));
+        }
+    }
+    Ok(None)
+}
+
+/// Parses `MM-DD` into a sortable `month * 100 + day` key.
+fn parse_month_day(s: &str) -> Result<u32> {
+    let invalid = || ChromashError::General(format!("Invalid season date '{}', expected MM-DD", s));
+    let (month, day) = s.split_once('-').ok_or_else(invalid)?;
+    let month: u32 = month.parse().map_err(|_| invalid())?;
+    let day: u32 = day.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err(invalid());
+    }
+    Ok(month * 100 + day)
+}
@@ -0,0 +1,100 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChromashError, ColorMode, Result, SchemeType};
+
+const PREFIX: &str = "chromash://";
+/// Format of the encoded theme; strings from a newer chromash are refused rather than
+/// half understood.
+pub const SHARE_VERSION: u32 = 1;
+
+/// Everything needed to recreate a theme on another machine, minus the wallpaper file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedTheme {
+    /// `SHARE_VERSION` of the chromash that made it; strings from before it had one are 1.
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub name: String,
+    /// Seed color as `#rrggbb`; wallpaper presets are shared via their extracted seed.
+    pub seed: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ColorMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<SchemeType>,
+    /// File name of the wallpaper a wallpaper preset was made from, without its directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wallpaper: Option<String>,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+impl SharedTheme {
+    pub fn encode(&self) -> Result<String> {
+        let json = serde_json::to_vec(self)?;
+        Ok(format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(json)))
+    }
+
+    pub fn decode(code: &str) -> Result<Self> {
+        let invalid = || ChromashError::General("Invalid share string".into());
+        let payload = code.trim().strip_prefix(PREFIX).unwrap_or(code.trim());
+        let json = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let shared: Self = serde_json::from_slice(&json).map_err(|_| invalid())?;
+        if shared.version > SHARE_VERSION {
+            return Err(ChromashError::General(format!(
+                "Share string is format {}, newer than this chromash understands ({}); update chromash", shared.version, SHARE_VERSION)));
+        }
+        Ok(shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forest() -> SharedTheme {
+        SharedTheme {
+            version: SHARE_VERSION,
+            name: "Forest walk".into(),
+            seed: "#2e6b3a".into(),
+            mode: Some(ColorMode::Dark),
+            scheme: Some(SchemeType::Expressive),
+            wallpaper: Some("forest.png".into()),
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let code = forest().encode().unwrap();
+        assert!(code.starts_with(PREFIX));
+        let decoded = SharedTheme::decode(&code).unwrap();
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(forest()).unwrap());
+        // Pasted without the prefix, or with surrounding whitespace
+        let bare = code.strip_prefix(PREFIX).unwrap();
+        assert_eq!(SharedTheme::decode(&format!(" {}\n", bare)).unwrap().wallpaper.as_deref(), Some("forest.png"));
+    }
+
+    #[test]
+    fn reads_strings_from_before_versions() {
+        let code = format!("{}{}", PREFIX, URL_SAFE_NO_PAD.encode(r##"{"name":"Old","seed":"#6750a4"}"##));
+        let decoded = SharedTheme::decode(&code).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert!(decoded.mode.is_none() && decoded.wallpaper.is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_strings() {
+        let code = forest().encode().unwrap();
+        for cut in [1, 5, code.len() / 2, code.len() - PREFIX.len()] {
+            assert!(SharedTheme::decode(&code[..code.len() - cut]).is_err(), "accepted {}", &code[..code.len() - cut]);
+        }
+        for code in ["", PREFIX, "chromash://!!!", "chromash://e30", "https://example.com"] {
+            assert!(SharedTheme::decode(code).is_err(), "accepted {}", code);
+        }
+        let newer = SharedTheme { version: SHARE_VERSION + 1, ..forest() }.encode().unwrap();
+        let error = SharedTheme::decode(&newer).unwrap_err().to_string();
+        assert!(error.contains("newer"), "{}", error);
+    }
+}
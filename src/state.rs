@@ -0,0 +1,96 @@
+use serde_json::{Map, Value};
+
+use crate::{ChromashError, CurrentTheme, PresetMetadata, Result};
+
+/// Schema version written to `metadata.json` of presets.
+pub const PRESET_VERSION: u32 = 1;
+/// Schema version written to `current_theme.json` and the history.
+pub const THEME_VERSION: u32 = 1;
+
+/// A migration upgrades a file from the version at its index to the next one.
+type Migration = fn(&mut Map<String, Value>);
+
+const PRESET_MIGRATIONS: [Migration; PRESET_VERSION as usize] = [preset_v0_to_v1];
+const THEME_MIGRATIONS: [Migration; THEME_VERSION as usize] = [theme_v0_to_v1];
+
+/// Parses preset metadata of any known version, upgrading it to the current one.
+pub fn parse_preset(content: &str) -> Result<PresetMetadata> {
+    let value = migrate(serde_json::from_str(content)?, &PRESET_MIGRATIONS, "Preset metadata")?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Parses a recorded theme of any known version, upgrading it to the current one.
+pub fn parse_theme(content: &str) -> Result<CurrentTheme> {
+    theme_from_value(serde_json::from_str(content)?)
+}
+
+pub fn theme_from_value(value: Value) -> Result<CurrentTheme> {
+    Ok(serde_json::from_value(migrate(value, &THEME_MIGRATIONS, "Theme state")?)?)
+}
+
+fn migrate(value: Value, migrations: &[Migration], what: &str) -> Result<Value> {
+    let Value::Object(mut fields) = value else {
+        return Err(ChromashError::General(format!("{} is not a JSON object", what)));
+    };
+    // Files written before versioning have no version field
+    let version = fields.get("version").and_then(Value::as_u64).unwrap_or(0) as usize;
+    if version > migrations.len() {
+        return Err(ChromashError::General(format!(
+            "{} has version {}, but this chromash only supports up to {}", what, version, migrations.len())));
+    }
+    for migration in &migrations[version..] {
+        migration(&mut fields);
+    }
+    fields.insert("version".into(), migrations.len().into());
+    Ok(Value::Object(fields))
+}
+
+/// Unversioned metadata may lack timestamps; treat them as created at the epoch.
+fn preset_v0_to_v1(fields: &mut Map<String, Value>) {
+    let created = fields.get("created").cloned().unwrap_or_else(|| 0.into());
+    fields.entry("modified").or_insert_with(|| created.clone());
+    fields.entry("created").or_insert(created);
+}
+
+fn theme_v0_to_v1(fields: &mut Map<String, Value>) {
+    fields.entry("timestamp").or_insert_with(|| 0.into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_v0_gets_timestamps() {
+        let preset = parse_preset(r##"{"name": "cozy", "source": "color_6750a4"}"##).unwrap();
+        assert_eq!(preset.version, PRESET_VERSION);
+        assert_eq!((preset.created, preset.modified), (0, 0));
+
+        let preset = parse_preset(r##"{"name": "cozy", "created": 1700000000, "source": null, "wallpaper": "/w/a.png"}"##).unwrap();
+        assert_eq!((preset.created, preset.modified), (1700000000, 1700000000));
+        assert_eq!(preset.wallpaper.as_deref(), Some(std::path::Path::new("/w/a.png")));
+    }
+
+    #[test]
+    fn preset_v1_is_left_alone() {
+        let preset = parse_preset(r##"{"version": 1, "name": "cozy", "created": 5, "modified": 9, "source": null, "pinned": true}"##).unwrap();
+        assert_eq!((preset.created, preset.modified), (5, 9));
+        assert!(preset.pinned);
+    }
+
+    #[test]
+    fn theme_v0_gets_a_timestamp() {
+        let theme = parse_theme(r##"{"source": "color_6750a4", "preset_name": null}"##).unwrap();
+        assert_eq!(theme.version, THEME_VERSION);
+        assert_eq!(theme.timestamp, 0);
+        assert_eq!(theme.source, "color_6750a4");
+    }
+
+    #[test]
+    fn refuses_newer_and_malformed_files() {
+        let error = parse_theme(r##"{"version": 2, "source": "color_6750a4", "timestamp": 1}"##).unwrap_err();
+        assert!(error.to_string().contains("only supports up to 1"), "{}", error);
+        assert!(parse_preset(r##"["cozy"]"##).is_err());
+        assert!(parse_preset(r##"{"version": 1, "created": 5, "modified": 9}"##).is_err());
+    }
+}
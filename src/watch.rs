@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::{paths, ChromashApi, ChromashError, Config, Result};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modification times of every watched file, used to detect changes between polls.
+type Snapshot = Vec<(PathBuf, Option<SystemTime>)>;
+
+/// Re-applies the current theme whenever its wallpaper or the matugen templates change.
+pub fn run(api: &mut ChromashApi) -> Result<()> {
+    let mut source = current_source(api)?;
+    let template_dir = Config::template_dir();
+    println!("Watching {} and {}", source, template_dir.display());
+
+    let mut snapshot = take_snapshot(&source, &template_dir);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        // Another chromash invocation applied a new theme: follow it without re-applying
+        let latest = current_source(api)?;
+        if latest != source {
+            source = latest;
+            snapshot = take_snapshot(&source, &template_dir);
+            continue;
+        }
+
+        let next = take_snapshot(&source, &template_dir);
+        if next == snapshot {
+            continue;
+        }
+        // Give editors and copy operations a moment to finish writing
+        thread::sleep(POLL_INTERVAL);
+        println!("Change detected, re-applying {}", source);
+        let options = api.load_current_theme()?.map(|t| t.options()).unwrap_or_default();
+        if let Err(e) = api.apply_source(&source, options) {
+            log::error!("Failed to re-apply theme: {}", e);
+        }
+        snapshot = take_snapshot(&source, &template_dir);
+    }
+}
+
+fn current_source(api: &ChromashApi) -> Result<String> {
+    api.load_current_theme()?
+        .map(|theme| theme.source)
+        .ok_or_else(|| ChromashError::NotFound("No theme applied yet".into()))
+}
+
+fn take_snapshot(source: &str, template_dir: &Path) -> Snapshot {
+    let mut files = Vec::new();
+    if let Some(wallpaper) = source.strip_prefix("wallpaper_") {
+        files.push(paths::decode(wallpaper));
+    }
+    collect_files(template_dir, &mut files);
+    files.sort();
+    files.into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::rpc::{self, ThemeState};
+use crate::{ChromashApi, ChromashError, ColorMode, Result};
+
+/// Roles listed in the tooltip, when the palette has them.
+const TOOLTIP_ROLES: &[&str] = &["primary", "secondary", "tertiary", "error", "surface", "on_surface", "outline"];
+
+/// One update of a waybar custom module with `"return-type": "json"`.
+#[derive(Debug, Serialize)]
+struct Output {
+    text: String,
+    /// The mode, for `format-icons`.
+    alt: String,
+    tooltip: String,
+    class: Vec<String>,
+}
+
+impl Output {
+    fn new(state: &ThemeState) -> Self {
+        let Some(theme) = &state.theme else {
+            return Self { text: "-".into(), alt: String::new(), tooltip: "No theme applied yet".into(), class: vec!["none".into()] };
+        };
+        let mode = theme.mode.or_else(|| state.palette.as_ref().map(|p| p.mode())).unwrap_or(ColorMode::Dark);
+        let text = theme.preset_name.clone()
+            .or_else(|| theme.accent.clone())
+            .unwrap_or_else(|| "-".into());
+
+        let mut tooltip = match &theme.preset_name {
+            Some(name) => format!("Preset: {}\n", escape(name)),
+            None => String::new(),
+        };
+        match &state.wallpaper {
+            Some(wallpaper) => tooltip.push_str(&format!("Wallpaper: {}\n", escape(&wallpaper.display().to_string()))),
+            None => tooltip.push_str(&format!("Color: {}\n", theme.accent.as_deref().unwrap_or("-"))),
+        }
+        tooltip.push_str(&format!("Mode: {}, scheme: {}",
+            mode.as_str(), theme.scheme.map_or("-", |s| s.as_str().trim_start_matches("scheme-"))));
+        if let Some(palette) = &state.palette {
+            let roles = palette.roles(mode);
+            tooltip.push('\n');
+            for role in TOOLTIP_ROLES {
+                if let Some(hex) = roles.get(*role) {
+                    tooltip.push_str(&format!("\n<span color='{}'>███</span> {} {}", hex, role, hex));
+                }
+            }
+        }
+
+        let kind = if state.wallpaper.is_some() { "wallpaper" } else { "color" };
+        Self { text, alt: mode.as_str().into(), tooltip, class: vec![mode.as_str().into(), kind.into()] }
+    }
+}
+
+/// Waybar renders tooltips as Pango markup.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Prints the module's JSON now and, unless `once`, on every theme change.
+pub fn run(once: bool) -> Result<()> {
+    if once {
+        return print(&Output::new(&ThemeState::load()?)).map_err(ChromashError::from);
+    }
+    rpc::subscribe(|state: Value| {
+        let Ok(state) = serde_json::from_value::<ThemeState>(state) else { return true };
+        // A closed pipe means waybar has gone; stop quietly
+        print(&Output::new(&state)).is_ok()
+    })
+}
+
+fn print(output: &Output) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{}", serde_json::to_string(output)?)?;
+    stdout.flush()
+}
+
+/// Click actions: `next` and `prev` step through the presets, `toggle-mode` switches the
+/// current theme to the other mode. Returns what was applied.
+pub fn action(api: &mut ChromashApi, action: &str) -> Result<String> {
+    match action {
+        "next" | "prev" => {
+            let mut presets: Vec<String> = api.list_presets()?.into_iter()
+                .filter(|p| !p.snapshot)
+                .map(|p| p.name)
+                .collect();
+            if presets.is_empty() {
+                return Err(ChromashError::NotFound("No saved presets".into()));
+            }
+            presets.sort_by_key(|name| name.to_lowercase());
+            let current = api.load_current_theme()?
+                .and_then(|theme| theme.preset_name)
+                .and_then(|name| presets.iter().position(|p| *p == name));
+            let index = match (current, action) {
+                (Some(i), "next") => (i + 1) % presets.len(),
+                (Some(i), _) => (i + presets.len() - 1) % presets.len(),
+                (None, "next") => 0,
+                (None, _) => presets.len() - 1,
+            };
+            api.apply_preset(&presets[index], Default::default())?;
+            Ok(format!("preset {}", presets[index]))
+        }
+        "toggle-mode" => Ok(format!("{} mode", api.switch_mode(None)?.as_str())),
+        action => Err(crate::unknown_value("waybar-module action", action, &["next", "prev", "toggle-mode"])),
+    }
+}